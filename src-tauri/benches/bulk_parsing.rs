@@ -0,0 +1,35 @@
+// Compares the packrat parser used for bulk parsing with the Earley parser on a large database. The database is given
+// as the first argument, or as MMT1_BENCH_DATABASE, and defaults to set.mm in the working directory:
+// cargo bench --features bench --bench bulk_parsing -- path/to/set.mm
+fn main() {
+    let file_path = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .or_else(|| std::env::var("MMT1_BENCH_DATABASE").ok())
+        .unwrap_or_else(|| "set.mm".to_string());
+
+    let benchmark = match mmt1_lib::benchmark_bulk_parsing(&file_path) {
+        Ok(benchmark) => benchmark,
+        Err(error) => {
+            eprintln!("Could not benchmark {}: {}", file_path, error);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Database:     {}", file_path);
+    println!("Expressions:  {}", benchmark.expressions);
+    println!("Fallbacks:    {}", benchmark.fallbacks);
+    println!("Mismatches:   {}", benchmark.mismatches);
+    println!(
+        "Bulk parsing: {:.3}s",
+        benchmark.bulk_duration.as_secs_f64()
+    );
+    println!(
+        "Earley only:  {:.3}s",
+        benchmark.earley_duration.as_secs_f64()
+    );
+    println!(
+        "Speedup:      {:.2}x",
+        benchmark.earley_duration.as_secs_f64() / benchmark.bulk_duration.as_secs_f64()
+    );
+}
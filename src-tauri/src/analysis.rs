@@ -0,0 +1,7 @@
+pub mod database_diff;
+pub mod dependency_graph;
+pub mod duplicates;
+pub mod proof_diff;
+pub mod proof_lint;
+pub mod style_lint;
+pub mod usage;
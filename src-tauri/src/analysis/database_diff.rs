@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    metamath::mm_parser,
+    model::{Header, HeaderPath, MetamathData, Statement, Theorem},
+    AppState, Error,
+};
+
+// Semantic differences between the open database (old) and another version of it (new), keyed by label
+#[derive(Serialize)]
+pub struct DatabaseDiff {
+    #[serde(rename = "addedStatements")]
+    pub added_statements: Vec<DatabaseDiffStatement>,
+    #[serde(rename = "removedStatements")]
+    pub removed_statements: Vec<DatabaseDiffStatement>,
+    #[serde(rename = "movedStatements")]
+    pub moved_statements: Vec<MovedDatabaseDiffStatement>,
+    #[serde(rename = "changedAssertions")]
+    pub changed_assertions: Vec<ChangedDatabaseDiffStatement>,
+    #[serde(rename = "changedProofs")]
+    pub changed_proofs: Vec<ChangedDatabaseDiffStatement>,
+    #[serde(rename = "changedDescriptions")]
+    pub changed_descriptions: Vec<ChangedDatabaseDiffStatement>,
+}
+
+#[derive(Serialize)]
+pub struct DatabaseDiffStatement {
+    pub label: String,
+    #[serde(rename = "headerPath")]
+    pub header_path: String,
+    #[serde(rename = "headerTitles")]
+    pub header_titles: Vec<String>,
+}
+
+// A statement that is located under headers with different titles in the new database, or whose order relative to
+// the other statements changed
+#[derive(Serialize)]
+pub struct MovedDatabaseDiffStatement {
+    pub label: String,
+    #[serde(rename = "oldHeaderPath")]
+    pub old_header_path: String,
+    #[serde(rename = "newHeaderPath")]
+    pub new_header_path: String,
+    #[serde(rename = "oldHeaderTitles")]
+    pub old_header_titles: Vec<String>,
+    #[serde(rename = "newHeaderTitles")]
+    pub new_header_titles: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ChangedDatabaseDiffStatement {
+    pub label: String,
+    #[serde(rename = "headerPath")]
+    pub header_path: String,
+    #[serde(rename = "oldValue")]
+    pub old_value: String,
+    #[serde(rename = "newValue")]
+    pub new_value: String,
+}
+
+// A labeled statement of a database together with its location
+pub struct LocatedStatement<'a> {
+    pub statement: &'a Statement,
+    pub header_path: HeaderPath,
+    pub header_titles: Vec<&'a str>,
+}
+
+#[tauri::command]
+pub async fn diff_database(
+    state: tauri::State<'_, Mutex<AppState>>,
+    mm_file_path: &str,
+) -> Result<DatabaseDiff, Error> {
+    let mut app_state = state.lock().await;
+    let database_id = app_state.id_manager.get_next_id();
+    let settings = app_state.settings.clone();
+    drop(app_state);
+
+    let new_metamath_data = mm_parser::load_metamath_data(mm_file_path, database_id, &settings)?;
+
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    Ok(calc_database_diff(metamath_data, &new_metamath_data))
+}
+
+pub fn calc_database_diff(
+    old_metamath_data: &MetamathData,
+    new_metamath_data: &MetamathData,
+) -> DatabaseDiff {
+    let old_statements = calc_located_statements(&old_metamath_data.database_header);
+    let new_statements = calc_located_statements(&new_metamath_data.database_header);
+
+    let old_statements_by_label: HashMap<&str, &LocatedStatement> = old_statements
+        .iter()
+        .filter_map(|ls| Some((statement_label(ls.statement)?, ls)))
+        .collect();
+    let new_statements_by_label: HashMap<&str, &LocatedStatement> = new_statements
+        .iter()
+        .filter_map(|ls| Some((statement_label(ls.statement)?, ls)))
+        .collect();
+
+    let reordered_labels = calc_reordered_labels(&old_statements, &new_statements);
+
+    let mut database_diff = DatabaseDiff {
+        added_statements: Vec::new(),
+        removed_statements: Vec::new(),
+        moved_statements: Vec::new(),
+        changed_assertions: Vec::new(),
+        changed_proofs: Vec::new(),
+        changed_descriptions: Vec::new(),
+    };
+
+    for old_statement in &old_statements {
+        let Some(label) = statement_label(old_statement.statement) else {
+            continue;
+        };
+
+        if !new_statements_by_label.contains_key(label) {
+            database_diff
+                .removed_statements
+                .push(to_database_diff_statement(label, old_statement));
+        }
+    }
+
+    for new_statement in &new_statements {
+        let Some(label) = statement_label(new_statement.statement) else {
+            continue;
+        };
+
+        let Some(old_statement) = old_statements_by_label.get(label) else {
+            database_diff
+                .added_statements
+                .push(to_database_diff_statement(label, new_statement));
+            continue;
+        };
+
+        if old_statement.header_titles != new_statement.header_titles
+            || reordered_labels.contains(label)
+        {
+            database_diff
+                .moved_statements
+                .push(MovedDatabaseDiffStatement {
+                    label: label.to_string(),
+                    old_header_path: old_statement.header_path.to_string(),
+                    new_header_path: new_statement.header_path.to_string(),
+                    old_header_titles: to_string_vec(&old_statement.header_titles),
+                    new_header_titles: to_string_vec(&new_statement.header_titles),
+                });
+        }
+
+        let changed = |old_value: String, new_value: String| ChangedDatabaseDiffStatement {
+            label: label.to_string(),
+            header_path: new_statement.header_path.to_string(),
+            old_value,
+            new_value,
+        };
+
+        match (old_statement.statement, new_statement.statement) {
+            (
+                Statement::TheoremStatement(old_theorem),
+                Statement::TheoremStatement(new_theorem),
+            ) => {
+                let old_assertion = theorem_assertion_string(old_theorem);
+                let new_assertion = theorem_assertion_string(new_theorem);
+                if old_assertion != new_assertion {
+                    database_diff
+                        .changed_assertions
+                        .push(changed(old_assertion, new_assertion));
+                }
+
+                if old_theorem.proof != new_theorem.proof {
+                    database_diff.changed_proofs.push(changed(
+                        old_theorem.proof.clone().unwrap_or_default(),
+                        new_theorem.proof.clone().unwrap_or_default(),
+                    ));
+                }
+
+                if old_theorem.description != new_theorem.description {
+                    database_diff.changed_descriptions.push(changed(
+                        old_theorem.description.clone(),
+                        new_theorem.description.clone(),
+                    ));
+                }
+            }
+            // Floating hypotheses, or a label that switched between a floating hypothesis and a theorem
+            _ => {
+                let old_assertion = statement_assertion_string(old_statement.statement);
+                let new_assertion = statement_assertion_string(new_statement.statement);
+                if old_assertion != new_assertion {
+                    database_diff
+                        .changed_assertions
+                        .push(changed(old_assertion, new_assertion));
+                }
+            }
+        }
+    }
+
+    database_diff
+}
+
+// Returns the labels of the statements that exist in both databases and are not part of the longest sequence of such
+// statements that keeps its order, which is the smallest set of statements that has to be moved to get the new order
+fn calc_reordered_labels<'a>(
+    old_statements: &[LocatedStatement<'a>],
+    new_statements: &[LocatedStatement<'a>],
+) -> HashSet<&'a str> {
+    let new_indices: HashMap<&str, usize> = new_statements
+        .iter()
+        .filter_map(|ls| statement_label(ls.statement))
+        .enumerate()
+        .map(|(i, label)| (label, i))
+        .collect();
+
+    let common_statements: Vec<(&str, usize)> = old_statements
+        .iter()
+        .filter_map(|ls| statement_label(ls.statement))
+        .filter_map(|label| Some((label, *new_indices.get(label)?)))
+        .collect();
+
+    // Longest increasing subsequence of the new indices. tails[l] is the position in common_statements of the smallest
+    // possible last element of a subsequence of length l + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut predecessors: Vec<Option<usize>> = Vec::with_capacity(common_statements.len());
+
+    for (i, &(_, new_i)) in common_statements.iter().enumerate() {
+        let length = tails.partition_point(|&tail| common_statements[tail].1 < new_i);
+
+        predecessors.push(length.checked_sub(1).map(|l| tails[l]));
+
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+
+    let mut in_order = vec![false; common_statements.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        in_order[i] = true;
+        next = predecessors[i];
+    }
+
+    common_statements
+        .into_iter()
+        .zip(in_order)
+        .filter(|(_, in_order)| !in_order)
+        .map(|((label, _), _)| label)
+        .collect()
+}
+
+// Returns all statements of the database in order, with the path and titles of the header they are located in
+pub fn calc_located_statements(top_header: &Header) -> Vec<LocatedStatement<'_>> {
+    let mut located_statements = Vec::new();
+    add_located_statements(
+        top_header,
+        &mut HeaderPath::new(),
+        &mut Vec::new(),
+        &mut located_statements,
+    );
+    located_statements
+}
+
+fn add_located_statements<'a>(
+    header: &'a Header,
+    header_path: &mut HeaderPath,
+    header_titles: &mut Vec<&'a str>,
+    located_statements: &mut Vec<LocatedStatement<'a>>,
+) {
+    for statement in &header.content {
+        located_statements.push(LocatedStatement {
+            statement,
+            header_path: header_path.clone(),
+            header_titles: header_titles.clone(),
+        });
+    }
+
+    for (i, subheader) in header.subheaders.iter().enumerate() {
+        header_path.path.push(i);
+        header_titles.push(&subheader.title);
+        add_located_statements(subheader, header_path, header_titles, located_statements);
+        header_titles.pop();
+        header_path.path.pop();
+    }
+}
+
+pub fn statement_label(statement: &Statement) -> Option<&str> {
+    match statement {
+        Statement::TheoremStatement(theorem) => Some(&theorem.label),
+        Statement::FloatingHypohesisStatement(floating_hypothesis) => {
+            Some(&floating_hypothesis.label)
+        }
+        _ => None,
+    }
+}
+
+fn statement_assertion_string(statement: &Statement) -> String {
+    match statement {
+        Statement::TheoremStatement(theorem) => theorem_assertion_string(theorem),
+        Statement::FloatingHypohesisStatement(floating_hypothesis) => format!(
+            "{} {}",
+            floating_hypothesis.typecode, floating_hypothesis.variable
+        ),
+        _ => String::new(),
+    }
+}
+
+// Writes the hypotheses and the assertion of a theorem into a single string, so that both are compared
+fn theorem_assertion_string(theorem: &Theorem) -> String {
+    let mut assertion_string = String::new();
+
+    for hypothesis in &theorem.hypotheses {
+        assertion_string.push_str(&hypothesis.expression);
+        assertion_string.push_str(" & ");
+    }
+
+    if !theorem.hypotheses.is_empty() {
+        assertion_string.push_str("=> ");
+    }
+
+    assertion_string.push_str(&theorem.assertion);
+    assertion_string
+}
+
+fn to_database_diff_statement(
+    label: &str,
+    located_statement: &LocatedStatement,
+) -> DatabaseDiffStatement {
+    DatabaseDiffStatement {
+        label: label.to_string(),
+        header_path: located_statement.header_path.to_string(),
+        header_titles: to_string_vec(&located_statement.header_titles),
+    }
+}
+
+fn to_string_vec(strs: &[&str]) -> Vec<String> {
+    strs.iter().map(|s| s.to_string()).collect()
+}
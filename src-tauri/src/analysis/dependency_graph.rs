@@ -0,0 +1,393 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    fmt::Write,
+    fs,
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    model::{MetamathData, Theorem, TheoremType},
+    AppState, Error,
+};
+
+// Direct-use graph between theorems, indexed by theorem number - 1
+// Syntax axioms are nodes without any edges, since every proof uses them
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    pub labels: Vec<String>,
+    pub label_indexes: HashMap<String, usize>,
+    pub theorem_types: Vec<Option<TheoremType>>,
+    pub uses: Vec<Vec<usize>>,
+    pub used_by: Vec<Vec<usize>>,
+    pub depths: Vec<u32>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum DependencyDirection {
+    #[serde(rename = "uses")]
+    Uses,
+    #[serde(rename = "usedBy")]
+    UsedBy,
+    #[serde(rename = "both")]
+    Both,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum GraphExportFormat {
+    #[serde(rename = "dot")]
+    Dot,
+    #[serde(rename = "json")]
+    Json,
+}
+
+#[derive(Serialize)]
+pub struct DirectDependencies {
+    pub uses: Vec<(String, u32)>,
+    #[serde(rename = "usedBy")]
+    pub used_by: Vec<(String, u32)>,
+}
+
+#[derive(Serialize)]
+pub struct DependencySubgraph {
+    pub nodes: Vec<DependencySubgraphNode>,
+    pub edges: Vec<DependencySubgraphEdge>,
+}
+
+#[derive(Serialize)]
+pub struct DependencySubgraphNode {
+    pub label: String,
+    #[serde(rename = "theoremNumber")]
+    pub theorem_number: u32,
+    #[serde(rename = "theoremType")]
+    pub theorem_type: Option<TheoremType>,
+    pub depth: u32,
+}
+
+// "from" uses "to" in its proof
+#[derive(Serialize)]
+pub struct DependencySubgraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+#[tauri::command]
+pub async fn get_direct_dependencies(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+) -> Result<DirectDependencies, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let graph = DependencyGraph::calc(metamath_data);
+    let i = graph.index_of(label)?;
+
+    Ok(DirectDependencies {
+        uses: graph.to_label_vec(&graph.uses[i]),
+        used_by: graph.to_label_vec(&graph.used_by[i]),
+    })
+}
+
+#[tauri::command]
+pub async fn get_transitive_dependencies(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+    direction: DependencyDirection,
+) -> Result<Vec<(String, u32)>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let graph = DependencyGraph::calc(metamath_data);
+    let i = graph.index_of(label)?;
+
+    let mut reachable: Vec<usize> = graph
+        .calc_reachable(i, direction, None)
+        .into_keys()
+        .filter(|&j| j != i)
+        .collect();
+    reachable.sort_unstable();
+
+    Ok(graph.to_label_vec(&reachable))
+}
+
+#[tauri::command]
+pub async fn get_shortest_dependency_path(
+    state: tauri::State<'_, Mutex<AppState>>,
+    from_label: &str,
+    to_label: &str,
+) -> Result<Option<Vec<(String, u32)>>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let graph = DependencyGraph::calc(metamath_data);
+    let from_i = graph.index_of(from_label)?;
+    let to_i = graph.index_of(to_label)?;
+
+    Ok(graph
+        .calc_shortest_path(from_i, to_i)
+        .map(|path| graph.to_label_vec(&path)))
+}
+
+#[tauri::command]
+pub async fn get_dependency_depth(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+) -> Result<u32, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let graph = DependencyGraph::calc(metamath_data);
+    let i = graph.index_of(label)?;
+
+    Ok(graph.depths[i])
+}
+
+#[tauri::command]
+pub async fn get_dependency_subgraph_export(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+    direction: DependencyDirection,
+    max_distance: Option<u32>,
+    format: GraphExportFormat,
+) -> Result<String, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    calc_dependency_subgraph_export(metamath_data, label, direction, max_distance, format)
+}
+
+#[tauri::command]
+pub async fn write_dependency_subgraph_export_to_file(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+    direction: DependencyDirection,
+    max_distance: Option<u32>,
+    format: GraphExportFormat,
+    file_path: &str,
+) -> Result<(), Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let export =
+        calc_dependency_subgraph_export(metamath_data, label, direction, max_distance, format)?;
+
+    fs::write(file_path, export).map_err(|_| Error::FileWriteError)?;
+
+    Ok(())
+}
+
+fn calc_dependency_subgraph_export(
+    metamath_data: &MetamathData,
+    label: &str,
+    direction: DependencyDirection,
+    max_distance: Option<u32>,
+    format: GraphExportFormat,
+) -> Result<String, Error> {
+    let graph = DependencyGraph::calc(metamath_data);
+    let i = graph.index_of(label)?;
+
+    let subgraph = graph.calc_subgraph(i, direction, max_distance);
+
+    match format {
+        GraphExportFormat::Dot => Ok(subgraph.to_dot_string()),
+        GraphExportFormat::Json => {
+            serde_json::to_string_pretty(&subgraph).map_err(|_| Error::InternalLogicError)
+        }
+    }
+}
+
+impl DependencyGraph {
+    pub fn calc(metamath_data: &MetamathData) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+
+        for (i, theorem) in metamath_data.database_header.theorem_iter().enumerate() {
+            let theorem_type = metamath_data
+                .optimized_data
+                .theorem_data
+                .get(&theorem.label)
+                .map(|theorem_data| theorem_data.theorem_type);
+
+            let mut uses: Vec<usize> = match theorem.proof.as_ref() {
+                Some(proof) => Theorem::calc_proof_labels(proof)
+                    .into_iter()
+                    .filter_map(|label| graph.label_indexes.get(label).copied())
+                    .filter(|&j| !graph.theorem_types[j].is_some_and(|tt| tt.is_syntax_axiom()))
+                    .collect(),
+                None => Vec::new(),
+            };
+            uses.sort_unstable();
+            uses.dedup();
+
+            // Proofs can only use prior theorems, so all depths of used theorems are already known
+            let depth = uses.iter().map(|&j| graph.depths[j] + 1).max().unwrap_or(0);
+
+            for &j in &uses {
+                graph.used_by[j].push(i);
+            }
+
+            graph.labels.push(theorem.label.clone());
+            graph.label_indexes.insert(theorem.label.clone(), i);
+            graph.theorem_types.push(theorem_type);
+            graph.uses.push(uses);
+            graph.used_by.push(Vec::new());
+            graph.depths.push(depth);
+        }
+
+        graph
+    }
+
+    pub fn index_of(&self, label: &str) -> Result<usize, Error> {
+        self.label_indexes
+            .get(label)
+            .copied()
+            .ok_or(Error::NotFoundError)
+    }
+
+    pub fn to_label_vec(&self, indexes: &[usize]) -> Vec<(String, u32)> {
+        indexes
+            .iter()
+            .map(|&i| (self.labels[i].clone(), (i + 1) as u32))
+            .collect()
+    }
+
+    // Returns every reachable theorem with its distance from start
+    pub fn calc_reachable(
+        &self,
+        start: usize,
+        direction: DependencyDirection,
+        max_distance: Option<u32>,
+    ) -> HashMap<usize, u32> {
+        let mut distances: HashMap<usize, u32> = HashMap::new();
+        let mut queue: VecDeque<usize> = VecDeque::new();
+
+        distances.insert(start, 0);
+        queue.push_back(start);
+
+        while let Some(i) = queue.pop_front() {
+            let distance = distances[&i];
+
+            if max_distance.is_some_and(|max| distance >= max) {
+                continue;
+            }
+
+            let (uses, used_by): (&[usize], &[usize]) = match direction {
+                DependencyDirection::Uses => (&self.uses[i], &[]),
+                DependencyDirection::UsedBy => (&[], &self.used_by[i]),
+                DependencyDirection::Both => (&self.uses[i], &self.used_by[i]),
+            };
+
+            for &j in uses.iter().chain(used_by) {
+                if let Entry::Vacant(entry) = distances.entry(j) {
+                    entry.insert(distance + 1);
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        distances
+    }
+
+    // Breadth first search from to_i backwards through the used theorems,
+    // so that the returned path starts at from_i (usually an axiom) and ends at to_i
+    pub fn calc_shortest_path(&self, from_i: usize, to_i: usize) -> Option<Vec<usize>> {
+        let mut next_on_path: HashMap<usize, usize> = HashMap::new();
+        let mut queue: VecDeque<usize> = VecDeque::new();
+
+        queue.push_back(to_i);
+
+        while let Some(i) = queue.pop_front() {
+            if i == from_i {
+                let mut path = vec![from_i];
+                let mut current = from_i;
+                while let Some(&next) = next_on_path.get(&current) {
+                    path.push(next);
+                    current = next;
+                }
+                return Some(path);
+            }
+
+            for &j in &self.uses[i] {
+                if j != to_i {
+                    if let Entry::Vacant(entry) = next_on_path.entry(j) {
+                        entry.insert(i);
+                        queue.push_back(j);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn calc_subgraph(
+        &self,
+        start: usize,
+        direction: DependencyDirection,
+        max_distance: Option<u32>,
+    ) -> DependencySubgraph {
+        let mut node_indexes: Vec<usize> = self
+            .calc_reachable(start, direction, max_distance)
+            .into_keys()
+            .collect();
+        node_indexes.sort_unstable();
+
+        let node_set: HashSet<usize> = node_indexes.iter().copied().collect();
+
+        let nodes = node_indexes
+            .iter()
+            .map(|&i| DependencySubgraphNode {
+                label: self.labels[i].clone(),
+                theorem_number: (i + 1) as u32,
+                theorem_type: self.theorem_types[i],
+                depth: self.depths[i],
+            })
+            .collect();
+
+        let edges = node_indexes
+            .iter()
+            .flat_map(|&i| {
+                self.uses[i]
+                    .iter()
+                    .filter(|j| node_set.contains(j))
+                    .map(move |&j| DependencySubgraphEdge {
+                        from: self.labels[i].clone(),
+                        to: self.labels[j].clone(),
+                    })
+            })
+            .collect();
+
+        DependencySubgraph { nodes, edges }
+    }
+}
+
+impl DependencySubgraph {
+    pub fn to_dot_string(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n    rankdir=BT;\n");
+
+        for node in &self.nodes {
+            let (shape, color) = match node.theorem_type {
+                Some(TheoremType::Axiom) => ("ellipse", "lightblue"),
+                Some(TheoremType::Definition) => ("ellipse", "lightgreen"),
+                Some(TheoremType::SyntaxAxiom) => ("ellipse", "lightgrey"),
+                _ => ("box", "white"),
+            };
+
+            // Writing to a String can't fail
+            writeln!(
+                dot,
+                "    \"{}\" [shape={}, style=filled, fillcolor={}, tooltip=\"#{} depth {}\"];",
+                node.label, shape, color, node.theorem_number, node.depth
+            )
+            .ok();
+        }
+
+        for edge in &self.edges {
+            writeln!(dot, "    \"{}\" -> \"{}\";", edge.from, edge.to).ok();
+        }
+
+        dot.push_str("}\n");
+
+        dot
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    model::{MetamathData, ParseTree, ParseTreeNode},
+    util::earley_parser_optimized::Grammar,
+    AppState, Error,
+};
+
+#[derive(Serialize)]
+pub struct DuplicateReport {
+    pub duplicates: Vec<Vec<DuplicateTheoremInfo>>,
+    #[serde(rename = "weakerTheorems")]
+    pub weaker_theorems: Vec<WeakerTheoremEntry>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DuplicateTheoremInfo {
+    pub label: String,
+    #[serde(rename = "theoremNumber")]
+    pub theorem_number: u32,
+    #[serde(rename = "proofLength")]
+    pub proof_length: Option<u32>,
+    #[serde(rename = "isDiscouraged")]
+    pub is_discouraged: bool,
+}
+
+// "weaker" has the same assertion as "stronger", but needs more hypotheses
+#[derive(Serialize)]
+pub struct WeakerTheoremEntry {
+    pub weaker: DuplicateTheoremInfo,
+    pub stronger: DuplicateTheoremInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum CanonicalSymbol {
+    Typecode(u32),
+    Rule(u32),
+    // Typecode and number of first occurence of the variable
+    Variable(u32, u32),
+}
+
+struct CanonicalTheorem {
+    info: DuplicateTheoremInfo,
+    assertion: Vec<CanonicalSymbol>,
+    // Variables numbered below this appear in the assertion. All other variables can be renamed when comparing the
+    // hypotheses of two theorems
+    assertion_variable_count: u32,
+    // Sorted by their shape, so that the order of hypotheses does not matter
+    hypotheses: Vec<Vec<CanonicalSymbol>>,
+    // The hypotheses with the numbers of the variables that don't appear in the assertion left out
+    hypothesis_shapes: Vec<Vec<CanonicalSymbol>>,
+}
+
+impl CanonicalTheorem {
+    // The variables must be numbered in the order they first appear, starting with the assertion
+    fn new(
+        info: DuplicateTheoremInfo,
+        assertion: Vec<CanonicalSymbol>,
+        hypotheses: Vec<Vec<CanonicalSymbol>>,
+    ) -> CanonicalTheorem {
+        let assertion_variable_count = assertion
+            .iter()
+            .filter_map(|symbol| match symbol {
+                CanonicalSymbol::Variable(_, number) => Some(number + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let mut hypotheses: Vec<(Vec<CanonicalSymbol>, Vec<CanonicalSymbol>)> = hypotheses
+            .into_iter()
+            .map(|hypothesis| {
+                let shape = hypothesis
+                    .iter()
+                    .map(|symbol| match *symbol {
+                        CanonicalSymbol::Variable(typecode, number)
+                            if number >= assertion_variable_count =>
+                        {
+                            CanonicalSymbol::Variable(typecode, u32::MAX)
+                        }
+                        ref symbol => symbol.clone(),
+                    })
+                    .collect();
+                (shape, hypothesis)
+            })
+            .collect();
+        hypotheses.sort_unstable();
+
+        let (hypothesis_shapes, hypotheses) = hypotheses.into_iter().unzip();
+
+        CanonicalTheorem {
+            info,
+            assertion,
+            assertion_variable_count,
+            hypotheses,
+            hypothesis_shapes,
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_duplicate_report(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<DuplicateReport, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    calc_duplicate_report(metamath_data)
+}
+
+pub fn calc_duplicate_report(metamath_data: &MetamathData) -> Result<DuplicateReport, Error> {
+    let grammar = &metamath_data.optimized_data.grammar;
+
+    let mut canonical_theorems: Vec<CanonicalTheorem> = Vec::new();
+
+    for (i, theorem) in metamath_data.database_header.theorem_iter().enumerate() {
+        let Some(theorem_data) = metamath_data
+            .optimized_data
+            .theorem_data
+            .get(&theorem.label)
+        else {
+            continue;
+        };
+
+        if theorem_data.theorem_type.is_syntax_axiom() {
+            continue;
+        }
+
+        let Some(parse_trees) = theorem_data.parse_trees.as_ref() else {
+            continue;
+        };
+
+        // Variables are numbered in the order they first appear, starting with the assertion
+        let mut variables: HashMap<u32, u32> = HashMap::new();
+
+        let Some(assertion) =
+            calc_canonical_form(&parse_trees.assertion_parsed, grammar, &mut variables)?
+        else {
+            continue;
+        };
+
+        let Some(hypotheses) = parse_trees
+            .hypotheses_parsed
+            .iter()
+            .map(|hyp| calc_canonical_form(hyp, grammar, &mut variables))
+            .collect::<Result<Option<Vec<Vec<CanonicalSymbol>>>, Error>>()?
+        else {
+            continue;
+        };
+
+        canonical_theorems.push(CanonicalTheorem::new(
+            DuplicateTheoremInfo {
+                label: theorem.label.clone(),
+                theorem_number: (i + 1) as u32,
+                proof_length: theorem.proof.as_deref().map(calc_proof_length),
+                is_discouraged: theorem_data.is_discouraged,
+            },
+            assertion,
+            hypotheses,
+        ));
+    }
+
+    let (duplicates, weaker_theorems) = calc_duplicates_and_weaker_theorems(&canonical_theorems);
+
+    Ok(DuplicateReport {
+        duplicates,
+        weaker_theorems,
+    })
+}
+
+// Returns None if the parse tree contains work variables
+fn calc_canonical_form(
+    parse_tree: &ParseTree,
+    grammar: &Grammar,
+    variables: &mut HashMap<u32, u32>,
+) -> Result<Option<Vec<CanonicalSymbol>>, Error> {
+    let mut form = vec![CanonicalSymbol::Typecode(parse_tree.typecode)];
+
+    let mut nodes: Vec<&ParseTreeNode> = vec![&parse_tree.top_node];
+
+    while let Some(node) = nodes.pop() {
+        let ParseTreeNode::Node { rule_i, sub_nodes } = node else {
+            return Ok(None);
+        };
+
+        let rule = grammar
+            .rules
+            .get(*rule_i as usize)
+            .ok_or(Error::InternalLogicError)?;
+
+        if rule.is_floating_hypothesis {
+            let next_number = variables.len() as u32;
+            let number = *variables.entry(*rule_i).or_insert(next_number);
+            form.push(CanonicalSymbol::Variable(rule.left_side.symbol_i, number));
+        } else {
+            form.push(CanonicalSymbol::Rule(*rule_i));
+
+            // Visit sub nodes in the order of the syntax axiom's floating hypotheses
+            for &var_i in rule.var_order.iter().rev() {
+                nodes.push(
+                    sub_nodes
+                        .get(var_i as usize)
+                        .ok_or(Error::InternalLogicError)?,
+                );
+            }
+        }
+    }
+
+    Ok(Some(form))
+}
+
+// Theorems with the same assertion are duplicates if their hypotheses are the same up to order and renaming of the
+// variables that don't appear in the assertion
+fn calc_duplicates_and_weaker_theorems(
+    canonical_theorems: &[CanonicalTheorem],
+) -> (Vec<Vec<DuplicateTheoremInfo>>, Vec<WeakerTheoremEntry>) {
+    let mut same_assertion: HashMap<&Vec<CanonicalSymbol>, Vec<&CanonicalTheorem>> = HashMap::new();
+
+    for canonical_theorem in canonical_theorems {
+        same_assertion
+            .entry(&canonical_theorem.assertion)
+            .or_default()
+            .push(canonical_theorem);
+    }
+
+    let mut groups: Vec<Vec<&CanonicalTheorem>> = same_assertion
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    groups.sort_unstable_by_key(|group| group[0].info.theorem_number);
+
+    let mut duplicates: Vec<Vec<DuplicateTheoremInfo>> = Vec::new();
+    let mut weaker_theorems: Vec<WeakerTheoremEntry> = Vec::new();
+
+    for group in groups {
+        let mut same_hypothesis_shapes: HashMap<
+            &Vec<Vec<CanonicalSymbol>>,
+            Vec<&CanonicalTheorem>,
+        > = HashMap::new();
+
+        for &canonical_theorem in &group {
+            same_hypothesis_shapes
+                .entry(&canonical_theorem.hypothesis_shapes)
+                .or_default()
+                .push(canonical_theorem);
+        }
+
+        let mut duplicate_groups: Vec<Vec<DuplicateTheoremInfo>> = Vec::new();
+
+        for shape_group in same_hypothesis_shapes.into_values() {
+            let mut same_hypotheses: Vec<Vec<&CanonicalTheorem>> = Vec::new();
+
+            for canonical_theorem in shape_group {
+                match same_hypotheses.iter_mut().find(|duplicate_group| {
+                    hypotheses_contained_in_other_hypotheses(duplicate_group[0], canonical_theorem)
+                }) {
+                    Some(duplicate_group) => duplicate_group.push(canonical_theorem),
+                    None => same_hypotheses.push(vec![canonical_theorem]),
+                }
+            }
+
+            duplicate_groups.extend(
+                same_hypotheses
+                    .into_iter()
+                    .filter(|duplicate_group| duplicate_group.len() > 1)
+                    .map(|duplicate_group| {
+                        duplicate_group.iter().map(|ct| ct.info.clone()).collect()
+                    }),
+            );
+        }
+
+        duplicate_groups.sort_unstable_by_key(|duplicate_group| duplicate_group[0].theorem_number);
+        duplicates.append(&mut duplicate_groups);
+
+        for &weaker in &group {
+            for &stronger in &group {
+                if stronger.hypotheses.len() < weaker.hypotheses.len()
+                    && sorted_list_contained_in_other_sorted_list(
+                        &stronger.hypothesis_shapes,
+                        &weaker.hypothesis_shapes,
+                    )
+                    && hypotheses_contained_in_other_hypotheses(stronger, weaker)
+                {
+                    weaker_theorems.push(WeakerTheoremEntry {
+                        weaker: weaker.info.clone(),
+                        stronger: stronger.info.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    (duplicates, weaker_theorems)
+}
+
+// Returns whether every hypothesis of the theorem can be matched with a different hypothesis of the other theorem, with
+// the variables that don't appear in the assertion renamed one-to-one. Both theorems must have the same assertion
+fn hypotheses_contained_in_other_hypotheses(
+    theorem: &CanonicalTheorem,
+    other_theorem: &CanonicalTheorem,
+) -> bool {
+    let mut matched = vec![false; other_theorem.hypotheses.len()];
+
+    match_hypotheses(theorem, other_theorem, 0, &mut matched, &HashMap::new())
+}
+
+// Depth first search over the hypotheses of the other theorem that the hypothesis with index hyp_i can be matched with
+fn match_hypotheses(
+    theorem: &CanonicalTheorem,
+    other_theorem: &CanonicalTheorem,
+    hyp_i: usize,
+    matched: &mut Vec<bool>,
+    renaming: &HashMap<u32, u32>,
+) -> bool {
+    let Some(hypothesis) = theorem.hypotheses.get(hyp_i) else {
+        return true;
+    };
+
+    for (other_hyp_i, other_hypothesis) in other_theorem.hypotheses.iter().enumerate() {
+        if matched[other_hyp_i]
+            || other_theorem.hypothesis_shapes[other_hyp_i] != theorem.hypothesis_shapes[hyp_i]
+        {
+            continue;
+        }
+
+        let mut extended_renaming = renaming.clone();
+
+        if !extend_renaming(
+            hypothesis,
+            other_hypothesis,
+            theorem.assertion_variable_count,
+            &mut extended_renaming,
+        ) {
+            continue;
+        }
+
+        matched[other_hyp_i] = true;
+        if match_hypotheses(
+            theorem,
+            other_theorem,
+            hyp_i + 1,
+            matched,
+            &extended_renaming,
+        ) {
+            return true;
+        }
+        matched[other_hyp_i] = false;
+    }
+
+    false
+}
+
+// Extends the renaming of variables so that form becomes other_form. Variables that appear in the assertion keep
+// their number. Returns false if that is not possible
+fn extend_renaming(
+    form: &[CanonicalSymbol],
+    other_form: &[CanonicalSymbol],
+    assertion_variable_count: u32,
+    renaming: &mut HashMap<u32, u32>,
+) -> bool {
+    if form.len() != other_form.len() {
+        return false;
+    }
+
+    for (symbol, other_symbol) in form.iter().zip(other_form) {
+        match (symbol, other_symbol) {
+            (
+                CanonicalSymbol::Variable(typecode, number),
+                CanonicalSymbol::Variable(other_typecode, other_number),
+            ) if typecode == other_typecode => {
+                if *number < assertion_variable_count || *other_number < assertion_variable_count {
+                    if number != other_number {
+                        return false;
+                    }
+                    continue;
+                }
+
+                match renaming.get(number) {
+                    Some(renamed_number) if renamed_number != other_number => return false,
+                    Some(_) => {}
+                    None => {
+                        if renaming
+                            .values()
+                            .any(|renamed_number| renamed_number == other_number)
+                        {
+                            return false;
+                        }
+                        renaming.insert(*number, *other_number);
+                    }
+                }
+            }
+            _ if symbol == other_symbol => {}
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+// Multiset inclusion of two sorted lists
+fn sorted_list_contained_in_other_sorted_list<T: Ord>(list: &[T], other_list: &[T]) -> bool {
+    let mut other_item_i = 0;
+
+    for item in list {
+        loop {
+            let Some(other_item) = other_list.get(other_item_i) else {
+                return false;
+            };
+
+            other_item_i += 1;
+
+            if other_item == item {
+                break;
+            } else if other_item > item {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn calc_proof_length(proof: &str) -> u32 {
+    if proof.starts_with("(") {
+        proof
+            .split_ascii_whitespace()
+            .skip_while(|token| *token != ")")
+            .flat_map(|token| token.chars())
+            .filter(|c| matches!(c, 'A'..='T'))
+            .count() as u32
+    } else {
+        proof.split_ascii_whitespace().count() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WFF: u32 = 0;
+    const TURNSTILE: u32 = 1;
+    const IMPLICATION: u32 = 0;
+
+    fn canonical_theorem(
+        label: &str,
+        theorem_number: u32,
+        assertion: Vec<CanonicalSymbol>,
+        hypotheses: Vec<Vec<CanonicalSymbol>>,
+    ) -> CanonicalTheorem {
+        CanonicalTheorem::new(
+            DuplicateTheoremInfo {
+                label: label.to_string(),
+                theorem_number,
+                proof_length: None,
+                is_discouraged: false,
+            },
+            assertion,
+            hypotheses,
+        )
+    }
+
+    // |- var
+    fn provable(var: u32) -> Vec<CanonicalSymbol> {
+        vec![
+            CanonicalSymbol::Typecode(TURNSTILE),
+            CanonicalSymbol::Variable(WFF, var),
+        ]
+    }
+
+    // |- ( antecedent -> consequent )
+    fn provable_implication(antecedent: u32, consequent: u32) -> Vec<CanonicalSymbol> {
+        vec![
+            CanonicalSymbol::Typecode(TURNSTILE),
+            CanonicalSymbol::Rule(IMPLICATION),
+            CanonicalSymbol::Variable(WFF, antecedent),
+            CanonicalSymbol::Variable(WFF, consequent),
+        ]
+    }
+
+    #[test]
+    fn duplicates_with_swapped_hypotheses_are_found() {
+        // { ( ph -> ps ), ( ch -> ps ), ph } => ps with ps, ph, ch numbered 0, 1, 2
+        let theorem = canonical_theorem(
+            "thm1",
+            1,
+            provable(0),
+            vec![
+                provable_implication(1, 0),
+                provable_implication(2, 0),
+                provable(1),
+            ],
+        );
+        // { ( ch -> ps ), ( ph -> ps ), ph } => ps with ps, ch, ph numbered 0, 1, 2
+        let swapped_theorem = canonical_theorem(
+            "thm2",
+            2,
+            provable(0),
+            vec![
+                provable_implication(1, 0),
+                provable_implication(2, 0),
+                provable(2),
+            ],
+        );
+
+        let (duplicates, weaker_theorems) =
+            calc_duplicates_and_weaker_theorems(&[theorem, swapped_theorem]);
+
+        assert_eq!(duplicates.len(), 1);
+        let labels: Vec<&str> = duplicates[0].iter().map(|i| &*i.label).collect();
+        assert_eq!(labels, vec!["thm1", "thm2"]);
+        assert!(weaker_theorems.is_empty());
+    }
+
+    #[test]
+    fn weaker_theorem_with_extra_hypothesis_is_found() {
+        // { ( ph -> ps ), ph } => ps with ps, ph numbered 0, 1
+        let stronger = canonical_theorem(
+            "stronger",
+            1,
+            provable(0),
+            vec![provable_implication(1, 0), provable(1)],
+        );
+        // { ( ch -> ps ), ( ph -> ps ), ph } => ps with ps, ch, ph numbered 0, 1, 2
+        let weaker = canonical_theorem(
+            "weaker",
+            2,
+            provable(0),
+            vec![
+                provable_implication(1, 0),
+                provable_implication(2, 0),
+                provable(2),
+            ],
+        );
+
+        let (duplicates, weaker_theorems) =
+            calc_duplicates_and_weaker_theorems(&[stronger, weaker]);
+
+        assert!(duplicates.is_empty());
+        assert_eq!(weaker_theorems.len(), 1);
+        assert_eq!(weaker_theorems[0].weaker.label, "weaker");
+        assert_eq!(weaker_theorems[0].stronger.label, "stronger");
+    }
+
+    #[test]
+    fn assertion_variables_are_not_renamed() {
+        // { ph } => ps and { ps } => ps
+        let theorem = canonical_theorem("thm1", 1, provable(0), vec![provable(1)]);
+        let other_theorem = canonical_theorem("thm2", 2, provable(0), vec![provable(0)]);
+
+        let (duplicates, weaker_theorems) =
+            calc_duplicates_and_weaker_theorems(&[theorem, other_theorem]);
+
+        assert!(duplicates.is_empty());
+        assert!(weaker_theorems.is_empty());
+    }
+}
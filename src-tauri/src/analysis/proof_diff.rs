@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    metamath::{
+        mm_parser,
+        verify::{Show, StepResult, Verifier, VerifierCreationResult},
+    },
+    model::{self, MetamathData, Theorem},
+    AppState, Error,
+};
+
+// Differences between an old and a new proof of the same assertion. Steps are aligned by their assertion
+#[derive(Serialize)]
+pub struct ProofDiff {
+    #[serde(rename = "addedSteps")]
+    pub added_steps: Vec<ProofDiffStep>,
+    #[serde(rename = "removedSteps")]
+    pub removed_steps: Vec<ProofDiffStep>,
+    #[serde(rename = "changedSteps")]
+    pub changed_steps: Vec<ChangedProofDiffStep>,
+    #[serde(rename = "unchangedSteps")]
+    pub unchanged_steps: u32,
+    #[serde(rename = "addedAxioms")]
+    pub added_axioms: Vec<String>,
+    #[serde(rename = "removedAxioms")]
+    pub removed_axioms: Vec<String>,
+    #[serde(rename = "addedDefinitions")]
+    pub added_definitions: Vec<String>,
+    #[serde(rename = "removedDefinitions")]
+    pub removed_definitions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProofDiffStep {
+    pub assertion: String,
+    pub reference: String,
+    pub hypotheses: Vec<String>,
+}
+
+// A step whose assertion occurs in both proofs, but which is proven differently
+#[derive(Serialize)]
+pub struct ChangedProofDiffStep {
+    pub assertion: String,
+    #[serde(rename = "oldReference")]
+    pub old_reference: String,
+    #[serde(rename = "newReference")]
+    pub new_reference: String,
+    #[serde(rename = "oldHypotheses")]
+    pub old_hypotheses: Vec<String>,
+    #[serde(rename = "newHypotheses")]
+    pub new_hypotheses: Vec<String>,
+}
+
+// Compares the proof of a theorem in the current database (new) with its proof in another .mm file (old)
+#[tauri::command]
+pub async fn diff_theorem_proof(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+    mm_file_path: &str,
+) -> Result<Option<ProofDiff>, Error> {
+    let mut app_state = state.lock().await;
+    let database_id = app_state.id_manager.get_next_id();
+    let settings = app_state.settings.clone();
+    drop(app_state);
+
+    let old_metamath_data = mm_parser::load_metamath_data(mm_file_path, database_id, &settings)?;
+
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let new_theorem = metamath_data
+        .database_header
+        .find_theorem_by_label(label)
+        .ok_or(Error::NotFoundError)?;
+
+    let old_theorem = old_metamath_data
+        .database_header
+        .find_theorem_by_label(label)
+        .ok_or(Error::NotFoundError)?;
+
+    if old_theorem.assertion != new_theorem.assertion
+        || old_theorem.hypotheses.len() != new_theorem.hypotheses.len()
+        || old_theorem
+            .hypotheses
+            .iter()
+            .zip(new_theorem.hypotheses.iter())
+            .any(|(old_hyp, new_hyp)| old_hyp.expression != new_hyp.expression)
+    {
+        return Err(Error::ProofDiffAssertionMismatchError);
+    }
+
+    calc_proof_diff(old_theorem, &old_metamath_data, new_theorem, metamath_data)
+}
+
+// Returns None if one of the proofs is incomplete or the theorems are axioms
+pub fn calc_proof_diff(
+    old_theorem: &Theorem,
+    old_metamath_data: &MetamathData,
+    new_theorem: &Theorem,
+    new_metamath_data: &MetamathData,
+) -> Result<Option<ProofDiff>, Error> {
+    let Some(old_steps) = calc_proof_diff_steps(old_theorem, old_metamath_data)? else {
+        return Ok(None);
+    };
+    let Some(new_steps) = calc_proof_diff_steps(new_theorem, new_metamath_data)? else {
+        return Ok(None);
+    };
+
+    // Old steps that haven't been matched with a new step yet, grouped by assertion
+    let mut unmatched_old_steps: HashMap<&str, Vec<&ProofDiffStep>> = HashMap::new();
+    for old_step in &old_steps {
+        unmatched_old_steps
+            .entry(&old_step.assertion)
+            .or_default()
+            .push(old_step);
+    }
+
+    let mut added_steps = Vec::new();
+    let mut changed_steps = Vec::new();
+    let mut unchanged_steps = 0;
+
+    for new_step in new_steps {
+        let Some(old_steps_with_assertion) = unmatched_old_steps
+            .get_mut(new_step.assertion.as_str())
+            .filter(|steps| !steps.is_empty())
+        else {
+            added_steps.push(new_step);
+            continue;
+        };
+
+        let old_step_i = old_steps_with_assertion
+            .iter()
+            .position(|old_step| {
+                old_step.reference == new_step.reference
+                    && old_step.hypotheses == new_step.hypotheses
+            })
+            .unwrap_or(0);
+        let old_step = old_steps_with_assertion.remove(old_step_i);
+
+        if old_step.reference == new_step.reference && old_step.hypotheses == new_step.hypotheses {
+            unchanged_steps += 1;
+        } else {
+            changed_steps.push(ChangedProofDiffStep {
+                assertion: new_step.assertion,
+                old_reference: old_step.reference.clone(),
+                new_reference: new_step.reference,
+                old_hypotheses: old_step.hypotheses.clone(),
+                new_hypotheses: new_step.hypotheses,
+            });
+        }
+    }
+
+    let removed_steps = old_steps
+        .iter()
+        .filter(|old_step| {
+            unmatched_old_steps
+                .get(old_step.assertion.as_str())
+                .is_some_and(|steps| steps.iter().any(|step| std::ptr::eq(*step, *old_step)))
+        })
+        .map(|old_step| ProofDiffStep {
+            assertion: old_step.assertion.clone(),
+            reference: old_step.reference.clone(),
+            hypotheses: old_step.hypotheses.clone(),
+        })
+        .collect();
+
+    let (old_axioms, old_definitions) = calc_dependency_labels(old_theorem, old_metamath_data)?;
+    let (new_axioms, new_definitions) = calc_dependency_labels(new_theorem, new_metamath_data)?;
+
+    Ok(Some(ProofDiff {
+        added_steps,
+        removed_steps,
+        changed_steps,
+        unchanged_steps,
+        added_axioms: labels_not_in(&new_axioms, &old_axioms),
+        removed_axioms: labels_not_in(&old_axioms, &new_axioms),
+        added_definitions: labels_not_in(&new_definitions, &old_definitions),
+        removed_definitions: labels_not_in(&old_definitions, &new_definitions),
+    }))
+}
+
+// Decompresses the proof and returns its logical steps, with the assertions of the hypotheses of each step instead of
+// their step names
+fn calc_proof_diff_steps(
+    theorem: &Theorem,
+    metamath_data: &MetamathData,
+) -> Result<Option<Vec<ProofDiffStep>>, Error> {
+    let Ok(VerifierCreationResult::Verifier(mut verifier)) = Verifier::new(
+        theorem,
+        metamath_data,
+        Show::Logical,
+        None,
+        None,
+        None,
+        None,
+    ) else {
+        return Ok(None);
+    };
+
+    let mut proof_lines: Vec<model::ProofLine> = Vec::new();
+
+    loop {
+        match verifier.proccess_next_step(metamath_data) {
+            Ok(StepResult::VerifierFinished) => break,
+            Ok(StepResult::NoProofLine) => {}
+            Ok(StepResult::ProofLine(proof_line)) => proof_lines.push(proof_line),
+            Err(Error::InvalidProofError) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+
+    let assertions: HashMap<&str, &str> = proof_lines
+        .iter()
+        .map(|pl| (pl.step_name.as_str(), pl.assertion.as_str()))
+        .collect();
+
+    Ok(Some(
+        proof_lines
+            .iter()
+            .map(|pl| {
+                Ok(ProofDiffStep {
+                    assertion: pl.assertion.clone(),
+                    reference: pl.reference.clone(),
+                    hypotheses: pl
+                        .hypotheses
+                        .iter()
+                        .map(|hyp| {
+                            assertions
+                                .get(hyp.as_str())
+                                .map(|assertion| assertion.to_string())
+                                .ok_or(Error::InternalLogicError)
+                        })
+                        .collect::<Result<Vec<String>, Error>>()?,
+                })
+            })
+            .collect::<Result<Vec<ProofDiffStep>, Error>>()?,
+    ))
+}
+
+fn calc_dependency_labels(
+    theorem: &Theorem,
+    metamath_data: &MetamathData,
+) -> Result<(Vec<String>, Vec<String>), Error> {
+    let theorem_data = metamath_data
+        .optimized_data
+        .theorem_data
+        .get(&theorem.label)
+        .ok_or(Error::InternalLogicError)?;
+
+    let to_labels = |theorem_i_vec: &Vec<usize>| -> Result<Vec<String>, Error> {
+        Ok(metamath_data
+            .database_header
+            .theorem_i_vec_to_theorem_label_vec(theorem_i_vec)
+            .map_err(|_| Error::InternalLogicError)?
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect())
+    };
+
+    Ok((
+        to_labels(&theorem_data.axiom_dependencies)?,
+        to_labels(&theorem_data.definition_dependencies)?,
+    ))
+}
+
+fn labels_not_in(labels: &[String], other_labels: &[String]) -> Vec<String> {
+    labels
+        .iter()
+        .filter(|label| !other_labels.contains(label))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    editor::on_edit::DetailedError,
+    metamath::{
+        mmp_parser::{
+            stage_3::calc_statement_str_and_line_number, MmpParserStage1Success,
+            MmpParserStage2Success, MmpStatement,
+        },
+        verify::{ProofStep, Show, StepResult, Verifier, VerifierCreationResult},
+    },
+    model::{FloatingHypothesis, MetamathData, Theorem, VerificationProofSteps},
+    util, AppState, Error,
+};
+
+#[derive(Serialize)]
+pub struct ProofLintResult {
+    #[serde(rename = "unusedHypotheses")]
+    pub unused_hypotheses: Vec<String>,
+    #[serde(rename = "unnecessaryDistinctVars")]
+    pub unnecessary_distinct_vars: Vec<(String, String)>,
+    #[serde(rename = "missingDistinctVars")]
+    pub missing_distinct_vars: Vec<(String, String)>,
+}
+
+#[derive(Serialize)]
+pub struct ProofLintEntry {
+    pub label: String,
+    #[serde(rename = "theoremNumber")]
+    pub theorem_number: u32,
+    pub lint: ProofLintResult,
+}
+
+#[tauri::command]
+pub async fn lint_theorem_proof(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+) -> Result<Option<ProofLintResult>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let theorem = metamath_data
+        .database_header
+        .find_theorem_by_label(label)
+        .ok_or(Error::NotFoundError)?;
+
+    let theorem_data = metamath_data
+        .optimized_data
+        .theorem_data
+        .get(label)
+        .ok_or(Error::InternalLogicError)?;
+
+    calc_proof_lint(
+        theorem,
+        metamath_data,
+        &theorem_data.distinct_variable_pairs,
+        None,
+        None,
+        None,
+    )
+}
+
+// Returns only the theorems for which the linter found something
+#[tauri::command]
+pub async fn lint_database_proofs(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<Vec<ProofLintEntry>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let VerificationProofSteps {
+        proof_steps,
+        theorems,
+    } = metamath_data.calc_verification_proof_steps(None, None, &mut 0)?;
+
+    let lint_results: Vec<Option<ProofLintResult>> = theorems
+        .into_par_iter()
+        .map(
+            |(theorem, prev_floating_hypotheses_num, compressed_infered_proof_steps)| {
+                let theorem_data = metamath_data
+                    .optimized_data
+                    .theorem_data
+                    .get(&theorem.label)
+                    .ok_or(Error::InternalLogicError)?;
+
+                if theorem.proof.is_none() {
+                    return Ok(None);
+                }
+
+                calc_proof_lint(
+                    theorem,
+                    metamath_data,
+                    &theorem_data.distinct_variable_pairs,
+                    Some(&proof_steps),
+                    Some(
+                        &metamath_data.optimized_data.floating_hypotheses
+                            [..prev_floating_hypotheses_num],
+                    ),
+                    Some(compressed_infered_proof_steps),
+                )
+            },
+        )
+        .collect::<Result<Vec<Option<ProofLintResult>>, Error>>()?;
+
+    Ok(metamath_data
+        .database_header
+        .theorem_iter()
+        .zip(lint_results)
+        .enumerate()
+        .filter_map(|(i, (theorem, lint))| {
+            lint.filter(|lint| !lint.is_empty())
+                .map(|lint| ProofLintEntry {
+                    label: theorem.label.clone(),
+                    theorem_number: (i + 1) as u32,
+                    lint,
+                })
+        })
+        .collect())
+}
+
+// Replays the proof and returns None if it is incomplete or invalid for reasons other than
+// missing distinct variable conditions
+pub fn calc_proof_lint<'a>(
+    theorem: &'a Theorem,
+    metamath_data: &'a MetamathData,
+    distinct_variable_pairs: &'a HashSet<(String, String)>,
+    already_calculated_proof_steps: Option<&'a HashMap<&'a str, ProofStep<'a>>>,
+    prev_flaoting_hypotheses: Option<&'a [FloatingHypothesis]>,
+    compressed_infered_proof_steps: Option<Vec<ProofStep<'a>>>,
+) -> Result<Option<ProofLintResult>, Error> {
+    let mut verifier = match Verifier::new(
+        theorem,
+        metamath_data,
+        Show::None,
+        Some(distinct_variable_pairs),
+        already_calculated_proof_steps,
+        prev_flaoting_hypotheses,
+        compressed_infered_proof_steps,
+    ) {
+        Ok(VerifierCreationResult::Verifier(v)) => v,
+        Ok(_) | Err(Error::InvalidProofError) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    verifier.track_usage();
+
+    loop {
+        match verifier.proccess_next_step(metamath_data) {
+            Ok(StepResult::VerifierFinished) => break,
+            Ok(_) => {}
+            Err(Error::InvalidProofError) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+
+    let stack = verifier.get_stack();
+    if stack.len() != 1 || stack[0].statement != theorem.assertion {
+        return Ok(None);
+    }
+
+    let usage = verifier.take_usage().ok_or(Error::InternalLogicError)?;
+
+    let unused_hypotheses = theorem
+        .hypotheses
+        .iter()
+        .filter(|hyp| !usage.used_labels.contains(&*hyp.label))
+        .map(|hyp| hyp.label.clone())
+        .collect();
+
+    let mut unnecessary_distinct_vars: Vec<(String, String)> = distinct_variable_pairs
+        .iter()
+        .filter(|pair| pair.0 < pair.1 && !usage.required_distinct_var_pairs.contains(*pair))
+        .cloned()
+        .collect();
+    unnecessary_distinct_vars.sort_unstable();
+
+    let mut missing_distinct_vars: Vec<(String, String)> = usage
+        .required_distinct_var_pairs
+        .into_iter()
+        .filter(|pair| !distinct_variable_pairs.contains(pair))
+        .collect();
+    missing_distinct_vars.sort_unstable();
+
+    Ok(Some(ProofLintResult {
+        unused_hypotheses,
+        unnecessary_distinct_vars,
+        missing_distinct_vars,
+    }))
+}
+
+pub fn calc_mmp_proof_lint_warnings(
+    stage_1: &MmpParserStage1Success,
+    stage_2: &MmpParserStage2Success,
+    theorem: &Theorem,
+    metamath_data: &MetamathData,
+) -> Result<Vec<DetailedError>, Error> {
+    let distinct_variable_pairs = util::calc_distinct_variable_pairs(&theorem.distincts);
+
+    let Some(lint) = calc_proof_lint(
+        theorem,
+        metamath_data,
+        &distinct_variable_pairs,
+        None,
+        None,
+        None,
+    )?
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut warnings: Vec<DetailedError> = Vec::new();
+
+    for label in &lint.unused_hypotheses {
+        if let Some(proof_line_i) = stage_2
+            .proof_lines
+            .iter()
+            .position(|pl| pl.is_hypothesis && pl.step_ref == label)
+        {
+            warnings.push(calc_statement_warning(
+                stage_1,
+                stage_2,
+                MmpStatement::ProofLine,
+                proof_line_i,
+                Error::UnusedHypothesisWarning,
+            )?);
+        }
+    }
+
+    for (distinct_var_i, distinct_var_str) in stage_2.distinct_vars.iter().enumerate() {
+        let distinct_vars: Vec<&str> = distinct_var_str.split_ascii_whitespace().collect();
+
+        if lint.unnecessary_distinct_vars.iter().any(|(var_1, var_2)| {
+            distinct_vars.contains(&var_1.as_str()) && distinct_vars.contains(&var_2.as_str())
+        }) {
+            warnings.push(calc_statement_warning(
+                stage_1,
+                stage_2,
+                MmpStatement::DistinctVar,
+                distinct_var_i,
+                Error::UnnecessaryDistinctVarWarning,
+            )?);
+        }
+    }
+
+    if !lint.missing_distinct_vars.is_empty() {
+        warnings.push(calc_statement_warning(
+            stage_1,
+            stage_2,
+            MmpStatement::MmpLabel,
+            0,
+            Error::MissingDistinctVarWarning,
+        )?);
+    }
+
+    Ok(warnings)
+}
+
+fn calc_statement_warning(
+    stage_1: &MmpParserStage1Success,
+    stage_2: &MmpParserStage2Success,
+    statement_type: MmpStatement,
+    statement_i: usize,
+    warning_type: Error,
+) -> Result<DetailedError, Error> {
+    let (statement_str, line_number) = calc_statement_str_and_line_number(
+        &stage_1.statements,
+        &stage_2.statements,
+        statement_type,
+        statement_i,
+    )
+    .ok_or(Error::InternalLogicError)?;
+
+    let last_non_whitespace_pos = util::last_non_whitespace_pos(statement_str);
+
+    Ok(DetailedError {
+        error_type: warning_type,
+        start_line_number: line_number,
+        start_column: 1,
+        end_line_number: line_number + last_non_whitespace_pos.0 - 1,
+        end_column: last_non_whitespace_pos.1 + 1,
+        explanation: None,
+        expected_symbols: None,
+    })
+}
+
+impl ProofLintResult {
+    pub fn is_empty(&self) -> bool {
+        self.unused_hypotheses.is_empty()
+            && self.unnecessary_distinct_vars.is_empty()
+            && self.missing_distinct_vars.is_empty()
+    }
+}
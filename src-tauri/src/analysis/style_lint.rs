@@ -0,0 +1,487 @@
+use serde::Serialize;
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    editor::on_edit::DetailedError,
+    metamath::{
+        mm_parser::html_validation,
+        mmp_parser::{
+            self, stage_3::calc_statement_str_and_line_number, MmpLabel, MmpParserStage1,
+            MmpParserStage1Success, MmpParserStage2, MmpParserStage2Success, MmpStatement,
+        },
+    },
+    model::{MetamathData, ParsedDescriptionSegment, ProofType, TheoremType},
+    util::{self, description_parser},
+    AppState, Error, Settings,
+};
+
+const NEW_USAGE_DISCOURAGED: &str = "(New usage is discouraged.)";
+const PROOF_MODIFICATION_DISCOURAGED: &str = "(Proof modification is discouraged.)";
+
+const CONTRIBUTOR_LINE_STARTS: [&str; 3] =
+    ["(Contributed by ", "(Revised by ", "(Proof shortened by "];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub struct StyleWarning {
+    pub warning_type: Error,
+    // Line of the description starting at 0, None if the warning is about the label or the
+    // statement as a whole
+    pub description_line: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct StyleLintEntry {
+    pub label: String,
+    #[serde(rename = "theoremNumber")]
+    pub theorem_number: u32,
+    // Positions are lines and columns of the description of the theorem
+    pub warnings: Vec<DetailedError>,
+}
+
+// Returns only the theorems with at least one warning
+#[tauri::command]
+pub async fn lint_database_style(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<Vec<StyleLintEntry>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+    let settings = &app_state.settings;
+
+    Ok(metamath_data
+        .database_header
+        .theorem_iter()
+        .enumerate()
+        .filter_map(|(i, theorem)| {
+            let theorem_data = metamath_data
+                .optimized_data
+                .theorem_data
+                .get(&theorem.label)?;
+
+            let warnings = calc_style_warnings(
+                &theorem.label,
+                &theorem.description,
+                &theorem_data.description_parsed,
+                theorem_data.theorem_type,
+                settings,
+            );
+
+            (!warnings.is_empty()).then(|| StyleLintEntry {
+                label: theorem.label.clone(),
+                theorem_number: (i + 1) as u32,
+                warnings: warnings
+                    .into_iter()
+                    .map(|warning| {
+                        calc_description_style_warning_position(
+                            warning,
+                            &theorem.description,
+                            settings,
+                        )
+                    })
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+// Meant to be run before add_to_database. Parse errors are already reported by on_edit, so an
+// mmp file that can't be parsed has no style warnings. Every block of a worksheet is linted on
+// its own
+#[tauri::command]
+pub async fn lint_mmp_style(
+    state: tauri::State<'_, Mutex<AppState>>,
+    text: &str,
+) -> Result<Vec<DetailedError>, Error> {
+    let app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+    let settings = &app_state.settings;
+
+    let mut warnings = Vec::new();
+
+    for (block, lines_before) in mmp_parser::split_into_theorem_blocks(text) {
+        warnings.extend(
+            calc_mmp_style_warnings(block, mm_data, settings)?
+                .into_iter()
+                .map(|mut warning| {
+                    warning.start_line_number += lines_before;
+                    warning.end_line_number += lines_before;
+                    warning
+                }),
+        );
+    }
+
+    Ok(warnings)
+}
+
+fn calc_mmp_style_warnings(
+    text: &str,
+    mm_data: &MetamathData,
+    settings: &Settings,
+) -> Result<Vec<DetailedError>, Error> {
+    let stage_0 = mmp_parser::new(text);
+
+    let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
+        return Ok(Vec::new());
+    };
+
+    let MmpParserStage2::Success(stage_2_success) = stage_1_success.next_stage()? else {
+        return Ok(Vec::new());
+    };
+
+    let (label, theorem_type) = match stage_2_success.label {
+        Some(MmpLabel::Theorem(label)) => (label, TheoremType::Theorem(ProofType::Correct)), // ProofType doesn't matter for linting
+        Some(MmpLabel::Axiom(label)) => (
+            label,
+            calc_mmp_axiom_type(label, &stage_2_success, mm_data, settings),
+        ),
+        _ => return Ok(Vec::new()),
+    };
+
+    // The comments directly before the label start the block, so the first one is the description
+    let description = stage_2_success.comments.first().copied().unwrap_or("");
+
+    let (allowed_tags_and_attributes, allowed_css_properties) =
+        html_validation::create_rule_structs();
+    let (description_parsed, _) = description_parser::parse_description(
+        description,
+        &mm_data.database_header,
+        &allowed_tags_and_attributes,
+        &allowed_css_properties,
+    );
+
+    calc_style_warnings(
+        label,
+        description,
+        &description_parsed,
+        theorem_type,
+        settings,
+    )
+    .into_iter()
+    .map(|warning| {
+        calc_mmp_style_warning_position(warning, &stage_1_success, &stage_2_success, settings)
+    })
+    .collect()
+}
+
+pub fn calc_style_warnings(
+    label: &str,
+    description: &str,
+    description_parsed: &[ParsedDescriptionSegment],
+    theorem_type: TheoremType,
+    settings: &Settings,
+) -> Vec<StyleWarning> {
+    let style_lint = &settings.style_lint;
+
+    let mut warnings: Vec<StyleWarning> = Vec::new();
+
+    let (collapsed_description, collapsed_line_numbers) = collapse_whitespace(description);
+
+    if style_lint.label_naming {
+        let looks_like_axiom = label.starts_with("ax-");
+        let looks_like_definition = !settings.definitons_start_with.is_empty()
+            && label.starts_with(&settings.definitons_start_with);
+
+        let misnamed = match theorem_type {
+            TheoremType::Theorem(_) => looks_like_axiom || looks_like_definition,
+            TheoremType::Axiom => !looks_like_axiom,
+            TheoremType::Definition | TheoremType::SyntaxAxiom => false,
+        };
+
+        if misnamed {
+            warnings.push(StyleWarning {
+                warning_type: Error::LabelNamingWarning,
+                description_line: None,
+            });
+        }
+    }
+
+    if style_lint.discouraged_tags {
+        // By convention, "OLD" and "ALT" theorems are kept for reference only
+        if (label.ends_with("OLD") || label.ends_with("ALT"))
+            && (!collapsed_description.contains(NEW_USAGE_DISCOURAGED)
+                || (theorem_type.is_theorem()
+                    && !collapsed_description.contains(PROOF_MODIFICATION_DISCOURAGED)))
+        {
+            warnings.push(StyleWarning {
+                warning_type: Error::MissingDiscouragedTagWarning,
+                description_line: None,
+            });
+        }
+
+        for (line_i, line) in description.split('\n').enumerate() {
+            // to_ascii_lowercase keeps the byte positions intact
+            let lowercase_line = line.to_ascii_lowercase();
+
+            let malformed = [NEW_USAGE_DISCOURAGED, PROOF_MODIFICATION_DISCOURAGED]
+                .iter()
+                .any(|tag| {
+                    // The tag without "(New " or "(Proof ", which is unlikely to be split over 2 lines
+                    let tag_start_len = tag.find(' ').unwrap_or(0) + 1;
+                    let phrase = tag[tag_start_len..tag.len() - 2].to_ascii_lowercase();
+
+                    lowercase_line.match_indices(&phrase).any(|(pos, _)| {
+                        !pos.checked_sub(tag_start_len)
+                            .and_then(|tag_pos| line.get(tag_pos..))
+                            .is_some_and(|rest| rest.starts_with(tag))
+                    })
+                });
+
+            if malformed {
+                warnings.push(StyleWarning {
+                    warning_type: Error::MalformedDiscouragedTagWarning,
+                    description_line: Some(line_i as u32),
+                });
+            }
+        }
+    }
+
+    if style_lint.description_presence && !has_description_text(description_parsed) {
+        warnings.push(StyleWarning {
+            warning_type: Error::MissingDescriptionWarning,
+            description_line: None,
+        });
+    }
+
+    // Syntax axioms traditionally have no contributor lines
+    if style_lint.contributor_lines && !theorem_type.is_syntax_axiom() {
+        if !collapsed_description.contains(CONTRIBUTOR_LINE_STARTS[0]) {
+            warnings.push(StyleWarning {
+                warning_type: Error::MissingContributedByWarning,
+                description_line: None,
+            });
+        }
+
+        for contributor_line_start in CONTRIBUTOR_LINE_STARTS {
+            for (pos, _) in collapsed_description.match_indices(contributor_line_start) {
+                let rest = &collapsed_description[pos + contributor_line_start.len()..];
+
+                let well_formed = rest.find(".)").is_some_and(|end| {
+                    rest[..end]
+                        .rsplit_once(", ")
+                        .is_some_and(|(name, date)| !name.trim().is_empty() && is_valid_date(date))
+                });
+
+                if !well_formed {
+                    warnings.push(StyleWarning {
+                        warning_type: Error::MalformedContributorLineWarning,
+                        description_line: collapsed_line_numbers.get(pos).copied(),
+                    });
+                }
+            }
+        }
+    }
+
+    if style_lint.line_length {
+        // The "$(" in front of the first line is not counted
+        for (line_i, line) in description.split('\n').enumerate() {
+            if line.trim_end().chars().count() > style_lint.max_line_length as usize {
+                warnings.push(StyleWarning {
+                    warning_type: Error::LineTooLongWarning,
+                    description_line: Some(line_i as u32),
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+fn calc_mmp_axiom_type(
+    label: &str,
+    stage_2: &MmpParserStage2Success,
+    mm_data: &MetamathData,
+    settings: &Settings,
+) -> TheoremType {
+    let is_syntax_axiom = stage_2
+        .proof_lines
+        .iter()
+        .find(|pl| pl.step_name == "qed")
+        .and_then(|pl| pl.expression.split_ascii_whitespace().next())
+        .is_some_and(|typecode| {
+            mm_data
+                .syntax_typecodes
+                .iter()
+                .any(|st| st.typecode == typecode)
+        });
+
+    if is_syntax_axiom {
+        TheoremType::SyntaxAxiom
+    } else if label.starts_with(&settings.definitons_start_with) {
+        TheoremType::Definition
+    } else {
+        TheoremType::Axiom
+    }
+}
+
+// Warnings about the label or the statement as a whole span the whole description
+fn calc_description_style_warning_position(
+    warning: StyleWarning,
+    description: &str,
+    settings: &Settings,
+) -> DetailedError {
+    let Some(description_line) = warning.description_line else {
+        let last_non_whitespace_pos = util::last_non_whitespace_pos(description);
+
+        return DetailedError {
+            error_type: warning.warning_type,
+            start_line_number: 1,
+            start_column: 1,
+            end_line_number: last_non_whitespace_pos.0,
+            end_column: last_non_whitespace_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
+        };
+    };
+
+    let line_len = description
+        .split('\n')
+        .nth(description_line as usize)
+        .map_or(0, |line| line.trim_end().chars().count() as u32);
+
+    DetailedError {
+        error_type: warning.warning_type,
+        start_line_number: description_line + 1,
+        start_column: calc_style_warning_start_column(&warning, settings),
+        end_line_number: description_line + 1,
+        end_column: line_len + 1,
+        explanation: None,
+        expected_symbols: None,
+    }
+}
+
+fn calc_style_warning_start_column(warning: &StyleWarning, settings: &Settings) -> u32 {
+    if matches!(warning.warning_type, Error::LineTooLongWarning) {
+        settings.style_lint.max_line_length + 1
+    } else {
+        1
+    }
+}
+
+fn calc_mmp_style_warning_position(
+    warning: StyleWarning,
+    stage_1: &MmpParserStage1Success,
+    stage_2: &MmpParserStage2Success,
+    settings: &Settings,
+) -> Result<DetailedError, Error> {
+    let (statement_type, line_in_statement) = match warning.description_line {
+        Some(line) => (MmpStatement::Comment, Some(line)),
+        None => (MmpStatement::MmpLabel, None),
+    };
+
+    let (statement_str, line_number) = calc_statement_str_and_line_number(
+        &stage_1.statements,
+        &stage_2.statements,
+        statement_type,
+        0,
+    )
+    .ok_or(Error::InternalLogicError)?;
+
+    let Some(line_in_statement) = line_in_statement else {
+        let last_non_whitespace_pos = util::last_non_whitespace_pos(statement_str);
+
+        return Ok(DetailedError {
+            error_type: warning.warning_type,
+            start_line_number: line_number,
+            start_column: 1,
+            end_line_number: line_number + last_non_whitespace_pos.0 - 1,
+            end_column: last_non_whitespace_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
+        });
+    };
+
+    // The comment statement starts with '*', which is not part of the description, so the lines
+    // of the statement and the description line up
+    let line = statement_str
+        .split('\n')
+        .nth(line_in_statement as usize)
+        .ok_or(Error::InternalLogicError)?;
+    let line_len = line.trim_end().chars().count() as u32;
+
+    Ok(DetailedError {
+        error_type: warning.warning_type,
+        start_line_number: line_number + line_in_statement,
+        start_column: calc_style_warning_start_column(&warning, settings),
+        end_line_number: line_number + line_in_statement,
+        end_column: line_len + 1,
+        explanation: None,
+        expected_symbols: None,
+    })
+}
+
+// Replaces every run of whitespace with a single space, and returns the line number (starting at 0)
+// of every byte in the collapsed string, so that tags split over multiple lines can be found
+fn collapse_whitespace(description: &str) -> (String, Vec<u32>) {
+    let mut collapsed = String::with_capacity(description.len());
+    let mut line_numbers: Vec<u32> = Vec::with_capacity(description.len());
+
+    let mut line_number: u32 = 0;
+    let mut last_was_whitespace = false;
+
+    for char in description.chars() {
+        if char.is_whitespace() {
+            if !last_was_whitespace {
+                collapsed.push(' ');
+                line_numbers.push(line_number);
+            }
+            last_was_whitespace = true;
+        } else {
+            collapsed.push(char);
+            line_numbers.extend(std::iter::repeat_n(line_number, char.len_utf8()));
+            last_was_whitespace = false;
+        }
+
+        if char == '\n' {
+            line_number += 1;
+        }
+    }
+
+    (collapsed, line_numbers)
+}
+
+// Tags like "(Contributed by ...)" don't count as description text
+fn has_description_text(description_parsed: &[ParsedDescriptionSegment]) -> bool {
+    description_parsed.iter().any(|segment| match segment {
+        ParsedDescriptionSegment::Text(text) | ParsedDescriptionSegment::Italic(text) => {
+            let mut text = text
+                .replace(NEW_USAGE_DISCOURAGED, "")
+                .replace(PROOF_MODIFICATION_DISCOURAGED, "");
+
+            for contributor_line_start in CONTRIBUTOR_LINE_STARTS {
+                while let Some(pos) = text.find(contributor_line_start) {
+                    let end = text[pos..]
+                        .find(".)")
+                        .map_or(text.len(), |end| pos + end + 2);
+                    text.replace_range(pos..end, "");
+                }
+            }
+
+            !text.trim().is_empty()
+        }
+        ParsedDescriptionSegment::MathMode(s)
+        | ParsedDescriptionSegment::Label(s, _)
+        | ParsedDescriptionSegment::Link(s) => !s.trim().is_empty(),
+        ParsedDescriptionSegment::Subscript(_)
+        | ParsedDescriptionSegment::Html(_)
+        | ParsedDescriptionSegment::HtmlCharacterRef(_) => false,
+    })
+}
+
+// Dates have the form D-Mon-YYYY or DD-Mon-YYYY
+fn is_valid_date(date: &str) -> bool {
+    let mut parts = date.split('-');
+
+    let (Some(day), Some(month), Some(year), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+
+    (1..=2).contains(&day.len())
+        && day.chars().all(|c| c.is_ascii_digit())
+        && MONTHS.contains(&month)
+        && year.len() == 4
+        && year.chars().all(|c| c.is_ascii_digit())
+}
@@ -0,0 +1,292 @@
+use std::{collections::HashMap, fmt::Write, fs};
+
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    model::{MetamathData, Theorem},
+    search, util, AppState, Error,
+};
+
+#[derive(Serialize)]
+pub struct UsageReport {
+    #[serde(rename = "axiomUsage")]
+    pub axiom_usage: Vec<UsageEntry>,
+    #[serde(rename = "definitionUsage")]
+    pub definition_usage: Vec<UsageEntry>,
+    #[serde(rename = "excessAxiomUsage")]
+    pub excess_axiom_usage: Vec<ExcessAxiomUsageEntry>,
+}
+
+#[derive(Serialize)]
+pub struct UsageEntry {
+    pub label: String,
+    #[serde(rename = "theoremNumber")]
+    pub theorem_number: u32,
+    #[serde(rename = "dependentTheorems")]
+    pub dependent_theorems: u32,
+}
+
+// A theorem that needs more axioms than a sibling theorem proving the same statement
+#[derive(Serialize)]
+pub struct ExcessAxiomUsageEntry {
+    pub label: String,
+    #[serde(rename = "theoremNumber")]
+    pub theorem_number: u32,
+    #[serde(rename = "siblingLabel")]
+    pub sibling_label: String,
+    #[serde(rename = "siblingTheoremNumber")]
+    pub sibling_theorem_number: u32,
+    #[serde(rename = "excessAxioms")]
+    pub excess_axioms: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum UsageReportTable {
+    #[serde(rename = "axioms")]
+    Axioms,
+    #[serde(rename = "definitions")]
+    Definitions,
+    #[serde(rename = "excessAxioms")]
+    ExcessAxioms,
+}
+
+#[tauri::command]
+pub async fn get_usage_report(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<UsageReport, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    Ok(calc_usage_report(metamath_data))
+}
+
+// Returns all theorems that would become unprovable if the given axiom or definition was removed
+#[tauri::command]
+pub async fn get_theorems_depending_on(
+    state: tauri::State<'_, Mutex<AppState>>,
+    label: &str,
+) -> Result<Vec<(String, u32)>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let (axiom_i, _) = metamath_data
+        .database_header
+        .find_theorem_and_index_by_label(label)
+        .ok_or(Error::NotFoundError)?;
+
+    Ok(metamath_data
+        .database_header
+        .theorem_iter()
+        .enumerate()
+        .filter(|&(i, theorem)| {
+            i != axiom_i
+                && metamath_data
+                    .optimized_data
+                    .theorem_data
+                    .get(&theorem.label)
+                    .is_some_and(|theorem_data| {
+                        theorem_data.theorem_type.is_theorem()
+                            && (theorem_data
+                                .axiom_dependencies
+                                .binary_search(&axiom_i)
+                                .is_ok()
+                                || theorem_data
+                                    .definition_dependencies
+                                    .binary_search(&axiom_i)
+                                    .is_ok())
+                    })
+        })
+        .map(|(i, theorem)| (theorem.label.clone(), (i + 1) as u32))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn write_usage_report_csv_to_file(
+    state: tauri::State<'_, Mutex<AppState>>,
+    table: UsageReportTable,
+    file_path: &str,
+) -> Result<(), Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let report = calc_usage_report(metamath_data);
+
+    fs::write(file_path, report.to_csv_string(table)).map_err(|_| Error::FileWriteError)?;
+
+    Ok(())
+}
+
+pub fn calc_usage_report(metamath_data: &MetamathData) -> UsageReport {
+    let theorems: Vec<&Theorem> = metamath_data.database_header.theorem_iter().collect();
+
+    let mut dependent_theorems: Vec<u32> = vec![0; theorems.len()];
+
+    // Theorems with the same assertion and hypotheses, in database order
+    let mut siblings: HashMap<(String, Vec<String>), Vec<usize>> = HashMap::new();
+
+    for (i, theorem) in theorems.iter().enumerate() {
+        let Some(theorem_data) = metamath_data
+            .optimized_data
+            .theorem_data
+            .get(&theorem.label)
+        else {
+            continue;
+        };
+
+        if !theorem_data.theorem_type.is_theorem() {
+            continue;
+        }
+
+        for &dep in theorem_data
+            .axiom_dependencies
+            .iter()
+            .chain(theorem_data.definition_dependencies.iter())
+        {
+            if let Some(count) = dependent_theorems.get_mut(dep) {
+                *count += 1;
+            }
+        }
+
+        siblings
+            .entry((
+                util::str_to_space_seperated_string(&theorem.assertion),
+                theorem
+                    .hypotheses
+                    .iter()
+                    .map(|hyp| util::str_to_space_seperated_string(&hyp.expression))
+                    .collect(),
+            ))
+            .or_default()
+            .push(i);
+    }
+
+    let mut axiom_usage: Vec<UsageEntry> = Vec::new();
+    let mut definition_usage: Vec<UsageEntry> = Vec::new();
+
+    for (i, theorem) in theorems.iter().enumerate() {
+        let Some(theorem_data) = metamath_data
+            .optimized_data
+            .theorem_data
+            .get(&theorem.label)
+        else {
+            continue;
+        };
+
+        let entry = UsageEntry {
+            label: theorem.label.clone(),
+            theorem_number: (i + 1) as u32,
+            dependent_theorems: dependent_theorems[i],
+        };
+
+        if theorem_data.theorem_type.is_axiom() {
+            axiom_usage.push(entry);
+        } else if theorem_data.theorem_type.is_definition() {
+            definition_usage.push(entry);
+        }
+    }
+
+    let mut excess_axiom_usage: Vec<ExcessAxiomUsageEntry> = Vec::new();
+
+    let mut sibling_groups: Vec<&Vec<usize>> =
+        siblings.values().filter(|group| group.len() > 1).collect();
+    sibling_groups.sort_unstable_by_key(|group| group[0]);
+
+    for group in sibling_groups {
+        for &i in group {
+            let axioms =
+                &metamath_data.optimized_data.theorem_data[&theorems[i].label].axiom_dependencies;
+
+            // Only report the sibling needing the fewest axioms
+            let Some(&sibling_i) = group
+                .iter()
+                .filter(|&&j| {
+                    let sibling_axioms = &metamath_data.optimized_data.theorem_data
+                        [&theorems[j].label]
+                        .axiom_dependencies;
+
+                    sibling_axioms.len() < axioms.len()
+                        && search::ordered_list_contained_in_other_ordered_list(
+                            sibling_axioms,
+                            axioms,
+                        )
+                })
+                .min_by_key(|&&j| {
+                    metamath_data.optimized_data.theorem_data[&theorems[j].label]
+                        .axiom_dependencies
+                        .len()
+                })
+            else {
+                continue;
+            };
+
+            let sibling_axioms = &metamath_data.optimized_data.theorem_data
+                [&theorems[sibling_i].label]
+                .axiom_dependencies;
+
+            excess_axiom_usage.push(ExcessAxiomUsageEntry {
+                label: theorems[i].label.clone(),
+                theorem_number: (i + 1) as u32,
+                sibling_label: theorems[sibling_i].label.clone(),
+                sibling_theorem_number: (sibling_i + 1) as u32,
+                excess_axioms: axioms
+                    .iter()
+                    .filter(|ax| sibling_axioms.binary_search(ax).is_err())
+                    .filter_map(|&ax| theorems.get(ax).map(|t| t.label.clone()))
+                    .collect(),
+            });
+        }
+    }
+
+    UsageReport {
+        axiom_usage,
+        definition_usage,
+        excess_axiom_usage,
+    }
+}
+
+impl UsageReport {
+    pub fn to_csv_string(&self, table: UsageReportTable) -> String {
+        let mut csv = String::new();
+
+        // Labels can't contain commas, quotes or whitespace, so no fields need to be escaped
+        match table {
+            UsageReportTable::Axioms | UsageReportTable::Definitions => {
+                let entries = if matches!(table, UsageReportTable::Axioms) {
+                    &self.axiom_usage
+                } else {
+                    &self.definition_usage
+                };
+
+                csv.push_str("label,theoremNumber,dependentTheorems\n");
+                for entry in entries {
+                    writeln!(
+                        csv,
+                        "{},{},{}",
+                        entry.label, entry.theorem_number, entry.dependent_theorems
+                    )
+                    .ok();
+                }
+            }
+            UsageReportTable::ExcessAxioms => {
+                csv.push_str(
+                    "label,theoremNumber,siblingLabel,siblingTheoremNumber,excessAxioms\n",
+                );
+                for entry in &self.excess_axiom_usage {
+                    writeln!(
+                        csv,
+                        "{},{},{},{},{}",
+                        entry.label,
+                        entry.theorem_number,
+                        entry.sibling_label,
+                        entry.sibling_theorem_number,
+                        entry.excess_axioms.join(" ")
+                    )
+                    .ok();
+                }
+            }
+        }
+
+        csv
+    }
+}
@@ -5,16 +5,20 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use tauri::async_runtime::Mutex;
+use tauri::{async_runtime::Mutex, AppHandle};
 
-use crate::{model::FolderData, AppState, Error};
+use crate::{model::FolderData, session, AppState, Error};
 
 pub mod add_to_database;
 pub mod external_window;
 pub mod format;
+pub mod merge_database;
+pub mod mmj2;
 pub mod on_edit;
 pub mod renumber;
+pub mod subproof;
 pub mod unify;
+pub mod work_variables;
 
 pub struct FolderRepresentation {
     file_names: Vec<String>,
@@ -24,6 +28,7 @@ pub struct FolderRepresentation {
 #[tauri::command]
 pub async fn open_folder(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
     folder_path: &str,
 ) -> Result<FolderRepresentation, Error> {
     let mut app_state = state.lock().await;
@@ -37,15 +42,22 @@ pub async fn open_folder(
         file_handles: HashMap::new(),
     });
 
+    session::save_session(&app, &app_state);
+
     Ok(folder)
 }
 
 #[tauri::command]
-pub async fn close_folder(state: tauri::State<'_, Mutex<AppState>>) -> Result<(), ()> {
+pub async fn close_folder(
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), ()> {
     let mut app_state = state.lock().await;
 
     app_state.open_folder_data = None;
 
+    session::save_session(&app, &app_state);
+
     Ok(())
 }
 
@@ -204,6 +216,7 @@ pub async fn delete_folder(
 #[tauri::command]
 pub async fn open_file(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
     relative_path: &str,
 ) -> Result<String, Error> {
     let mut app_state = state.lock().await;
@@ -230,6 +243,8 @@ pub async fn open_file(
         .file_handles
         .insert(relative_path.to_string(), file_handle);
 
+    session::save_session(&app, &app_state);
+
     Ok(file_content)
 }
 
@@ -262,6 +277,7 @@ pub async fn save_file(
 #[tauri::command]
 pub async fn close_file(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
     relative_path: &str,
 ) -> Result<(), Error> {
     let mut app_state = state.lock().await;
@@ -272,6 +288,8 @@ pub async fn close_file(
 
     open_folder_data.file_handles.remove(relative_path);
 
+    session::save_session(&app, &app_state);
+
     Ok(())
 }
 
@@ -295,6 +313,7 @@ pub async fn create_file(
 #[tauri::command]
 pub async fn rename_file(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
     folder_path: &str,
     file_name: &str,
     new_file_name: &str,
@@ -322,12 +341,17 @@ pub async fn rename_file(
             .insert(rename_path_str, file_handle);
     }
 
-    Ok(fs::rename(path, rename_path).is_ok())
+    let renamed = fs::rename(path, rename_path).is_ok();
+
+    session::save_session(&app, &app_state);
+
+    Ok(renamed)
 }
 
 #[tauri::command]
 pub async fn delete_file(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
     relative_path: &str,
 ) -> Result<bool, Error> {
     let mut app_state = state.lock().await;
@@ -341,7 +365,11 @@ pub async fn delete_file(
     let mut path = open_folder_data.path.clone();
     path.push(relative_path);
 
-    Ok(fs::remove_file(path).is_ok())
+    let removed = fs::remove_file(path).is_ok();
+
+    session::save_session(&app, &app_state);
+
+    Ok(removed)
 }
 
 #[tauri::command]
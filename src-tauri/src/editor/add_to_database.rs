@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fs::{self, File},
     io::{BufReader, Read},
 };
@@ -9,19 +10,20 @@ use tauri::async_runtime::Mutex;
 
 use crate::{
     metamath::{
+        export,
         mm_parser::{html_validation, MmParser, StatementProcessed},
         mmp_parser::{
             self, LocateAfterRef, MmpParserStage1, MmpParserStage2, MmpParserStage2Success,
             MmpParserStage3, MmpParserStage3Header, MmpParserStage3Success, MmpParserStage3Theorem,
-            MmpParserStage4, MmpParserStage5, MmpParserStage6,
+            MmpParserStage4, MmpParserStage5, MmpParserStage6, WorksheetTheorems,
         },
     },
     model::{
         DatabaseElement, Header, HeaderContentRepresentation, HeaderPath, Hypothesis, MetamathData,
-        Statement, Theorem,
+        ScopeStatement, Statement, Theorem,
     },
     util::{self, description_parser, StrIterToSpaceSeperatedString},
-    AppState, Error, ProofFormatOption,
+    AppState, Error, ExportFormattingSettings, ProofFormatOption, Settings,
 };
 
 #[derive(Serialize)]
@@ -40,14 +42,25 @@ pub async fn add_to_database_preview(
     text: &str,
     override_proof_format: Option<ProofFormatOption>,
 ) -> Result<AddToDatabasePreviewData, Error> {
-    let app_state = state.lock().await;
-    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
-    let settings = &app_state.settings;
+    let mut app_state = state.lock().await;
+    let settings = app_state.settings.clone();
+    let mm_data = app_state.metamath_data.as_mut().ok_or(Error::NoMmDbError)?;
 
     if database_has_changed(&mm_data.database_path, &mm_data.database_hash)? {
         return Err(Error::DatabaseHasChangedError);
     }
 
+    let blocks = mmp_parser::split_into_theorem_blocks(text);
+
+    if blocks.len() > 1 {
+        let mut new_settings = settings.clone();
+        if let Some(pf) = override_proof_format {
+            new_settings.proof_format = pf;
+        }
+
+        return add_theorem_blocks_preview(mm_data, &new_settings, &blocks);
+    }
+
     let stage_0 = mmp_parser::new(text);
 
     let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
@@ -82,6 +95,7 @@ pub async fn add_to_database_preview(
                 &mm_data.database_path,
                 &mm_data.database_header,
                 stage_3_header,
+                &settings.export_formatting,
             )?;
 
             return Ok(AddToDatabasePreviewData {
@@ -96,6 +110,7 @@ pub async fn add_to_database_preview(
                 &mm_data.database_header,
                 stage_2_success.locate_after,
                 Statement::CommentStatement(stage_3_comment.comment),
+                &settings.export_formatting,
             )?;
 
             return Ok(AddToDatabasePreviewData {
@@ -110,6 +125,7 @@ pub async fn add_to_database_preview(
                 &mm_data.database_header,
                 stage_2_success.locate_after,
                 Statement::ConstantStatement(constants),
+                &settings.export_formatting,
             )?;
 
             return Ok(AddToDatabasePreviewData {
@@ -124,6 +140,7 @@ pub async fn add_to_database_preview(
                 &mm_data.database_header,
                 stage_2_success.locate_after,
                 Statement::VariableStatement(variables),
+                &settings.export_formatting,
             )?;
 
             return Ok(AddToDatabasePreviewData {
@@ -146,6 +163,7 @@ pub async fn add_to_database_preview(
                 &mm_data.database_header,
                 stage_2_success.locate_after,
                 Statement::FloatingHypohesisStatement(floating_hypothesis),
+                &settings.export_formatting,
             )?;
 
             return Ok(AddToDatabasePreviewData {
@@ -163,13 +181,19 @@ pub async fn add_to_database_preview(
         return Err(Error::CantAddToDatabaseError);
     };
 
-    let stage_5 = stage_4_success.next_stage(&stage_2_success, &stage_3_theorem, mm_data, None)?;
-
     let mut new_settings = settings.clone();
     if let Some(pf) = override_proof_format {
         new_settings.proof_format = pf;
     }
 
+    let stage_5 = stage_4_success.next_stage(
+        &stage_2_success,
+        &stage_3_theorem,
+        mm_data,
+        &new_settings,
+        None,
+    )?;
+
     let stage_6 = stage_5.next_stage(&stage_3_theorem, &stage_4_success, mm_data, &new_settings)?;
 
     let locate_after = stage_2_success.locate_after;
@@ -204,6 +228,7 @@ pub async fn add_to_database_preview(
         &mm_data.database_header,
         locate_after,
         Statement::TheoremStatement(theorem),
+        &settings.export_formatting,
     )?;
 
     return Ok(AddToDatabasePreviewData {
@@ -257,11 +282,72 @@ pub async fn add_to_database(
     state: tauri::State<'_, Mutex<AppState>>,
     text: &str,
     override_proof_format: Option<ProofFormatOption>,
-) -> Result<Option<(AddToDatabaseResult, bool)>, Error> {
+) -> Result<Option<(Vec<AddToDatabaseResult>, bool)>, Error> {
     let mut app_state = state.lock().await;
     let mut settings = app_state.settings.clone();
     let mm_data = app_state.metamath_data.as_mut().ok_or(Error::NoMmDbError)?;
 
+    if let Some(pf) = override_proof_format {
+        settings.proof_format = pf;
+    }
+
+    let blocks = mmp_parser::split_into_theorem_blocks(text);
+
+    if blocks.len() == 1 {
+        return Ok(add_mmp_to_database(mm_data, &settings, text)?.map(
+            |(add_to_database_result, is_syntax_axiom)| {
+                (vec![add_to_database_result], is_syntax_axiom)
+            },
+        ));
+    }
+
+    let database_path = mm_data.database_path.clone();
+    let old_file_content = fs::read_to_string(&database_path).or(Err(Error::FileReadError))?;
+
+    let mut added_theorems: Vec<AddedTheorem> = Vec::new();
+
+    let new_database_hash = match add_theorem_blocks(
+        mm_data,
+        &settings,
+        &blocks,
+        Some(&database_path),
+        &mut added_theorems,
+    ) {
+        Ok(Some(Some(new_database_hash))) => new_database_hash,
+        result => {
+            // Roll back the whole batch, if one of the blocks could not be added
+            remove_added_theorems(mm_data, added_theorems)?;
+            fs::write(&database_path, old_file_content).or(Err(Error::FileWriteError))?;
+            return result.map(|_| None);
+        }
+    };
+
+    mm_data.database_hash = new_database_hash;
+
+    let is_syntax_axiom = added_theorems.iter().any(|at| at.is_syntax_axiom);
+
+    if is_syntax_axiom {
+        mm_data.grammar_calculations_done = false;
+    }
+
+    Ok(Some((
+        added_theorems
+            .into_iter()
+            .map(|at| AddToDatabaseResult::NewStatement {
+                content_rep: at.content_rep,
+                header_path: at.header_path,
+                header_content_i: at.header_content_i,
+            })
+            .collect(),
+        is_syntax_axiom,
+    )))
+}
+
+fn add_mmp_to_database(
+    mm_data: &mut MetamathData,
+    settings: &Settings,
+    text: &str,
+) -> Result<Option<(AddToDatabaseResult, bool)>, Error> {
     let stage_0 = mmp_parser::new(text);
 
     let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
@@ -290,6 +376,7 @@ pub async fn add_to_database(
                 &mm_data.database_path,
                 &mut mm_data.database_header,
                 stage_3_header,
+                &settings.export_formatting,
             )?;
 
             mm_data.database_hash = new_database_hash;
@@ -319,6 +406,7 @@ pub async fn add_to_database(
                 &mut mm_data.database_header,
                 stage_2_success.locate_after,
                 statement,
+                &settings.export_formatting,
             )?;
 
             mm_data.database_hash = new_database_hash;
@@ -342,6 +430,7 @@ pub async fn add_to_database(
                 &mut mm_data.database_header,
                 stage_2_success.locate_after,
                 statement,
+                &settings.export_formatting,
             )?;
 
             mm_data.database_hash = new_database_hash;
@@ -371,6 +460,7 @@ pub async fn add_to_database(
                 &mut mm_data.database_header,
                 stage_2_success.locate_after,
                 statement,
+                &settings.export_formatting,
             )?;
 
             mm_data.database_hash = new_database_hash;
@@ -396,6 +486,7 @@ pub async fn add_to_database(
                 &mut mm_data.database_header,
                 stage_2_success.locate_after,
                 statement,
+                &settings.export_formatting,
             )?;
 
             mm_data.database_hash = new_database_hash;
@@ -422,13 +513,10 @@ pub async fn add_to_database(
         return Ok(None);
     };
 
-    let stage_5 = stage_4_success.next_stage(&stage_2_success, &stage_3_theorem, mm_data, None)?;
-
-    if let Some(pf) = override_proof_format {
-        settings.proof_format = pf;
-    }
+    let stage_5 =
+        stage_4_success.next_stage(&stage_2_success, &stage_3_theorem, mm_data, settings, None)?;
 
-    let stage_6 = stage_5.next_stage(&stage_3_theorem, &stage_4_success, mm_data, &settings)?;
+    let stage_6 = stage_5.next_stage(&stage_3_theorem, &stage_4_success, mm_data, settings)?;
 
     let locate_after = stage_2_success.locate_after;
 
@@ -449,11 +537,12 @@ pub async fn add_to_database(
         &mut mm_data.database_header,
         locate_after,
         statement,
+        &settings.export_formatting,
     )?;
 
     mm_data.database_hash = new_database_hash;
 
-    let is_syntax_axiom = mm_data.update_optimized_theorem_data(&theorem_label, &settings)?;
+    let is_syntax_axiom = mm_data.update_optimized_theorem_data(&theorem_label, settings)?;
 
     if is_syntax_axiom {
         mm_data.grammar_calculations_done = false;
@@ -469,6 +558,287 @@ pub async fn add_to_database(
     )))
 }
 
+pub struct AddedTheorem {
+    pub label: String,
+    pub content_rep: HeaderContentRepresentation,
+    pub header_path: HeaderPath,
+    pub header_content_i: usize,
+    pub is_syntax_axiom: bool,
+}
+
+// Runs all parser stages on a single $theorem or $axiom block, returns None if the block is not a complete theorem
+pub fn calc_mmp_theorem<'a>(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    text: &'a str,
+) -> Result<Option<(Theorem, Option<LocateAfterRef<'a>>)>, Error> {
+    calc_mmp_theorem_in_worksheet(mm_data, settings, text, &WorksheetTheorems::default())
+}
+
+// Like calc_mmp_theorem, but the proof can also reference the theorems of the earlier blocks of a worksheet
+pub fn calc_mmp_theorem_in_worksheet<'a>(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    text: &'a str,
+    worksheet_theorems: &WorksheetTheorems,
+) -> Result<Option<(Theorem, Option<LocateAfterRef<'a>>)>, Error> {
+    let stage_0 = mmp_parser::new(text);
+
+    let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage2::Success(stage_2_success) = stage_1_success.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage3::Success(MmpParserStage3Success::Theorem(stage_3_theorem)) =
+        stage_2_success.next_stage_in_worksheet(&stage_1_success, mm_data, worksheet_theorems)?
+    else {
+        return Ok(None);
+    };
+
+    let MmpParserStage4::Success(stage_4_success) =
+        stage_3_theorem.next_stage(&stage_1_success, &stage_2_success, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let stage_5 =
+        stage_4_success.next_stage(&stage_2_success, &stage_3_theorem, mm_data, settings, None)?;
+
+    let stage_6 = stage_5.next_stage(&stage_3_theorem, &stage_4_success, mm_data, settings)?;
+
+    let locate_after = stage_2_success.locate_after;
+
+    Ok(
+        mmp_parser_stages_to_theorem(stage_2_success, stage_3_theorem, stage_5, stage_6, mm_data)
+            .map(|theorem| (theorem, locate_after)),
+    )
+}
+
+// Adds a theorem to the database in memory and, if a file path is given, also to that file.
+// Returns the new hash of the file, if one was written
+pub fn add_theorem(
+    mm_data: &mut MetamathData,
+    settings: &Settings,
+    theorem: Theorem,
+    locate_after: Option<LocateAfterRef>,
+    file_path: Option<&str>,
+) -> Result<(AddedTheorem, Option<String>), Error> {
+    let label = theorem.label.clone();
+
+    let mut statement = Statement::TheoremStatement(theorem);
+
+    let content_rep = statement.to_header_content_representation();
+
+    let (header_path, header_content_i, new_database_hash) = match file_path {
+        Some(file_path) => {
+            let (header_path, header_content_i, new_database_hash) = add_statement(
+                file_path,
+                &mut mm_data.database_header,
+                locate_after,
+                statement,
+                &settings.export_formatting,
+            )?;
+            (header_path, header_content_i, Some(new_database_hash))
+        }
+        None => {
+            let (header_path, header_content_i) = match locate_after {
+                Some(loc_after) => {
+                    let scope_depth =
+                        join_scope_block(&mm_data.database_header, loc_after, &mut statement);
+                    add_statement_locate_after_memory(
+                        &mut mm_data.database_header,
+                        loc_after,
+                        statement,
+                        scope_depth.is_some(),
+                        &mut HeaderPath::new(),
+                    )
+                    .map_err(|_| Error::InternalLogicError)?
+                }
+                None => add_statement_at_end_memory(&mut mm_data.database_header, statement),
+            };
+            (header_path, header_content_i, None)
+        }
+    };
+
+    let mut added_theorem = AddedTheorem {
+        label,
+        content_rep,
+        header_path,
+        header_content_i,
+        is_syntax_axiom: false,
+    };
+
+    match mm_data.update_optimized_theorem_data(&added_theorem.label, settings) {
+        Ok(is_syntax_axiom) => added_theorem.is_syntax_axiom = is_syntax_axiom,
+        Err(error) => {
+            remove_added_theorems(mm_data, vec![added_theorem])?;
+            return Err(error);
+        }
+    }
+
+    Ok((added_theorem, new_database_hash))
+}
+
+// Removes theorems added with add_theorem from the database in memory, in reverse order
+pub fn remove_added_theorems(
+    mm_data: &mut MetamathData,
+    added_theorems: Vec<AddedTheorem>,
+) -> Result<(), Error> {
+    for added_theorem in added_theorems.into_iter().rev() {
+        let theorem_i = mm_data
+            .database_header
+            .theorem_iter()
+            .position(|t| t.label == added_theorem.label)
+            .ok_or(Error::InternalLogicError)?;
+
+        let header = added_theorem
+            .header_path
+            .resolve_mut(&mut mm_data.database_header)
+            .ok_or(Error::InternalLogicError)?;
+
+        if !matches!(
+            header.content.get(added_theorem.header_content_i),
+            Some(Statement::TheoremStatement(theorem)) if theorem.label == added_theorem.label
+        ) {
+            return Err(Error::InternalLogicError);
+        }
+
+        let Statement::TheoremStatement(theorem) =
+            header.content.remove(added_theorem.header_content_i)
+        else {
+            return Err(Error::InternalLogicError);
+        };
+
+        if let Some(proof) = &theorem.proof {
+            for label in Theorem::calc_proof_labels(proof) {
+                if let Some(theorem_data) = mm_data.optimized_data.theorem_data.get_mut(label) {
+                    if let Some(reference_i) = theorem_data
+                        .references
+                        .iter()
+                        .rposition(|&r| r == theorem_i)
+                    {
+                        theorem_data.references.remove(reference_i);
+                    }
+                }
+            }
+        }
+
+        if mm_data
+            .optimized_data
+            .theorem_data
+            .remove(&added_theorem.label)
+            .is_some()
+        {
+            mm_data.optimized_data.theorem_amount -= 1;
+        }
+    }
+
+    Ok(())
+}
+
+// Adds the theorems of all blocks in order, so that later blocks can reference earlier ones. Every added theorem is
+// pushed to added_theorems, so that the caller can remove them again. Returns None if a block is not a complete
+// theorem, otherwise the new hash of the file, if one was written
+fn add_theorem_blocks(
+    mm_data: &mut MetamathData,
+    settings: &Settings,
+    blocks: &[(&str, u32)],
+    file_path: Option<&str>,
+    added_theorems: &mut Vec<AddedTheorem>,
+) -> Result<Option<Option<String>>, Error> {
+    let mut new_database_hash = None;
+
+    for &(block, _) in blocks {
+        let Some((theorem, locate_after)) = calc_mmp_theorem(mm_data, settings, block)? else {
+            return Ok(None);
+        };
+
+        if mm_data
+            .database_header
+            .theorem_iter()
+            .any(|t| t.label == theorem.label)
+        {
+            return Err(Error::LabelAlreadyExistsError);
+        }
+
+        let (added_theorem, hash) =
+            add_theorem(mm_data, settings, theorem, locate_after, file_path)?;
+
+        added_theorems.push(added_theorem);
+        new_database_hash = hash;
+    }
+
+    Ok(Some(new_database_hash))
+}
+
+// Adds all blocks to a copy of the database in memory and exports it the same way the file would be written, so that
+// the open database stays untouched
+fn add_theorem_blocks_preview(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    blocks: &[(&str, u32)],
+) -> Result<AddToDatabasePreviewData, Error> {
+    let old_file_content =
+        fs::read_to_string(&mm_data.database_path).or(Err(Error::FileReadError))?;
+
+    let mut preview_data = MetamathData {
+        database_header: mm_data.database_header.clone(),
+        optimized_data: mm_data.optimized_data.clone(),
+        grammar_calculations_done: mm_data.grammar_calculations_done,
+        database_path: mm_data.database_path.clone(),
+        syntax_typecodes: mm_data.syntax_typecodes.clone(),
+        logical_typecodes: mm_data.logical_typecodes.clone(),
+        ..Default::default()
+    };
+
+    let mut added_theorems: Vec<AddedTheorem> = Vec::new();
+
+    if add_theorem_blocks(
+        &mut preview_data,
+        settings,
+        blocks,
+        None,
+        &mut added_theorems,
+    )?
+    .is_none()
+    {
+        return Err(Error::CantAddToDatabaseError);
+    }
+
+    let (allowed_tags_and_attributes, allowed_css_properties) =
+        html_validation::create_rule_structs();
+    let invalid_html = preview_data
+        .database_header
+        .theorem_iter()
+        .filter(|t| added_theorems.iter().any(|at| at.label == t.label))
+        .any(|t| {
+            !description_parser::parse_description(
+                &t.description,
+                &preview_data.database_header,
+                &allowed_tags_and_attributes,
+                &allowed_css_properties,
+            )
+            .1
+            .is_empty()
+        });
+
+    let new_file_content = export::calc_database_string(
+        &preview_data,
+        &HashMap::new(),
+        &settings.export_formatting,
+        mm_data.original_source.as_ref(),
+    );
+
+    Ok(AddToDatabasePreviewData {
+        old_file_content,
+        new_file_content,
+        invalid_html,
+    })
+}
+
 fn mmp_parser_stages_to_theorem(
     stage_2_success: MmpParserStage2Success,
     stage_3_theorem: MmpParserStage3Theorem,
@@ -516,26 +886,27 @@ fn mmp_parser_stages_to_theorem(
                 })
             })
             .collect::<Option<Vec<Hypothesis>>>()?,
-        assertion: if stage_3_theorem.is_axiom {
-            stage_2_success
+        assertion: match &stage_5
+            .unify_result
+            .iter()
+            .find(|ul| ul.step_name == "qed")?
+            .parse_tree
+        {
+            Some(parse_tree) => parse_tree
+                .to_expression(
+                    &mm_data.optimized_data.symbol_number_mapping,
+                    &mm_data.optimized_data.grammar,
+                )
+                .ok()?,
+            // Syntax axioms can't be parsed by the grammar of the database yet
+            None if stage_3_theorem.is_axiom => stage_2_success
                 .proof_lines
                 .iter()
                 .find(|pl| pl.step_name == "qed")?
                 .expression
                 .split_ascii_whitespace()
-                .fold_to_space_seperated_string()
-        } else {
-            stage_5
-                .unify_result
-                .iter()
-                .find(|ul| ul.step_name == "qed")?
-                .parse_tree
-                .as_ref()?
-                .to_expression(
-                    &mm_data.optimized_data.symbol_number_mapping,
-                    &mm_data.optimized_data.grammar,
-                )
-                .ok()?
+                .fold_to_space_seperated_string(),
+            None => return None,
         },
         proof,
     })
@@ -545,13 +916,23 @@ fn add_statement_preview(
     file_path: &str,
     header: &Header,
     locate_after: Option<LocateAfterRef>,
-    statement: Statement,
+    mut statement: Statement,
+    formatting: &ExportFormattingSettings,
 ) -> Result<(String, String), Error> {
     match locate_after {
         Some(loc_after) => {
-            add_statement_locate_after_file(file_path, header, loc_after, &statement, false)
+            let scope_depth = join_scope_block(header, loc_after, &mut statement);
+            add_statement_locate_after_file(
+                file_path,
+                header,
+                loc_after,
+                &statement,
+                scope_depth,
+                formatting,
+                false,
+            )
         }
-        None => add_statement_at_end_file(file_path, &statement, false),
+        None => add_statement_at_end_file(file_path, &statement, formatting, false),
     }
 }
 
@@ -560,10 +941,13 @@ fn add_statement(
     header: &mut Header,
     locate_after: Option<LocateAfterRef>,
     statement: Statement,
+    formatting: &ExportFormattingSettings,
 ) -> Result<(HeaderPath, usize, String), Error> {
     match locate_after {
-        Some(loc_after) => add_statement_locate_after(file_path, header, loc_after, statement),
-        None => add_statement_at_end(file_path, header, statement),
+        Some(loc_after) => {
+            add_statement_locate_after(file_path, header, loc_after, statement, formatting)
+        }
+        None => add_statement_at_end(file_path, header, statement, formatting),
     }
 }
 
@@ -571,21 +955,145 @@ fn add_statement_locate_after(
     file_path: &str,
     header: &mut Header,
     locate_after: LocateAfterRef,
-    statement: Statement,
+    mut statement: Statement,
+    formatting: &ExportFormattingSettings,
 ) -> Result<(HeaderPath, usize, String), Error> {
-    let (new_database_hash, _) =
-        add_statement_locate_after_file(file_path, header, locate_after, &statement, true)?;
-    let (header_path, header_content_i) =
-        add_statement_locate_after_memory(header, locate_after, statement, &mut HeaderPath::new())
-            .map_err(|_| Error::InternalLogicError)?;
+    let scope_depth = join_scope_block(header, locate_after, &mut statement);
+    let (new_database_hash, _) = add_statement_locate_after_file(
+        file_path,
+        header,
+        locate_after,
+        &statement,
+        scope_depth,
+        formatting,
+        true,
+    )?;
+    let (header_path, header_content_i) = add_statement_locate_after_memory(
+        header,
+        locate_after,
+        statement,
+        scope_depth.is_some(),
+        &mut HeaderPath::new(),
+    )
+    .map_err(|_| Error::InternalLogicError)?;
 
     Ok((header_path, header_content_i, new_database_hash))
 }
 
+// Theorems located after a theorem inside of a ${ $} block are added to that block, if they have the same hypotheses
+// and only use local declarations and distinct variable conditions of the block. In that case the local declarations
+// and distinct variable conditions of the block are copied to the new theorem and the depth of the block is returned.
+// Otherwise the theorem is added after the outermost block
+fn join_scope_block(
+    header: &Header,
+    locate_after: LocateAfterRef,
+    statement: &mut Statement,
+) -> Option<u32> {
+    let (LocateAfterRef::LocateAfter(label), Statement::TheoremStatement(theorem)) =
+        (locate_after, statement)
+    else {
+        return None;
+    };
+
+    let (block_theorem, scope_depth) = find_theorem_and_scope_depth(header, label)?;
+
+    let block_distinct_variable_pairs =
+        util::calc_distinct_variable_pairs(&block_theorem.distincts);
+
+    let joinable = scope_depth != 0
+        && theorem.hypotheses.len() == block_theorem.hypotheses.len()
+        && theorem
+            .hypotheses
+            .iter()
+            .zip(&block_theorem.hypotheses)
+            .all(|(hyp, block_hyp)| {
+                hyp.label == block_hyp.label && hyp.expression == block_hyp.expression
+            })
+        && util::calc_distinct_variable_pairs(&theorem.distincts)
+            .is_subset(&block_distinct_variable_pairs)
+        && theorem.temp_variables.iter().flatten().all(|var| {
+            block_theorem
+                .temp_variables
+                .iter()
+                .flatten()
+                .any(|block_var| block_var.symbol == var.symbol)
+        })
+        && theorem.temp_floating_hypotheses.iter().all(|fh| {
+            block_theorem
+                .temp_floating_hypotheses
+                .iter()
+                .any(|block_fh| block_fh.label == fh.label)
+        });
+
+    if !joinable {
+        return None;
+    }
+
+    theorem.temp_variables = block_theorem.temp_variables.clone();
+    theorem.temp_floating_hypotheses = block_theorem.temp_floating_hypotheses.clone();
+    theorem.distincts = block_theorem.distincts.clone();
+
+    Some(scope_depth)
+}
+
+// The scope depth is tracked over the whole database, since ${ $} blocks can span header comments
+fn find_theorem_and_scope_depth<'a>(header: &'a Header, label: &str) -> Option<(&'a Theorem, u32)> {
+    let mut scope_depth: u32 = 0;
+
+    for database_element in header.iter() {
+        match database_element {
+            DatabaseElement::Statement(Statement::ScopeStatement(ScopeStatement::OpeningScope)) => {
+                scope_depth += 1
+            }
+            DatabaseElement::Statement(Statement::ScopeStatement(ScopeStatement::ClosingScope)) => {
+                scope_depth = scope_depth.saturating_sub(1)
+            }
+            DatabaseElement::Statement(Statement::TheoremStatement(theorem))
+                if theorem.label == label =>
+            {
+                return Some((theorem, scope_depth))
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// Returns the index after the ${ $} block that the statement at the given index is in, or the index after the
+// statement if it is not in a block
+fn end_of_scope_block(content: &[Statement], statement_i: usize) -> usize {
+    let mut scope_depth: u32 = 0;
+
+    for statement in &content[..=statement_i] {
+        match statement {
+            Statement::ScopeStatement(ScopeStatement::OpeningScope) => scope_depth += 1,
+            Statement::ScopeStatement(ScopeStatement::ClosingScope) => {
+                scope_depth = scope_depth.saturating_sub(1)
+            }
+            _ => {}
+        }
+    }
+
+    let mut end_i = statement_i + 1;
+
+    while scope_depth != 0 && end_i < content.len() {
+        match content[end_i] {
+            Statement::ScopeStatement(ScopeStatement::OpeningScope) => scope_depth += 1,
+            Statement::ScopeStatement(ScopeStatement::ClosingScope) => scope_depth -= 1,
+            _ => {}
+        }
+        end_i += 1;
+    }
+
+    end_i
+}
+
 fn add_statement_locate_after_memory(
     header: &mut Header,
     locate_after: LocateAfterRef,
     mut statement: Statement,
+    in_scope_block: bool,
     header_path: &mut HeaderPath,
 ) -> Result<(HeaderPath, usize), Statement> {
     match locate_after {
@@ -639,8 +1147,13 @@ fn add_statement_locate_after_memory(
             for i in 0..header.content.len() {
                 if let Some(Statement::TheoremStatement(theorem)) = header.content.get(i) {
                     if theorem.label == *label_str {
-                        header.content.insert(i + 1, statement);
-                        return Ok((header_path.clone(), i + 1));
+                        let insert_i = if in_scope_block {
+                            i + 1
+                        } else {
+                            end_of_scope_block(&header.content, i)
+                        };
+                        header.content.insert(insert_i, statement);
+                        return Ok((header_path.clone(), insert_i));
                     }
                 } else if let Some(Statement::FloatingHypohesisStatement(floating_hypothesis)) =
                     header.content.get(i)
@@ -660,6 +1173,7 @@ fn add_statement_locate_after_memory(
             subheader,
             locate_after,
             statement,
+            in_scope_block,
             header_path,
         ) {
             Ok(hp) => return Ok(hp),
@@ -671,11 +1185,14 @@ fn add_statement_locate_after_memory(
     Err(statement)
 }
 
+// If scope_depth is given, the statement is added right after the located statement, inside of its ${ $} block
 fn add_statement_locate_after_file(
     file_path: &str,
     header: &Header,
     locate_after: LocateAfterRef,
     statement: &Statement,
+    scope_depth: Option<u32>,
+    formatting: &ExportFormattingSettings,
     write_to_file: bool,
 ) -> Result<(String, String), Error> {
     let mut mm_parser = MmParser::new(file_path, None, None)?;
@@ -731,25 +1248,22 @@ fn add_statement_locate_after_file(
                         break;
                     }
                 },
+                Statement::ScopeStatement(scope_statement) => {
+                    advance_past_scope_statement(&mut mm_parser, scope_statement)?
+                }
             },
         }
     }
 
-    while mm_parser.get_scope() != 0 {
-        match mm_parser.process_next_statement()? {
-            Some(StatementProcessed::ClosingScopeStatement)
-            | Some(StatementProcessed::OpeningScopeStatement)
-            | Some(StatementProcessed::VariableStatement)
-            | Some(StatementProcessed::FloatingHypothesisStatement)
-            | Some(StatementProcessed::DistinctVariableStatement)
-            | Some(StatementProcessed::EssentialHypothesisStatement)
-            | Some(StatementProcessed::CommentStatement) => {}
-            Some(StatementProcessed::TheoremStatement)
-            | Some(StatementProcessed::HeaderStatement) => {
-                return Err(Error::AddingToInnerScopeError)
+    if scope_depth.is_none() {
+        while mm_parser.get_scope() != 0 {
+            match mm_parser.process_next_statement()? {
+                Some(StatementProcessed::HeaderStatement) => {
+                    return Err(Error::AddingToInnerScopeError)
+                }
+                Some(_) => {}
+                None => return Err(Error::UnclosedScopeError),
             }
-            // Should never happen
-            Some(StatementProcessed::ConstantStatement) | None => {}
         }
     }
 
@@ -761,15 +1275,26 @@ fn add_statement_locate_after_file(
         Some(file_content.clone())
     };
 
-    file_content.insert_str(next_token_i, "\n\n");
-    statement.insert_mm_string(
-        &mut file_content,
-        if matches!(locate_after, LocateAfterRef::LocateAfterStart) {
-            next_token_i
-        } else {
-            next_token_i + 2
-        },
-    );
+    match scope_depth {
+        Some(scope_depth) => {
+            let mut mm_string = String::from("\n");
+            statement.write_mm_string_in_scope(&mut mm_string, formatting, scope_depth);
+            file_content.insert_str(next_token_i, &mm_string);
+        }
+        None => {
+            let mut mm_string = String::new();
+            statement.write_mm_string_formatted(&mut mm_string, formatting);
+            file_content.insert_str(next_token_i, "\n\n");
+            file_content.insert_str(
+                if matches!(locate_after, LocateAfterRef::LocateAfterStart) {
+                    next_token_i
+                } else {
+                    next_token_i + 2
+                },
+                &mm_string,
+            );
+        }
+    }
 
     if write_to_file {
         let new_database_hash = util::str_to_hash_string(&file_content);
@@ -781,12 +1306,54 @@ fn add_statement_locate_after_file(
     }
 }
 
+// The scope statements of the database have to match the file one to one, otherwise the file has changed
+fn advance_past_scope_statement(
+    mm_parser: &mut MmParser,
+    scope_statement: &ScopeStatement,
+) -> Result<(), Error> {
+    let statement_processed = mm_parser
+        .process_next_statement()?
+        .ok_or(Error::DatabaseHasChangedError)?;
+
+    if !matches!(
+        (scope_statement, statement_processed),
+        (
+            ScopeStatement::OpeningScope,
+            StatementProcessed::OpeningScopeStatement
+        ) | (
+            ScopeStatement::ClosingScope,
+            StatementProcessed::ClosingScopeStatement
+        ) | (
+            ScopeStatement::Comment(_),
+            StatementProcessed::CommentStatement
+        ) | (
+            ScopeStatement::Variables(_),
+            StatementProcessed::VariableStatement
+        ) | (
+            ScopeStatement::FloatingHypothesis(_),
+            StatementProcessed::FloatingHypothesisStatement
+        ) | (
+            ScopeStatement::EssentialHypothesis(_),
+            StatementProcessed::EssentialHypothesisStatement
+        ) | (
+            ScopeStatement::DistinctVariables(_),
+            StatementProcessed::DistinctVariableStatement
+        )
+    ) {
+        return Err(Error::DatabaseHasChangedError);
+    }
+
+    Ok(())
+}
+
 fn add_statement_at_end(
     file_path: &str,
     header: &mut Header,
     statement: Statement,
+    formatting: &ExportFormattingSettings,
 ) -> Result<(HeaderPath, usize, String), Error> {
-    let (new_database_hash, _) = add_statement_at_end_file(file_path, &statement, true)?;
+    let (new_database_hash, _) =
+        add_statement_at_end_file(file_path, &statement, formatting, true)?;
     let (header_path, header_content_i) = add_statement_at_end_memory(header, statement);
     Ok((header_path, header_content_i, new_database_hash))
 }
@@ -809,6 +1376,7 @@ fn add_statement_at_end_memory(header: &mut Header, statement: Statement) -> (He
 fn add_statement_at_end_file(
     file_path: &str,
     statement: &Statement,
+    formatting: &ExportFormattingSettings,
     write_to_file: bool,
 ) -> Result<(String, String), Error> {
     let mut file_content = fs::read_to_string(file_path).or(Err(Error::FileReadError))?;
@@ -834,7 +1402,7 @@ fn add_statement_at_end_file(
         file_content.push_str("\n\n");
     }
 
-    statement.write_mm_string(&mut file_content);
+    statement.write_mm_string_formatted(&mut file_content, formatting);
     file_content.push_str("\n");
 
     if write_to_file {
@@ -852,16 +1420,19 @@ fn add_header_preview(
     file_path: &str,
     header: &Header,
     stage_3_header: MmpParserStage3Header,
+    formatting: &ExportFormattingSettings,
 ) -> Result<(String, String), Error> {
-    add_header_file(file_path, header, &stage_3_header, false)
+    add_header_file(file_path, header, &stage_3_header, formatting, false)
 }
 
 fn add_header(
     file_path: &str,
     header: &mut Header,
     stage_3_header: MmpParserStage3Header,
+    formatting: &ExportFormattingSettings,
 ) -> Result<String, Error> {
-    let (new_database_hash, _) = add_header_file(file_path, header, &stage_3_header, true)?;
+    let (new_database_hash, _) =
+        add_header_file(file_path, header, &stage_3_header, formatting, true)?;
     add_header_memory(header, stage_3_header)?;
 
     Ok(new_database_hash)
@@ -871,6 +1442,7 @@ fn add_header_file(
     file_path: &str,
     header: &Header,
     stage_3_header: &MmpParserStage3Header,
+    formatting: &ExportFormattingSettings,
     write_to_file: bool,
 ) -> Result<(String, String), Error> {
     let mut mm_parser = MmParser::new(file_path, None, None)?;
@@ -938,6 +1510,9 @@ fn add_header_file(
                         break;
                     }
                 },
+                Statement::ScopeStatement(scope_statement) => {
+                    advance_past_scope_statement(&mut mm_parser, scope_statement)?
+                }
             },
         }
     }
@@ -975,6 +1550,7 @@ fn add_header_file(
         &stage_3_header.description,
         &mut file_content,
         next_token_i + 2,
+        formatting,
     )?;
 
     if write_to_file {
@@ -1,17 +1,61 @@
+use tauri::async_runtime::Mutex;
+
 use crate::{
     metamath::mmp_parser::{
         self, calc_indention::calc_indention, LocateAfterRef, MmpLabel, MmpParserStage1,
         MmpParserStage2, MmpStatement,
     },
-    util, Error,
+    model::MetamathData,
+    util::{self, unicode_input},
+    AppState, Error,
 };
 
 #[tauri::command]
-pub async fn format(text: &str) -> Result<Option<String>, Error> {
-    format_mmp_file(text)
+pub async fn format(
+    state: tauri::State<'_, Mutex<AppState>>,
+    text: &str,
+) -> Result<Option<String>, Error> {
+    let app_state = state.lock().await;
+
+    format_mmp_file_with_mm_data(text, app_state.metamath_data.as_ref())
 }
 
 pub fn format_mmp_file(text: &str) -> Result<Option<String>, Error> {
+    format_mmp_file_with_mm_data(text, None)
+}
+
+// If mm_data is given and the file contains $unicodeinput, the expressions of proof lines are rewritten into space
+// separated metamath tokens. Worksheets are formatted block by block, blocks that can't be formatted are kept as they
+// are
+fn format_mmp_file_with_mm_data(
+    text: &str,
+    mm_data: Option<&MetamathData>,
+) -> Result<Option<String>, Error> {
+    let blocks = mmp_parser::split_into_theorem_blocks(text);
+
+    if blocks.len() == 1 {
+        return format_mmp_block(text, mm_data);
+    }
+
+    let mut result_text = String::new();
+    let mut any_block_formatted = false;
+
+    for (block, _) in blocks {
+        let block_text = match format_mmp_block(block, mm_data)? {
+            Some(formatted_block) => {
+                any_block_formatted = true;
+                formatted_block
+            }
+            None => block.to_string(),
+        };
+
+        mmp_parser::push_theorem_block(&mut result_text, &block_text);
+    }
+
+    Ok(any_block_formatted.then_some(result_text))
+}
+
+fn format_mmp_block(text: &str, mm_data: Option<&MetamathData>) -> Result<Option<String>, Error> {
     let stage_0 = mmp_parser::new(text);
 
     let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
@@ -22,6 +66,9 @@ pub fn format_mmp_file(text: &str) -> Result<Option<String>, Error> {
         return Ok(None);
     };
 
+    let unicode_input = mm_data
+        .filter(|mm_data| stage_2_success.unicode_input && mm_data.grammar_calculations_done);
+
     let mut result_text = String::new();
 
     // for _ in 0..(stage_1_success.number_of_lines_before_first_statement - 1) {
@@ -112,6 +159,15 @@ pub fn format_mmp_file(text: &str) -> Result<Option<String>, Error> {
             MmpStatement::AllowIncomplete => {
                 result_text.push_str("$allowincomplete");
             }
+            MmpStatement::AllowProofModification => {
+                result_text.push_str("$allowproofmodification");
+            }
+            MmpStatement::DeductionMode => {
+                result_text.push_str("$deductionmode");
+            }
+            MmpStatement::UnicodeInput => {
+                result_text.push_str("$unicodeinput");
+            }
             MmpStatement::LocateAfter => {
                 let locate_after = stage_2_success
                     .locate_after
@@ -176,7 +232,25 @@ pub fn format_mmp_file(text: &str) -> Result<Option<String>, Error> {
                     result_text.push_str(util::spaces(indention - 1 + 20 - prefix_len));
                 }
 
-                result_text.push_str(&util::str_to_space_seperated_string(proof_line.expression));
+                let mut expression = None;
+
+                if let Some(mm_data) = unicode_input {
+                    if mm_data
+                        .expression_to_parse_tree(proof_line.expression)
+                        .is_err()
+                    {
+                        expression = unicode_input::unicode_expression_to_tokens(
+                            proof_line.expression,
+                            mm_data,
+                        )?;
+                    }
+                }
+
+                result_text.push_str(
+                    &expression.unwrap_or_else(|| {
+                        util::str_to_space_seperated_string(proof_line.expression)
+                    }),
+                );
             }
         }
 
@@ -0,0 +1,315 @@
+use std::{collections::HashMap, fs};
+
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    editor::add_to_database::{self, AddToDatabaseResult, AddedTheorem},
+    metamath::{export, mm_parser, mmp_parser::LocateAfterRef},
+    model::{MetamathData, Theorem},
+    util, AppState, Error, Settings,
+};
+
+// The proof and description a theorem of the open database had before it was replaced by a merge
+struct ReplacedTheorem {
+    label: String,
+    old_proof: Option<String>,
+    old_description: String,
+}
+
+// Merges the selected theorems and axioms of another version of the database. Theorems that don't exist in the open
+// database yet are added in the order of the other database, each one after the last theorem it depends on, and after
+// the theorem that precedes it in the other database if that one exists in the open database. Theorems that already
+// exist keep their place, only their proof and description are replaced. Replacing the proof of a theorem marked with
+// "(Proof modification is discouraged.)" needs explicit consent through allow_proof_modification
+#[tauri::command]
+pub async fn merge_database_changes(
+    state: tauri::State<'_, Mutex<AppState>>,
+    mm_file_path: &str,
+    labels: Vec<String>,
+    allow_proof_modification: bool,
+) -> Result<(Vec<AddToDatabaseResult>, bool), Error> {
+    let mut app_state = state.lock().await;
+    let database_id = app_state.id_manager.get_next_id();
+    let settings = app_state.settings.clone();
+    drop(app_state);
+
+    let other_metamath_data = mm_parser::load_metamath_data(mm_file_path, database_id, &settings)?;
+
+    let mut app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_mut().ok_or(Error::NoMmDbError)?;
+
+    if add_to_database::database_has_changed(&mm_data.database_path, &mm_data.database_hash)? {
+        return Err(Error::DatabaseHasChangedError);
+    }
+
+    let other_theorem_labels: Vec<&str> = other_metamath_data
+        .database_header
+        .theorem_iter()
+        .map(|t| &*t.label)
+        .collect();
+
+    if labels
+        .iter()
+        .any(|label| !other_theorem_labels.contains(&label.as_str()))
+    {
+        return Err(Error::NotFoundError);
+    }
+
+    let database_path = mm_data.database_path.clone();
+    let old_file_content = fs::read_to_string(&database_path).or(Err(Error::FileReadError))?;
+
+    let mut added_theorems: Vec<AddedTheorem> = Vec::new();
+    let mut replaced_theorems: Vec<ReplacedTheorem> = Vec::new();
+
+    let new_database_hash = match merge_theorems(
+        mm_data,
+        &settings,
+        &other_metamath_data,
+        &labels,
+        allow_proof_modification,
+        &mut added_theorems,
+        &mut replaced_theorems,
+    ) {
+        Ok(new_database_hash) => new_database_hash,
+        Err(error) => {
+            add_to_database::remove_added_theorems(mm_data, added_theorems)?;
+            for replaced_theorem in replaced_theorems.into_iter().rev() {
+                mm_data.replace_theorem_proof_and_description(
+                    &replaced_theorem.label,
+                    replaced_theorem.old_proof,
+                    replaced_theorem.old_description,
+                )?;
+            }
+            fs::write(&database_path, old_file_content).or(Err(Error::FileWriteError))?;
+            return Err(error);
+        }
+    };
+
+    if let Some(new_database_hash) = new_database_hash {
+        mm_data.database_hash = new_database_hash;
+    }
+
+    let is_syntax_axiom = added_theorems.iter().any(|at| at.is_syntax_axiom);
+
+    if is_syntax_axiom {
+        mm_data.grammar_calculations_done = false;
+    }
+
+    Ok((
+        added_theorems
+            .into_iter()
+            .map(|at| AddToDatabaseResult::NewStatement {
+                content_rep: at.content_rep,
+                header_path: at.header_path,
+                header_content_i: at.header_content_i,
+            })
+            .collect(),
+        is_syntax_axiom,
+    ))
+}
+
+// Every added theorem is pushed to added_theorems and every replaced theorem to replaced_theorems, so that the caller
+// can undo them. Returns the new hash of the file, if a theorem was added or replaced
+fn merge_theorems(
+    mm_data: &mut MetamathData,
+    settings: &Settings,
+    other_metamath_data: &MetamathData,
+    labels: &[String],
+    allow_proof_modification: bool,
+    added_theorems: &mut Vec<AddedTheorem>,
+    replaced_theorems: &mut Vec<ReplacedTheorem>,
+) -> Result<Option<String>, Error> {
+    let mut new_database_hash = None;
+
+    let file_path = mm_data.database_path.clone();
+
+    let other_theorem_labels: Vec<&str> = other_metamath_data
+        .database_header
+        .theorem_iter()
+        .map(|t| &*t.label)
+        .collect();
+
+    for (other_theorem_i, theorem) in other_metamath_data
+        .database_header
+        .theorem_iter()
+        .enumerate()
+        .filter(|(_, t)| labels.contains(&t.label))
+    {
+        let theorem_indices: HashMap<&str, usize> = mm_data
+            .database_header
+            .theorem_iter()
+            .enumerate()
+            .map(|(i, t)| (&*t.label, i))
+            .collect();
+
+        if let Some(&theorem_i) = theorem_indices.get(&*theorem.label) {
+            check_theorem_replacement(
+                mm_data,
+                theorem,
+                theorem_i,
+                &theorem_indices,
+                allow_proof_modification,
+            )?;
+
+            let (old_proof, old_description) = mm_data.replace_theorem_proof_and_description(
+                &theorem.label,
+                theorem.proof.clone(),
+                theorem.description.clone(),
+            )?;
+
+            replaced_theorems.push(ReplacedTheorem {
+                label: theorem.label.clone(),
+                old_proof,
+                old_description,
+            });
+            continue;
+        }
+
+        // Besides the label of the theorem, the labels of its hypotheses must not be taken by any label or math
+        // symbol of the open database either
+        let mut new_labels: Vec<&str> = vec![&theorem.label];
+        new_labels.extend(theorem.hypotheses.iter().map(|hyp| &*hyp.label));
+        new_labels.extend(theorem.temp_floating_hypotheses.iter().map(|fh| &*fh.label));
+
+        if !mm_data.symbols_not_already_taken(&new_labels) {
+            return Err(Error::LabelAlreadyExistsError);
+        }
+
+        let preceding_theorem_i = other_theorem_labels[..other_theorem_i]
+            .iter()
+            .rev()
+            .find_map(|label| theorem_indices.get(label).copied());
+
+        let mut locate_after_i = preceding_theorem_i;
+
+        for dependency_i in calc_dependency_indices(theorem, mm_data, &theorem_indices)? {
+            if locate_after_i.is_none_or(|i| i < dependency_i) {
+                locate_after_i = Some(dependency_i);
+            }
+        }
+
+        let locate_after_label = locate_after_i
+            .map(|i| {
+                mm_data
+                    .database_header
+                    .theorem_iter()
+                    .nth(i)
+                    .map(|t| t.label.clone())
+                    .ok_or(Error::InternalLogicError)
+            })
+            .transpose()?;
+
+        let (added_theorem, hash) = add_to_database::add_theorem(
+            mm_data,
+            settings,
+            theorem.clone(),
+            locate_after_label
+                .as_deref()
+                .map(LocateAfterRef::LocateAfter),
+            Some(&file_path),
+        )?;
+
+        added_theorems.push(added_theorem);
+        new_database_hash = hash;
+    }
+
+    // The replaced theorems are changed in memory only, so the file is rewritten from the database. Statements that
+    // were not changed since the database was loaded keep their original text
+    if !replaced_theorems.is_empty() {
+        let database_string = export::calc_database_string(
+            mm_data,
+            &HashMap::new(),
+            &settings.export_formatting,
+            mm_data.original_source.as_ref(),
+        );
+
+        fs::write(&file_path, &database_string).or(Err(Error::FileWriteError))?;
+        new_database_hash = Some(util::str_to_hash_string(&database_string));
+    }
+
+    Ok(new_database_hash)
+}
+
+// Only the proof and description of a theorem that exists in the open database can be replaced. The statement itself
+// has to stay the same, and the new proof may only reference theorems that come before it
+fn check_theorem_replacement(
+    mm_data: &MetamathData,
+    theorem: &Theorem,
+    theorem_i: usize,
+    theorem_indices: &HashMap<&str, usize>,
+    allow_proof_modification: bool,
+) -> Result<(), Error> {
+    let old_theorem = mm_data
+        .database_header
+        .theorem_iter()
+        .nth(theorem_i)
+        .ok_or(Error::InternalLogicError)?;
+
+    if old_theorem.assertion != theorem.assertion
+        || old_theorem.hypotheses.len() != theorem.hypotheses.len()
+        || old_theorem
+            .hypotheses
+            .iter()
+            .zip(theorem.hypotheses.iter())
+            .any(|(old_hyp, hyp)| {
+                old_hyp.label != hyp.label || old_hyp.expression != hyp.expression
+            })
+        || old_theorem.distincts != theorem.distincts
+    {
+        return Err(Error::MergeStatementChangedError);
+    }
+
+    if !allow_proof_modification
+        && old_theorem.proof != theorem.proof
+        && mm_data
+            .optimized_data
+            .theorem_data
+            .get(&theorem.label)
+            .is_some_and(|theorem_data| theorem_data.is_proof_modification_discouraged)
+    {
+        return Err(Error::MergeProofModificationDiscouragedError);
+    }
+
+    if calc_dependency_indices(theorem, mm_data, theorem_indices)?
+        .into_iter()
+        .any(|dependency_i| dependency_i >= theorem_i)
+    {
+        return Err(Error::MergeDependencyOrderError);
+    }
+
+    Ok(())
+}
+
+// Returns the indices of all theorems in the open database the proof of the theorem references. Fails if the proof
+// references a label that doesn't exist in the open database
+fn calc_dependency_indices(
+    theorem: &Theorem,
+    mm_data: &MetamathData,
+    theorem_indices: &HashMap<&str, usize>,
+) -> Result<Vec<usize>, Error> {
+    let Some(proof) = &theorem.proof else {
+        return Ok(Vec::new());
+    };
+
+    let mut dependency_indices = Vec::new();
+
+    for label in Theorem::calc_proof_labels(proof) {
+        if let Some(&theorem_i) = theorem_indices.get(label) {
+            dependency_indices.push(theorem_i);
+        } else if label != "?"
+            && !theorem.hypotheses.iter().any(|hyp| hyp.label == label)
+            && !theorem
+                .temp_floating_hypotheses
+                .iter()
+                .any(|fh| fh.label == label)
+            && !mm_data
+                .database_header
+                .floating_hypohesis_iter()
+                .any(|fh| fh.label == label)
+        {
+            return Err(Error::MergeMissingDependencyError);
+        }
+    }
+
+    Ok(dependency_indices)
+}
@@ -0,0 +1,135 @@
+use crate::{
+    editor::format,
+    metamath::mmp_parser::{self, MmpParserStage1},
+    Error,
+};
+
+// Converts an mmj2 proof worksheet into an mmp file
+#[tauri::command]
+pub async fn import_mmj2_worksheet(text: &str) -> Result<String, Error> {
+    let statements = split_into_statements(text)?;
+
+    let mut statement_iter = statements.into_iter();
+
+    let header = statement_iter.next().ok_or(Error::Mmj2MissingHeaderError)?;
+
+    let mut header_tokens = header.split_ascii_whitespace();
+
+    if header_tokens.next() != Some("$(")
+        || header_tokens.next() != Some("<MM>")
+        || header_tokens.next() != Some("<PROOF_ASST>")
+    {
+        return Err(Error::Mmj2MissingHeaderError);
+    }
+
+    let mut theorem_label = None;
+    let mut locate_after = None;
+
+    for token in header_tokens {
+        if let Some(label) = token.strip_prefix("THEOREM=") {
+            theorem_label = Some(label);
+        } else if let Some(label) = token.strip_prefix("LOC_AFTER=") {
+            locate_after = Some(label);
+        }
+    }
+
+    let theorem_label = theorem_label
+        .filter(|label| !label.is_empty())
+        .ok_or(Error::Mmj2MissingHeaderError)?;
+
+    let mut result_text = String::new();
+
+    result_text.push_str("$theorem ");
+    result_text.push_str(theorem_label);
+    result_text.push_str("\n\n");
+
+    if let Some(locate_after) = locate_after.filter(|label| !label.is_empty() && *label != "?") {
+        result_text.push_str("$locateafter ");
+        result_text.push_str(locate_after);
+        result_text.push_str("\n\n");
+    }
+
+    for statement in statement_iter {
+        let trailing_whitespace = &statement[statement.trim_end().len()..];
+
+        match statement.split_ascii_whitespace().next() {
+            Some("$)") => break,
+            Some(token) if token.starts_with('!') && !token.contains(':') => {
+                result_text.push('*');
+                result_text.push_str(statement[1..].trim());
+                result_text.push_str(trailing_whitespace);
+            }
+            Some("$=") => {
+                let proof = statement.trim_end();
+                result_text.push_str(proof.strip_suffix("$.").unwrap_or(proof).trim_end());
+                result_text.push_str(trailing_whitespace);
+            }
+            _ => result_text.push_str(statement),
+        }
+    }
+
+    Ok(format::format_mmp_file(&result_text)?.unwrap_or(result_text))
+}
+
+// Converts an mmp file into an mmj2 proof worksheet. An mmj2 worksheet holds a single theorem, so worksheets with
+// several theorem blocks can't be converted
+#[tauri::command]
+pub async fn export_mmj2_worksheet(text: &str) -> Result<String, Error> {
+    if mmp_parser::split_into_theorem_blocks(text).len() > 1 {
+        return Err(Error::Mmj2UnsupportedStatementError);
+    }
+
+    let statements = split_into_statements(text)?;
+
+    let mut theorem_label = None;
+    let mut locate_after = None;
+    let mut body = String::new();
+
+    for statement in statements {
+        let mut token_iter = statement.split_ascii_whitespace();
+
+        match token_iter.next() {
+            Some("$theorem") => theorem_label = token_iter.next(),
+            Some("$locateafter") => locate_after = token_iter.next(),
+            Some("$=") => {
+                let proof = statement.trim_end();
+                body.push_str(proof);
+                if !proof.ends_with("$.") {
+                    body.push_str(" $.");
+                }
+                body.push_str(&statement[proof.len()..]);
+            }
+            // mmj2 has no advanced unification marker
+            Some(token) if token.starts_with('!') && token.contains(':') => {
+                body.push_str(&statement[1..]);
+            }
+            Some(token) if token.starts_with('$') && token != "$d" => {
+                return Err(Error::Mmj2UnsupportedStatementError);
+            }
+            _ => body.push_str(statement),
+        }
+    }
+
+    let theorem_label = theorem_label.ok_or(Error::Mmj2UnsupportedStatementError)?;
+
+    let mut result_text = String::new();
+
+    result_text.push_str("$( <MM> <PROOF_ASST> THEOREM=");
+    result_text.push_str(theorem_label);
+    result_text.push_str("  LOC_AFTER=");
+    result_text.push_str(locate_after.unwrap_or("?"));
+    result_text.push_str("\n\n");
+
+    result_text.push_str(body.trim_end());
+    result_text.push_str("\n\n$)\n");
+
+    Ok(result_text)
+}
+
+// Both formats start a new statement at every line that doesn't start with whitespace
+fn split_into_statements(text: &str) -> Result<Vec<&str>, Error> {
+    match mmp_parser::new(text).next_stage()? {
+        MmpParserStage1::Success(stage_1_success) => Ok(stage_1_success.statements),
+        MmpParserStage1::Fail(fail) => Err(fail.error.error_type),
+    }
+}
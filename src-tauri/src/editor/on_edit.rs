@@ -1,13 +1,15 @@
-use std::sync::Arc;
+use std::{fmt, fs, sync::Arc};
 
 use crate::{
+    analysis::proof_lint,
+    editor::add_to_database,
     metamath::{
-        mm_parser::html_validation,
+        mm_parser::{self, html_validation},
         mmp_parser::{
-            self, calc_indention::calc_indention, MmpParserStage1, MmpParserStage2,
+            self, calc_indention::calc_indention, stage_3, MmpParserStage1, MmpParserStage2,
             MmpParserStage2Success, MmpParserStage3, MmpParserStage3Success,
             MmpParserStage3Theorem, MmpParserStage4, MmpParserStage5, MmpParserStage6,
-            ProofLineStatus,
+            MmpStatement, ProofLineStatus, WorksheetTheorems,
         },
     },
     model::{
@@ -24,6 +26,7 @@ use tauri::async_runtime::Mutex;
 pub struct OnEditData {
     pub page_data: Option<DatabaseElementPageData>,
     pub errors: Vec<DetailedError>,
+    pub warnings: Vec<DetailedError>,
 }
 
 pub struct DetailedError {
@@ -32,6 +35,21 @@ pub struct DetailedError {
     pub start_column: u32,
     pub end_line_number: u32,
     pub end_column: u32,
+    pub explanation: Option<Box<UnificationExplanation>>,
+    // Terminals and typecodes that would have been accepted at the position of an expression parse error
+    pub expected_symbols: Option<Vec<String>>,
+}
+
+// Explains why a step can't be proven by its step ref
+#[derive(Debug)]
+pub struct UnificationExplanation {
+    // Hypothesis or assertion of the referenced theorem that the step doesn't match, or the violated $d statement
+    pub pattern: String,
+    // Variables of the referenced theorem and their substitutions computed so far
+    pub substitution: Vec<(String, String)>,
+    // The first subterm of the pattern and of the step where the parse trees clash
+    pub clash: Option<(String, String)>,
+    pub distinct_variable_pair: Option<(String, String)>,
 }
 
 #[tauri::command]
@@ -51,8 +69,101 @@ pub async fn on_edit(
 
     let app_state = state.lock().await;
     let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
-    let settings = &app_state.settings;
 
+    let blocks = mmp_parser::split_into_theorem_blocks(text);
+
+    if blocks.len() == 1 {
+        return calc_on_edit_data(
+            mm_data,
+            &app_state.settings,
+            text,
+            &WorksheetTheorems::default(),
+            &stop,
+        );
+    }
+
+    calc_on_edit_data_blocks(mm_data, &app_state.settings, &blocks, &stop)
+}
+
+// Processes the blocks of a worksheet in order. The theorems of all blocks but the last one are collected in a
+// worksheet overlay, so that later blocks can reference them. The page data is the one of the last block
+fn calc_on_edit_data_blocks(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    blocks: &[(&str, u32)],
+    stop: &Arc<std::sync::Mutex<bool>>,
+) -> Result<OnEditData, Error> {
+    let mut page_data = None;
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut worksheet_theorems = WorksheetTheorems::default();
+
+    for (i, &(block, lines_before)) in blocks.iter().enumerate() {
+        let block_on_edit_data =
+            calc_on_edit_data(mm_data, settings, block, &worksheet_theorems, stop)?;
+
+        let offset_lines = |mut error: DetailedError| {
+            error.start_line_number += lines_before;
+            error.end_line_number += lines_before;
+            error
+        };
+
+        page_data = block_on_edit_data.page_data;
+        errors.extend(block_on_edit_data.errors.into_iter().map(offset_lines));
+        warnings.extend(block_on_edit_data.warnings.into_iter().map(offset_lines));
+
+        if i + 1 < blocks.len() {
+            if let Some((theorem, _)) = add_to_database::calc_mmp_theorem_in_worksheet(
+                mm_data,
+                settings,
+                block,
+                &worksheet_theorems,
+            )? {
+                worksheet_theorems.add_theorem(mm_data, settings, theorem)?;
+            }
+        }
+    }
+
+    Ok(OnEditData {
+        page_data,
+        errors,
+        warnings,
+    })
+}
+
+// Explains all steps of a worksheet file that can't be proven by their step ref, together with the line of the step.
+// Used by the command line, so the database is loaded without the app
+pub fn explain_mmp_file_unifications(
+    database_path: &str,
+    mmp_path: &str,
+) -> Result<Vec<(u32, UnificationExplanation)>, Error> {
+    let settings = Settings::default();
+    let mm_data = mm_parser::load_metamath_data_with_grammar(database_path, &settings)?;
+
+    let text = fs::read_to_string(mmp_path).or(Err(Error::FileReadError))?;
+    let blocks = mmp_parser::split_into_theorem_blocks(&text);
+    let stop = Arc::new(std::sync::Mutex::new(false));
+
+    let on_edit_data = calc_on_edit_data_blocks(&mm_data, &settings, &blocks, &stop)?;
+
+    Ok(on_edit_data
+        .errors
+        .into_iter()
+        .filter_map(|error| {
+            error
+                .explanation
+                .map(|explanation| (error.start_line_number, *explanation))
+        })
+        .collect())
+}
+
+fn calc_on_edit_data(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    text: &str,
+    worksheet_theorems: &WorksheetTheorems,
+    stop: &Arc<std::sync::Mutex<bool>>,
+) -> Result<OnEditData, Error> {
     let stage_0 = mmp_parser::new(text);
 
     if stop.lock().is_ok_and(|stop| *stop) {
@@ -65,6 +176,7 @@ pub async fn on_edit(
             return Ok(OnEditData {
                 page_data: None,
                 errors: vec![fail.error],
+                warnings: Vec::new(),
             });
         }
     };
@@ -79,6 +191,7 @@ pub async fn on_edit(
             return Ok(OnEditData {
                 page_data: None,
                 errors: fail.errors,
+                warnings: Vec::new(),
             });
         }
     };
@@ -87,12 +200,17 @@ pub async fn on_edit(
         return Err(Error::OnEditStoppedEarlyError);
     }
 
-    let stage_3_success = match stage_2_success.next_stage(&stage_1_success, mm_data)? {
+    let stage_3_success = match stage_2_success.next_stage_in_worksheet(
+        &stage_1_success,
+        mm_data,
+        worksheet_theorems,
+    )? {
         MmpParserStage3::Success(success) => success,
         MmpParserStage3::Fail(fail) => {
             return Ok(OnEditData {
                 page_data: None,
                 errors: fail.errors,
+                warnings: Vec::new(),
             })
         }
     };
@@ -102,6 +220,7 @@ pub async fn on_edit(
             return Ok(OnEditData {
                 page_data: Some(DatabaseElementPageData::Empty),
                 errors: Vec::new(),
+                warnings: Vec::new(),
             })
         }
         MmpParserStage3Success::Header(mut stage_3_header) => {
@@ -128,6 +247,7 @@ pub async fn on_edit(
                     invalid_html: !invalid_html.is_empty(),
                 })),
                 errors: Vec::new(),
+                warnings: Vec::new(),
             });
         }
         MmpParserStage3Success::Comment(stage_3_comment) => {
@@ -141,6 +261,7 @@ pub async fn on_edit(
                     comment: stage_3_comment.comment,
                 })),
                 errors: Vec::new(),
+                warnings: Vec::new(),
             })
         }
         MmpParserStage3Success::Constants(constants) => {
@@ -149,6 +270,7 @@ pub async fn on_edit(
                     constants,
                 })),
                 errors: Vec::new(),
+                warnings: Vec::new(),
             })
         }
         MmpParserStage3Success::Variables(variables) => {
@@ -157,6 +279,7 @@ pub async fn on_edit(
                     variables: variables.into_iter().map(|v| (v, String::new())).collect(),
                 })),
                 errors: Vec::new(),
+                warnings: Vec::new(),
             })
         }
         MmpParserStage3Success::FloatingHypohesis(floating_hypothesis) => {
@@ -167,6 +290,7 @@ pub async fn on_edit(
                     },
                 )),
                 errors: Vec::new(),
+                warnings: Vec::new(),
             })
         }
         MmpParserStage3Success::Theorem(stage_3_theorem) => stage_3_theorem,
@@ -193,6 +317,7 @@ pub async fn on_edit(
                         settings,
                     )?),
                     errors: fail.errors,
+                    warnings: Vec::new(),
                 })
             }
         };
@@ -201,10 +326,11 @@ pub async fn on_edit(
         return Err(Error::OnEditStoppedEarlyError);
     }
 
-    let stage_5 = stage_4_success.next_stage(
+    let mut stage_5 = stage_4_success.next_stage(
         &stage_2_success,
         &stage_3_theorem,
         mm_data,
+        settings,
         Some(stop.clone()),
     )?;
 
@@ -218,19 +344,73 @@ pub async fn on_edit(
         return Err(Error::OnEditStoppedEarlyError);
     }
 
+    let proof_complete = stage_6.proof.is_some();
+
+    let errors = std::mem::take(&mut stage_5.unification_explanations)
+        .into_iter()
+        .map(|(proof_line_i, explanation)| {
+            let (statement_str, line_number) = stage_3::calc_statement_str_and_line_number(
+                &stage_1_success.statements,
+                &stage_2_success.statements,
+                MmpStatement::ProofLine,
+                proof_line_i,
+            )
+            .ok_or(Error::InternalLogicError)?;
+
+            let first_token_end_pos = util::nth_token_end_pos(statement_str, 0);
+
+            Ok(DetailedError {
+                error_type: Error::UnificationError,
+                start_line_number: line_number,
+                start_column: 1,
+                end_line_number: line_number + first_token_end_pos.0 - 1,
+                end_column: first_token_end_pos.1 + 1,
+                explanation: Some(Box::new(explanation)),
+                expected_symbols: None,
+            })
+        })
+        .collect::<Result<Vec<DetailedError>, Error>>()?;
+
+    let page_data = calc_theorem_page_data(
+        mm_data,
+        &stage_2_success,
+        stage_3_theorem,
+        stage_4_success.reference_numbers,
+        stage_4_success.proof_line_statuses,
+        stage_4_success.is_syntax_axiom,
+        Some(stage_5),
+        Some(stage_6),
+        settings,
+    )?;
+
+    // The proof lint verifies the proof against the database, which doesn't contain the earlier blocks of a worksheet
+    let warnings = match &page_data {
+        DatabaseElementPageData::Theorem(theorem_page_data)
+            if proof_complete
+                && !theorem_page_data
+                    .theorem
+                    .proof
+                    .as_ref()
+                    .is_some_and(|proof| {
+                        Theorem::calc_proof_labels(proof)
+                            .into_iter()
+                            .any(|label| worksheet_theorems.contains(label))
+                    }) =>
+        {
+            proof_lint::calc_mmp_proof_lint_warnings(
+                &stage_1_success,
+                &stage_2_success,
+                &theorem_page_data.theorem,
+                mm_data,
+            )?
+        }
+        _ => Vec::new(),
+    };
+
     Ok(OnEditData {
-        page_data: Some(calc_theorem_page_data(
-            mm_data,
-            &stage_2_success,
-            stage_3_theorem,
-            stage_4_success.reference_numbers,
-            stage_4_success.proof_line_statuses,
-            stage_4_success.is_syntax_axiom,
-            Some(stage_5),
-            Some(stage_6),
-            settings,
-        )?),
-        errors: Vec::new(),
+        page_data: Some(page_data),
+        errors,
+        warnings,
     })
 }
 
@@ -471,9 +651,10 @@ impl Serialize for OnEditData {
     {
         use serde::ser::SerializeStruct;
 
-        let mut state = serializer.serialize_struct("OnEditData", 2)?;
+        let mut state = serializer.serialize_struct("OnEditData", 3)?;
         state.serialize_field("pageData", &self.page_data)?;
         state.serialize_field("errors", &self.errors)?;
+        state.serialize_field("warnings", &self.warnings)?;
         state.end()
     }
 }
@@ -483,12 +664,51 @@ impl Serialize for DetailedError {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("DetailedError", 5)?;
+        let mut state = serializer.serialize_struct("DetailedError", 7)?;
         state.serialize_field("errorType", &self.error_type)?;
         state.serialize_field("startLineNumber", &self.start_line_number)?;
         state.serialize_field("startColumn", &self.start_column)?;
         state.serialize_field("endLineNumber", &self.end_line_number)?;
         state.serialize_field("endColumn", &self.end_column)?;
+        state.serialize_field("explanation", &self.explanation)?;
+        state.serialize_field("expectedSymbols", &self.expected_symbols)?;
+        state.end()
+    }
+}
+
+impl fmt::Display for UnificationExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  does not match: {}", self.pattern)?;
+        if !self.substitution.is_empty() {
+            writeln!(f, "  substitution:")?;
+            for (variable, substitution) in &self.substitution {
+                writeln!(f, "    {} := {}", variable, substitution)?;
+            }
+        }
+        if let Some((pattern_subterm, step_subterm)) = &self.clash {
+            writeln!(f, "  first clash: {} / {}", pattern_subterm, step_subterm)?;
+        }
+        if let Some((first_variable, second_variable)) = &self.distinct_variable_pair {
+            writeln!(
+                f,
+                "  distinct variable pair: {} {}",
+                first_variable, second_variable
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for UnificationExplanation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("UnificationExplanation", 4)?;
+        state.serialize_field("pattern", &self.pattern)?;
+        state.serialize_field("substitution", &self.substitution)?;
+        state.serialize_field("clash", &self.clash)?;
+        state.serialize_field("distinctVariablePair", &self.distinct_variable_pair)?;
         state.end()
     }
 }
@@ -0,0 +1,651 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::Serialize;
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    editor::format,
+    metamath::{
+        mmp_parser::{
+            self, MmpLabel, MmpParserStage1, MmpParserStage2, MmpParserStage2Success,
+            MmpParserStage3, MmpParserStage3Success, MmpParserStage4, MmpStatement, ProofLine,
+        },
+        verify::{Show, StepResult, Verifier, VerifierCreationResult},
+    },
+    model::{MetamathData, ParseTree, ParseTreeNode},
+    util::{
+        self, earley_parser_optimized::WorkVariable, work_variable_manager::WorkVariableManager,
+        StrIterToDelimiterSeperatedString,
+    },
+    AppState, Error,
+};
+
+#[derive(Serialize)]
+pub struct ExtractSubproofResult {
+    pub text: String,
+    #[serde(rename = "lemmaFilePath")]
+    pub lemma_file_path: String,
+}
+
+// Removes all proof lines that the qed step doesn't depend on. Hypotheses are always kept
+#[tauri::command]
+pub async fn remove_unused_steps(text: &str) -> Result<Option<String>, Error> {
+    let stage_0 = mmp_parser::new(text);
+
+    let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage2::Success(stage_2_success) = stage_1_success.next_stage()? else {
+        return Ok(None);
+    };
+
+    let steps: Vec<(&str, Vec<&str>)> = stage_2_success
+        .proof_lines
+        .iter()
+        .map(|pl| (pl.step_name, split_hypotheses(pl.hypotheses)))
+        .collect();
+
+    let Some(qed_i) = steps.iter().position(|(step_name, _)| *step_name == "qed") else {
+        return Ok(None);
+    };
+
+    let used_steps = calc_used_steps(&steps, qed_i);
+
+    let mut result_text = String::new();
+    let mut proof_line_i = 0;
+
+    for (&statement, (statement_type, _)) in stage_1_success
+        .statements
+        .iter()
+        .zip(stage_2_success.statements.iter())
+    {
+        if matches!(statement_type, MmpStatement::ProofLine) {
+            let proof_line = stage_2_success
+                .proof_lines
+                .get(proof_line_i)
+                .ok_or(Error::InternalLogicError)?;
+
+            if proof_line.is_hypothesis || used_steps.contains(&proof_line_i) {
+                result_text.push_str(statement);
+            }
+
+            proof_line_i += 1;
+        } else {
+            result_text.push_str(statement);
+        }
+    }
+
+    format::format_mmp_file(&result_text)
+}
+
+// Moves the subproof of a step into a new lemma mmp file next to the current one. The hypotheses of the lemma are the
+// hypotheses the subproof uses, its $d statements are the ones of the current file that concern its variables. The
+// step is then proven by the lemma and lines that are no longer used are removed
+#[tauri::command]
+pub async fn extract_subproof(
+    state: tauri::State<'_, Mutex<AppState>>,
+    text: &str,
+    step_name: &str,
+    file_path: &str,
+) -> Result<Option<ExtractSubproofResult>, Error> {
+    let app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+    let open_folder_data = app_state
+        .open_folder_data
+        .as_ref()
+        .ok_or(Error::NoOpenFolderError)?;
+
+    let stage_0 = mmp_parser::new(text);
+
+    let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage2::Success(stage_2_success) = stage_1_success.next_stage()? else {
+        return Ok(None);
+    };
+
+    let Some(MmpLabel::Theorem(theorem_label)) = stage_2_success.label else {
+        return Ok(None);
+    };
+
+    let proof_lines = &stage_2_success.proof_lines;
+
+    let Some(root_i) = proof_lines
+        .iter()
+        .position(|pl| pl.step_name == step_name && !pl.is_hypothesis && pl.step_name != "qed")
+    else {
+        return Ok(None);
+    };
+
+    let mut steps: Vec<(&str, Vec<&str>)> = proof_lines
+        .iter()
+        .map(|pl| (pl.step_name, split_hypotheses(pl.hypotheses)))
+        .collect();
+
+    let mut subproof_steps: Vec<usize> = calc_used_steps(&steps, root_i).into_iter().collect();
+    subproof_steps.sort_unstable();
+
+    if subproof_steps
+        .iter()
+        .any(|&i| split_hypotheses(proof_lines[i].hypotheses).contains(&"?"))
+    {
+        return Ok(None);
+    }
+
+    let lemma_hypotheses: Vec<usize> = subproof_steps
+        .iter()
+        .copied()
+        .filter(|&i| proof_lines[i].is_hypothesis)
+        .collect();
+
+    let mut lemma_file_path = Path::new(file_path).to_path_buf();
+    let mut lemma_label = String::new();
+
+    for n in 1.. {
+        lemma_label = format!("{}lem{}", theorem_label, n);
+        lemma_file_path.set_file_name(format!("{}.mmp", lemma_label));
+
+        if !mm_data
+            .database_header
+            .theorem_iter()
+            .any(|t| t.label == lemma_label)
+            && !open_folder_data.path.join(&lemma_file_path).exists()
+        {
+            break;
+        }
+    }
+
+    let lemma_text = calc_lemma_text(
+        &stage_2_success,
+        &lemma_label,
+        root_i,
+        &subproof_steps,
+        mm_data,
+    );
+
+    steps[root_i].1 = lemma_hypotheses
+        .iter()
+        .map(|&i| proof_lines[i].step_name)
+        .collect();
+
+    let qed_i = steps
+        .iter()
+        .position(|(step_name, _)| *step_name == "qed")
+        .unwrap_or(root_i);
+
+    let used_steps = calc_used_steps(&steps, qed_i);
+
+    let mut result_text = String::new();
+    let mut proof_line_i = 0;
+
+    for (&statement, (statement_type, _)) in stage_1_success
+        .statements
+        .iter()
+        .zip(stage_2_success.statements.iter())
+    {
+        if matches!(statement_type, MmpStatement::ProofLine) {
+            let proof_line = proof_lines
+                .get(proof_line_i)
+                .ok_or(Error::InternalLogicError)?;
+
+            if proof_line_i == root_i {
+                write_proof_line(
+                    &mut result_text,
+                    proof_line,
+                    &steps[root_i].1,
+                    &lemma_label,
+                    proof_line.expression,
+                );
+                for _ in 0..util::new_lines_at_end_of_str(statement) {
+                    result_text.push('\n');
+                }
+            } else if proof_line.is_hypothesis
+                || used_steps.contains(&proof_line_i)
+                || subproof_steps.binary_search(&proof_line_i).is_err()
+            {
+                result_text.push_str(statement);
+            }
+
+            proof_line_i += 1;
+        } else {
+            result_text.push_str(statement);
+        }
+    }
+
+    fs::write(
+        open_folder_data.path.join(&lemma_file_path),
+        format::format_mmp_file(&lemma_text)?.unwrap_or(lemma_text),
+    )
+    .or(Err(Error::FileWriteError))?;
+
+    Ok(Some(ExtractSubproofResult {
+        text: format::format_mmp_file(&result_text)?.unwrap_or(result_text),
+        lemma_file_path: lemma_file_path.to_string_lossy().to_string(),
+    }))
+}
+
+// Replaces a step proven by a theorem with the proof of that theorem. Variables that only occur in the proof of the
+// theorem are replaced with new work variables
+#[tauri::command]
+pub async fn inline_step(
+    state: tauri::State<'_, Mutex<AppState>>,
+    text: &str,
+    step_name: &str,
+) -> Result<Option<String>, Error> {
+    let app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let stage_0 = mmp_parser::new(text);
+
+    let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage2::Success(stage_2_success) = stage_1_success.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage3::Success(MmpParserStage3Success::Theorem(stage_3_theorem)) =
+        stage_2_success.next_stage(&stage_1_success, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let MmpParserStage4::Success(stage_4_success) =
+        stage_3_theorem.next_stage(&stage_1_success, &stage_2_success, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let Some(root_i) = stage_2_success
+        .proof_lines
+        .iter()
+        .position(|pl| pl.step_name == step_name && !pl.is_hypothesis)
+    else {
+        return Ok(None);
+    };
+
+    let parse_trees: Vec<Option<&ParseTree>> = stage_4_success
+        .proof_lines_parsed
+        .iter()
+        .map(|pl_p| pl_p.parse_tree.as_ref())
+        .collect();
+
+    let Some(new_lines) =
+        calc_inlined_proof_lines(&stage_2_success, &parse_trees, root_i, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let mut result_text = String::new();
+    let mut proof_line_i = 0;
+
+    for (&statement, (statement_type, _)) in stage_1_success
+        .statements
+        .iter()
+        .zip(stage_2_success.statements.iter())
+    {
+        if matches!(statement_type, MmpStatement::ProofLine) && proof_line_i == root_i {
+            result_text.push_str(&new_lines);
+            for _ in 0..util::new_lines_at_end_of_str(statement) {
+                result_text.push('\n');
+            }
+        } else {
+            result_text.push_str(statement);
+        }
+
+        if matches!(statement_type, MmpStatement::ProofLine) {
+            proof_line_i += 1;
+        }
+    }
+
+    format::format_mmp_file(&result_text)
+}
+
+// Returns the proof lines of the theorem referenced by the root step, with the last one being the root step itself
+fn calc_inlined_proof_lines(
+    stage_2_success: &MmpParserStage2Success,
+    parse_trees: &[Option<&ParseTree>],
+    root_i: usize,
+    mm_data: &MetamathData,
+) -> Result<Option<String>, Error> {
+    let proof_lines = &stage_2_success.proof_lines;
+    let root_line = &proof_lines[root_i];
+
+    let Some(theorem) = mm_data
+        .database_header
+        .find_theorem_by_label(root_line.step_ref)
+        .filter(|theorem| theorem.proof.is_some())
+    else {
+        return Ok(None);
+    };
+
+    let Some(theorem_parse_trees) = mm_data
+        .optimized_data
+        .theorem_data
+        .get(root_line.step_ref)
+        .and_then(|theorem_data| theorem_data.parse_trees.as_ref())
+    else {
+        return Ok(None);
+    };
+
+    let root_hypotheses = split_hypotheses(root_line.hypotheses);
+
+    if root_hypotheses.len() != theorem.hypotheses.len() {
+        return Ok(None);
+    }
+
+    let Some(root_parse_trees) = root_hypotheses
+        .iter()
+        .map(|hyp| {
+            proof_lines
+                .iter()
+                .position(|pl| pl.step_name == *hyp)
+                .and_then(|i| parse_trees.get(i).copied().flatten())
+        })
+        .chain(std::iter::once(parse_trees.get(root_i).copied().flatten()))
+        .collect::<Option<Vec<&ParseTree>>>()
+    else {
+        return Ok(None);
+    };
+
+    let Some(substitutions) = ParseTree::calc_substitutions(
+        &theorem_parse_trees.to_ref_parse_tree_vec(),
+        &root_parse_trees,
+        &HashSet::new(),
+        &HashSet::new(),
+        &mm_data.optimized_data.grammar,
+        &mm_data.optimized_data.symbol_number_mapping,
+    )?
+    else {
+        return Ok(None);
+    };
+
+    let Ok(VerifierCreationResult::Verifier(mut verifier)) =
+        Verifier::new(theorem, mm_data, Show::Logical, None, None, None, None)
+    else {
+        return Ok(None);
+    };
+
+    let mut theorem_proof_lines = Vec::new();
+
+    loop {
+        match verifier.proccess_next_step(mm_data)? {
+            StepResult::VerifierFinished => break,
+            StepResult::NoProofLine => {}
+            StepResult::ProofLine(proof_line) => theorem_proof_lines.push(proof_line),
+        }
+    }
+
+    let Some(last_proof_line) = theorem_proof_lines.pop() else {
+        return Ok(None);
+    };
+
+    let mut work_variable_manager = WorkVariableManager::new(
+        &parse_trees.iter().flatten().copied().collect(),
+        &mm_data.optimized_data.symbol_number_mapping,
+    )?;
+
+    let mut floating_hypotheses_substitutions: HashMap<u32, WorkVariable> = HashMap::new();
+
+    let new_parse_trees = theorem_proof_lines
+        .iter()
+        .map(|pl| {
+            mm_data
+                .expression_to_parse_tree(&pl.assertion)?
+                .clone_and_replace_floating_hypotheses(
+                    &mm_data.optimized_data.symbol_number_mapping,
+                    &mm_data.optimized_data.grammar,
+                    &mut floating_hypotheses_substitutions,
+                    &mut work_variable_manager,
+                )
+        })
+        .collect::<Result<Vec<ParseTree>, Error>>()?;
+
+    let work_variable_substitutions: HashMap<WorkVariable, ParseTreeNode> =
+        floating_hypotheses_substitutions
+            .iter()
+            .filter_map(|(rule_i, work_var)| {
+                substitutions
+                    .get(rule_i)
+                    .map(|&node| (*work_var, node.clone()))
+            })
+            .collect();
+
+    let used_step_names: HashSet<&str> = proof_lines.iter().map(|pl| pl.step_name).collect();
+    let mut next_step_number = 1;
+
+    let mut step_name_mapping: HashMap<&str, String> = HashMap::new();
+
+    for (hypothesis, root_hypothesis) in theorem.hypotheses.iter().zip(root_hypotheses.iter()) {
+        for proof_line in theorem_proof_lines
+            .iter()
+            .chain(std::iter::once(&last_proof_line))
+            .filter(|pl| pl.reference == hypothesis.label)
+        {
+            step_name_mapping.insert(&proof_line.step_name, root_hypothesis.to_string());
+        }
+    }
+
+    let mut result_text = String::new();
+
+    for (proof_line, parse_tree) in theorem_proof_lines.iter().zip(new_parse_trees) {
+        if step_name_mapping.contains_key(&*proof_line.step_name) {
+            continue;
+        }
+
+        while used_step_names.contains(&*next_step_number.to_string()) {
+            next_step_number += 1;
+        }
+        let new_step_name = next_step_number.to_string();
+        next_step_number += 1;
+
+        result_text.push_str(&new_step_name);
+        result_text.push(':');
+        result_text.push_str(&map_step_names(&proof_line.hypotheses, &step_name_mapping)?);
+        result_text.push(':');
+        result_text.push_str(&proof_line.reference);
+        result_text.push(' ');
+        result_text.push_str(
+            &ParseTree {
+                typecode: parse_tree.typecode,
+                top_node: parse_tree
+                    .top_node
+                    .clone_and_apply_substitutions(&work_variable_substitutions),
+            }
+            .to_expression(
+                &mm_data.optimized_data.symbol_number_mapping,
+                &mm_data.optimized_data.grammar,
+            )?,
+        );
+        result_text.push('\n');
+
+        step_name_mapping.insert(&proof_line.step_name, new_step_name);
+    }
+
+    let new_hypotheses = map_step_names(&last_proof_line.hypotheses, &step_name_mapping)?;
+
+    write_proof_line(
+        &mut result_text,
+        root_line,
+        &new_hypotheses.split(',').collect::<Vec<&str>>(),
+        &last_proof_line.reference,
+        root_line.expression,
+    );
+
+    Ok(Some(result_text))
+}
+
+fn map_step_names(
+    step_names: &[String],
+    step_name_mapping: &HashMap<&str, String>,
+) -> Result<String, Error> {
+    Ok(step_names
+        .iter()
+        .map(|step_name| {
+            step_name_mapping
+                .get(step_name.as_str())
+                .map(|new_step_name| new_step_name.as_str())
+                .ok_or(Error::InternalLogicError)
+        })
+        .collect::<Result<Vec<&str>, Error>>()?
+        .into_iter()
+        .fold_to_delimiter_seperated_string(","))
+}
+
+fn calc_lemma_text(
+    stage_2_success: &MmpParserStage2Success,
+    lemma_label: &str,
+    root_i: usize,
+    subproof_steps: &[usize],
+    mm_data: &MetamathData,
+) -> String {
+    let proof_lines = &stage_2_success.proof_lines;
+
+    let mut lemma_text = String::new();
+
+    lemma_text.push_str("$theorem ");
+    lemma_text.push_str(lemma_label);
+    lemma_text.push_str("\n\n");
+
+    let lemma_symbols: HashSet<&str> = subproof_steps
+        .iter()
+        .flat_map(|&i| proof_lines[i].expression.split_ascii_whitespace())
+        .collect();
+
+    let distinct_variable_pairs: HashSet<(String, String)> =
+        util::calc_distinct_variable_pairs(&stage_2_success.distinct_vars)
+            .into_iter()
+            .filter(|(var_1, var_2)| {
+                lemma_symbols.contains(var_1.as_str()) && lemma_symbols.contains(var_2.as_str())
+            })
+            .collect();
+
+    let variable_order: HashMap<&str, usize> = mm_data
+        .optimized_data
+        .floating_hypotheses
+        .iter()
+        .enumerate()
+        .map(|(i, fh)| (fh.variable.as_str(), i))
+        .collect();
+
+    let distinct_var_groups = util::group_distinct_variable_pairs(
+        &distinct_variable_pairs,
+        &HashSet::new(),
+        &variable_order,
+    );
+
+    for group in &distinct_var_groups {
+        lemma_text.push_str("$d ");
+        lemma_text.push_str(&group.iter().fold_to_delimiter_seperated_string(" "));
+        lemma_text.push('\n');
+    }
+    if !distinct_var_groups.is_empty() {
+        lemma_text.push('\n');
+    }
+
+    let mut hypothesis_number = 1;
+
+    for &i in subproof_steps {
+        let proof_line = &proof_lines[i];
+
+        if proof_line.is_hypothesis {
+            write_hypothesis_line(
+                &mut lemma_text,
+                proof_line,
+                &format!("{}.{}", lemma_label, hypothesis_number),
+            );
+            hypothesis_number += 1;
+        } else if i == root_i {
+            lemma_text.push_str("qed:");
+            lemma_text.push_str(proof_line.hypotheses);
+            lemma_text.push(':');
+            lemma_text.push_str(proof_line.step_ref);
+            lemma_text.push(' ');
+            lemma_text.push_str(proof_line.expression.trim());
+            lemma_text.push('\n');
+        } else {
+            write_proof_line(
+                &mut lemma_text,
+                proof_line,
+                &split_hypotheses(proof_line.hypotheses),
+                proof_line.step_ref,
+                proof_line.expression,
+            );
+            lemma_text.push('\n');
+        }
+    }
+
+    lemma_text
+}
+
+fn write_hypothesis_line(result_text: &mut String, proof_line: &ProofLine, hypothesis_label: &str) {
+    result_text.push('h');
+    result_text.push_str(proof_line.step_name);
+    result_text.push_str("::");
+    result_text.push_str(hypothesis_label);
+    result_text.push(' ');
+    result_text.push_str(proof_line.expression.trim());
+    result_text.push('\n');
+}
+
+// Writes a proof line without the new lines at its end
+fn write_proof_line(
+    result_text: &mut String,
+    proof_line: &ProofLine,
+    hypotheses: &[&str],
+    step_ref: &str,
+    expression: &str,
+) {
+    if proof_line.advanced_unification {
+        result_text.push('!');
+    }
+    if proof_line.is_hypothesis {
+        result_text.push('h');
+    }
+
+    result_text.push_str(proof_line.step_name);
+    result_text.push(':');
+    result_text.push_str(&hypotheses.iter().fold_to_delimiter_seperated_string(","));
+    result_text.push(':');
+    result_text.push_str(step_ref);
+    result_text.push(' ');
+    result_text.push_str(expression.trim());
+}
+
+fn split_hypotheses(hypotheses: &str) -> Vec<&str> {
+    if hypotheses.is_empty() {
+        Vec::new()
+    } else {
+        hypotheses.split(',').collect()
+    }
+}
+
+// Returns the indices of all steps the root step depends on, including the root step itself
+fn calc_used_steps(steps: &[(&str, Vec<&str>)], root_i: usize) -> HashSet<usize> {
+    let mut used_steps: HashSet<usize> = HashSet::new();
+    let mut steps_to_check: Vec<usize> = vec![root_i];
+
+    while let Some(i) = steps_to_check.pop() {
+        if !used_steps.insert(i) {
+            continue;
+        }
+
+        for hypothesis in &steps[i].1 {
+            if let Some(hypothesis_i) = steps
+                .iter()
+                .position(|(step_name, _)| step_name == hypothesis)
+            {
+                steps_to_check.push(hypothesis_i);
+            }
+        }
+    }
+
+    used_steps
+}
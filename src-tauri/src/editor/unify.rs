@@ -1,17 +1,19 @@
+use std::collections::{HashMap, HashSet};
+
 use tauri::async_runtime::Mutex;
 
 use crate::{
-    editor::format,
+    editor::{add_to_database, format},
     metamath::{
         export,
         mmp_parser::{
             self, MmpParserStage1, MmpParserStage2, MmpParserStage3, MmpParserStage3Success,
-            MmpParserStage4, MmpStatement, UnifyLine,
+            MmpParserStage4, MmpStatement, UnifyLine, WorksheetTheorems,
         },
     },
     model::MetamathData,
     util::{self, StrIterToDelimiterSeperatedString},
-    AppState, Error,
+    AppState, Error, Settings,
 };
 
 #[tauri::command]
@@ -21,8 +23,82 @@ pub async fn unify(
 ) -> Result<Option<String>, Error> {
     let app_state = state.lock().await;
     let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
-    let settings = &app_state.settings;
 
+    let blocks = mmp_parser::split_into_theorem_blocks(text);
+
+    if blocks.len() == 1 {
+        return unify_mmp(mm_data, &app_state.settings, text);
+    }
+
+    unify_blocks(mm_data, &app_state.settings, &blocks)
+}
+
+// Unifies the blocks of a worksheet in order. The theorems of all blocks but the last one are collected in a
+// worksheet overlay, so that later blocks can reference them. Blocks that can't be unified are kept as they are
+fn unify_blocks(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    blocks: &[(&str, u32)],
+) -> Result<Option<String>, Error> {
+    let mut result_text = String::new();
+    let mut any_block_unified = false;
+    let mut worksheet_theorems = WorksheetTheorems::default();
+
+    for (i, &(block, _)) in blocks.iter().enumerate() {
+        let block_text =
+            match unify_mmp_in_worksheet(mm_data, settings, block, &worksheet_theorems)? {
+                Some(unified_block) => {
+                    any_block_unified = true;
+                    unified_block
+                }
+                None => block.to_string(),
+            };
+
+        mmp_parser::push_theorem_block(&mut result_text, &block_text);
+
+        if i + 1 < blocks.len() {
+            if let Some((theorem, _)) = add_to_database::calc_mmp_theorem_in_worksheet(
+                mm_data,
+                settings,
+                &block_text,
+                &worksheet_theorems,
+            )? {
+                worksheet_theorems.add_theorem(mm_data, settings, theorem)?;
+            }
+        }
+    }
+
+    Ok(any_block_unified.then_some(result_text))
+}
+
+pub fn unify_mmp(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    text: &str,
+) -> Result<Option<String>, Error> {
+    unify_mmp_in_worksheet(mm_data, settings, text, &WorksheetTheorems::default())
+}
+
+// Like unify_mmp, but the proof can also reference the theorems of the earlier blocks of a worksheet
+fn unify_mmp_in_worksheet(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    text: &str,
+    worksheet_theorems: &WorksheetTheorems,
+) -> Result<Option<String>, Error> {
+    unify_mmp_pass(mm_data, settings, text, worksheet_theorems, true)
+}
+
+// If insert_missing_distinct_vars is set and the proof needs $d statements that are missing, they are added and the
+// text is unified a second time, without inserting $d statements again. Existing $d statements are never changed, the
+// ones the proof doesn't need are flagged as warnings by on_edit instead
+fn unify_mmp_pass(
+    mm_data: &MetamathData,
+    settings: &Settings,
+    text: &str,
+    worksheet_theorems: &WorksheetTheorems,
+    insert_missing_distinct_vars: bool,
+) -> Result<Option<String>, Error> {
     let stage_0 = mmp_parser::new(text);
 
     let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
@@ -34,7 +110,7 @@ pub async fn unify(
     };
 
     let MmpParserStage3::Success(MmpParserStage3Success::Theorem(stage_3_theorem)) =
-        stage_2_success.next_stage(&stage_1_success, mm_data)?
+        stage_2_success.next_stage_in_worksheet(&stage_1_success, mm_data, worksheet_theorems)?
     else {
         return Ok(None);
     };
@@ -45,7 +121,37 @@ pub async fn unify(
         return Ok(None);
     };
 
-    let stage_5 = stage_4_success.next_stage(&stage_2_success, &stage_3_theorem, mm_data, None)?;
+    let stage_5 =
+        stage_4_success.next_stage(&stage_2_success, &stage_3_theorem, mm_data, settings, None)?;
+
+    let required_distinct_variable_pairs =
+        stage_5.calc_required_distinct_variable_pairs(&stage_3_theorem, mm_data)?;
+
+    let missing_distinct_variable_pairs: HashSet<(String, String)> =
+        required_distinct_variable_pairs
+            .iter()
+            .filter(|pair| !stage_4_success.distinct_variable_pairs.contains(*pair))
+            .cloned()
+            .collect();
+
+    // The proof can only be completed once the missing $d statements are there, so add them and unify again
+    if insert_missing_distinct_vars && !missing_distinct_variable_pairs.is_empty() {
+        let text_with_distinct_vars = insert_distinct_vars(
+            &stage_1_success.statements,
+            &stage_2_success.statements,
+            &missing_distinct_variable_pairs,
+            &stage_4_success.distinct_variable_pairs,
+            mm_data,
+        );
+
+        return unify_mmp_pass(
+            mm_data,
+            settings,
+            &text_with_distinct_vars,
+            worksheet_theorems,
+            false,
+        );
+    }
 
     let stage_6 = stage_5.next_stage(&stage_3_theorem, &stage_4_success, mm_data, settings)?;
 
@@ -104,6 +210,80 @@ pub async fn unify(
     format::format_mmp_file(&result_text)
 }
 
+// Adds $d statements covering the missing pairs after the last $d statement, or before the first proof line if there
+// is none
+fn insert_distinct_vars(
+    statement_strs: &[&str],
+    statements: &[(MmpStatement, u32)],
+    missing_distinct_variable_pairs: &HashSet<(String, String)>,
+    distinct_variable_pairs: &HashSet<(String, String)>,
+    mm_data: &MetamathData,
+) -> String {
+    let variable_order = calc_variable_order(mm_data);
+
+    let mut distinct_vars_text = String::new();
+
+    for group in util::group_distinct_variable_pairs(
+        missing_distinct_variable_pairs,
+        distinct_variable_pairs,
+        &variable_order,
+    ) {
+        distinct_vars_text.push_str("$d ");
+        distinct_vars_text.push_str(&group.into_iter().fold_to_delimiter_seperated_string(" "));
+        distinct_vars_text.push('\n');
+    }
+
+    let last_distinct_var_i = statements
+        .iter()
+        .rposition(|(statement_type, _)| *statement_type == MmpStatement::DistinctVar);
+
+    let insert_before_i = match last_distinct_var_i {
+        Some(i) => i + 1,
+        None => statements
+            .iter()
+            .position(|(statement_type, _)| {
+                matches!(
+                    statement_type,
+                    MmpStatement::ProofLine | MmpStatement::Proof
+                )
+            })
+            .unwrap_or(statements.len()),
+    };
+
+    let mut result_text: String = statement_strs[..insert_before_i].concat();
+
+    if last_distinct_var_i.is_some() {
+        // Insert directly after the last $d statement, keeping the new lines that followed it
+        let new_lines = result_text.len() - result_text.trim_end_matches('\n').len();
+        result_text.truncate(result_text.len() - new_lines);
+        result_text.push('\n');
+        result_text.push_str(&distinct_vars_text);
+        for _ in 1..new_lines {
+            result_text.push('\n');
+        }
+    } else {
+        if !result_text.is_empty() && !result_text.ends_with("\n\n") {
+            result_text.push('\n');
+        }
+        result_text.push_str(&distinct_vars_text);
+        result_text.push('\n');
+    }
+
+    result_text.push_str(&statement_strs[insert_before_i..].concat());
+
+    result_text
+}
+
+fn calc_variable_order(mm_data: &MetamathData) -> HashMap<&str, usize> {
+    mm_data
+        .optimized_data
+        .floating_hypotheses
+        .iter()
+        .enumerate()
+        .map(|(i, fh)| (fh.variable.as_str(), i))
+        .collect()
+}
+
 fn write_unify_line(
     result_text: &mut String,
     unify_line: UnifyLine,
@@ -156,11 +336,21 @@ fn write_proof(result_text: &mut String, proof: &str) -> Result<(), Error> {
     if proof.starts_with('(') {
         let (label_str, step_str) = proof.split_once(')').ok_or(Error::InternalLogicError)?;
 
-        export::write_text_wrapped(result_text, label_str, "  ");
+        export::write_text_wrapped(
+            result_text,
+            label_str,
+            "  ",
+            export::DEFAULT_MAX_LINE_LENGTH,
+        );
         result_text.push_str(" ) ");
-        export::write_text_wrapped_no_whitespace(result_text, step_str, "  ");
+        export::write_text_wrapped_no_whitespace(
+            result_text,
+            step_str,
+            "  ",
+            export::DEFAULT_MAX_LINE_LENGTH,
+        );
     } else {
-        export::write_text_wrapped(result_text, proof, "  ");
+        export::write_text_wrapped(result_text, proof, "  ", export::DEFAULT_MAX_LINE_LENGTH);
     }
 
     Ok(())
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+
+use tauri::async_runtime::Mutex;
+
+use crate::{
+    editor::{format, unify},
+    metamath::mmp_parser::{
+        self, MmpParserStage1, MmpParserStage1Success, MmpParserStage2, MmpParserStage2Success,
+        MmpParserStage3, MmpParserStage3Success, MmpParserStage4, MmpStatement,
+    },
+    model::{MetamathData, ParseTree, ParseTreeNode},
+    util::{
+        self,
+        earley_parser_optimized::{InputSymbol, WorkVariable},
+    },
+    AppState, Error,
+};
+
+// Replaces a work variable, for example "ph$1", with the given expression everywhere in the mmp file. The expression
+// is written without typecode
+#[tauri::command]
+pub async fn substitute_work_variable(
+    state: tauri::State<'_, Mutex<AppState>>,
+    text: &str,
+    work_variable: &str,
+    expression: &str,
+) -> Result<Option<String>, Error> {
+    let app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let (work_var, typecode) = parse_work_variable(work_variable, mm_data)?;
+
+    let substitution = mm_data
+        .expression_to_parse_tree(&format!("{} {}", typecode, expression))?
+        .top_node;
+
+    let mut substitutions: HashMap<WorkVariable, ParseTreeNode> = HashMap::new();
+    substitutions.insert(work_var, substitution);
+
+    let Some(result_text) = apply_work_variable_substitutions(text, &substitutions, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    format::format_mmp_file(&result_text)
+}
+
+// Replaces all work variables left in the mmp file with variables of the same typecode that don't occur in the file
+// yet, then unifies the result to add the $d statements the new variables need
+#[tauri::command]
+pub async fn resolve_work_variables(
+    state: tauri::State<'_, Mutex<AppState>>,
+    text: &str,
+) -> Result<Option<String>, Error> {
+    let app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let Some((stage_1_success, stage_2_success, parse_trees)) = calc_parse_trees(text, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let mut work_variables: Vec<WorkVariable> = Vec::new();
+    let mut used_floating_hypotheses: HashSet<&str> = HashSet::new();
+
+    for parse_tree in parse_trees.iter().flatten() {
+        for node in &parse_tree.top_node {
+            if let ParseTreeNode::WorkVariable(work_var) = node {
+                if !work_variables.contains(work_var) {
+                    work_variables.push(*work_var);
+                }
+            }
+        }
+
+        for rule_i in parse_tree
+            .top_node
+            .get_floating_hypotheses_rules(&mm_data.optimized_data.grammar)?
+        {
+            used_floating_hypotheses.insert(
+                &mm_data
+                    .optimized_data
+                    .grammar
+                    .rules
+                    .get(rule_i as usize)
+                    .ok_or(Error::InternalLogicError)?
+                    .label,
+            );
+        }
+    }
+
+    if work_variables.is_empty() {
+        return Ok(None);
+    }
+
+    let distinct_vars: HashSet<&str> = stage_2_success
+        .distinct_vars
+        .iter()
+        .flat_map(|distinct_var| distinct_var.split_ascii_whitespace())
+        .collect();
+
+    work_variables.sort_by_key(|work_var| (work_var.typecode_i, work_var.number));
+
+    let mut substitutions: HashMap<WorkVariable, ParseTreeNode> = HashMap::new();
+
+    for work_var in work_variables {
+        let floating_hypothesis = mm_data
+            .optimized_data
+            .floating_hypotheses
+            .iter()
+            .find(|fh| {
+                !used_floating_hypotheses.contains(&*fh.label)
+                    && !distinct_vars.contains(&*fh.variable)
+                    && mm_data
+                        .optimized_data
+                        .symbol_number_mapping
+                        .numbers
+                        .get(&format!("${}", fh.typecode))
+                        == Some(&work_var.typecode_i)
+            })
+            .ok_or(Error::NoDummyVariableAvailableError)?;
+
+        used_floating_hypotheses.insert(&floating_hypothesis.label);
+
+        substitutions.insert(
+            work_var,
+            mm_data
+                .expression_to_parse_tree(&format!(
+                    "{} {}",
+                    floating_hypothesis.typecode, floating_hypothesis.variable
+                ))?
+                .top_node,
+        );
+    }
+
+    let result_text = write_parse_trees(
+        &stage_1_success,
+        &stage_2_success,
+        &parse_trees,
+        &substitutions,
+        mm_data,
+    )?;
+
+    match unify::unify_mmp(mm_data, &app_state.settings, &result_text)? {
+        Some(unified_text) => Ok(Some(unified_text)),
+        None => format::format_mmp_file(&result_text),
+    }
+}
+
+// Returns the work variable and the typecode of its variable
+fn parse_work_variable<'a>(
+    work_variable: &str,
+    mm_data: &'a MetamathData,
+) -> Result<(WorkVariable, &'a str), Error> {
+    let work_variable = work_variable.trim();
+
+    let (variable, _) = work_variable
+        .split_once('$')
+        .ok_or(Error::InvalidWorkVariableError)?;
+
+    let floating_hypothesis = mm_data
+        .optimized_data
+        .floating_hypotheses
+        .iter()
+        .find(|fh| fh.variable == variable)
+        .ok_or(Error::InvalidWorkVariableError)?;
+
+    let input_symbols = mm_data
+        .optimized_data
+        .symbol_number_mapping
+        .expression_to_input_vec_skip_first(
+            &format!("{} {}", floating_hypothesis.typecode, work_variable),
+            &mm_data.optimized_data.floating_hypotheses,
+        )?;
+
+    let [InputSymbol::WorkVariable(work_var)] = input_symbols[..] else {
+        return Err(Error::InvalidWorkVariableError);
+    };
+
+    Ok((work_var, &floating_hypothesis.typecode))
+}
+
+fn apply_work_variable_substitutions(
+    text: &str,
+    substitutions: &HashMap<WorkVariable, ParseTreeNode>,
+    mm_data: &MetamathData,
+) -> Result<Option<String>, Error> {
+    let Some((stage_1_success, stage_2_success, parse_trees)) = calc_parse_trees(text, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(write_parse_trees(
+        &stage_1_success,
+        &stage_2_success,
+        &parse_trees,
+        substitutions,
+        mm_data,
+    )?))
+}
+
+type ParsedMmp<'a> = (
+    MmpParserStage1Success<'a>,
+    MmpParserStage2Success<'a>,
+    Vec<Option<ParseTree>>,
+);
+
+fn calc_parse_trees<'a>(
+    text: &'a str,
+    mm_data: &MetamathData,
+) -> Result<Option<ParsedMmp<'a>>, Error> {
+    let stage_0 = mmp_parser::new(text);
+
+    let MmpParserStage1::Success(stage_1_success) = stage_0.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage2::Success(stage_2_success) = stage_1_success.next_stage()? else {
+        return Ok(None);
+    };
+
+    let MmpParserStage3::Success(MmpParserStage3Success::Theorem(stage_3_theorem)) =
+        stage_2_success.next_stage(&stage_1_success, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let MmpParserStage4::Success(stage_4_success) =
+        stage_3_theorem.next_stage(&stage_1_success, &stage_2_success, mm_data)?
+    else {
+        return Ok(None);
+    };
+
+    let parse_trees = stage_4_success
+        .proof_lines_parsed
+        .into_iter()
+        .map(|pl_p| pl_p.parse_tree)
+        .collect();
+
+    Ok(Some((stage_1_success, stage_2_success, parse_trees)))
+}
+
+// Rewrites the expressions of all proof lines with the substitutions applied, keeping everything else as it is
+fn write_parse_trees(
+    stage_1_success: &MmpParserStage1Success,
+    stage_2_success: &MmpParserStage2Success,
+    parse_trees: &[Option<ParseTree>],
+    substitutions: &HashMap<WorkVariable, ParseTreeNode>,
+    mm_data: &MetamathData,
+) -> Result<String, Error> {
+    let mut result_text = String::new();
+
+    let mut proof_line_iter = stage_2_success.proof_lines.iter().zip(parse_trees.iter());
+
+    for (&statement, (statement_type, _)) in stage_1_success
+        .statements
+        .iter()
+        .zip(stage_2_success.statements.iter())
+    {
+        if matches!(statement_type, MmpStatement::ProofLine) {
+            let (proof_line, parse_tree) =
+                proof_line_iter.next().ok_or(Error::InternalLogicError)?;
+
+            let Some(parse_tree) = parse_tree else {
+                result_text.push_str(statement);
+                continue;
+            };
+
+            if proof_line.advanced_unification {
+                result_text.push('!');
+            }
+            if proof_line.is_hypothesis {
+                result_text.push('h');
+            }
+
+            result_text.push_str(proof_line.step_name);
+            result_text.push(':');
+            result_text.push_str(proof_line.hypotheses);
+            result_text.push(':');
+            result_text.push_str(proof_line.step_ref);
+            result_text.push(' ');
+
+            result_text.push_str(
+                &ParseTree {
+                    typecode: parse_tree.typecode,
+                    top_node: parse_tree
+                        .top_node
+                        .clone_and_apply_substitutions(substitutions),
+                }
+                .to_expression(
+                    &mm_data.optimized_data.symbol_number_mapping,
+                    &mm_data.optimized_data.grammar,
+                )?,
+            );
+
+            for _ in 0..util::new_lines_at_end_of_str(statement) {
+                result_text.push('\n');
+            }
+        } else {
+            result_text.push_str(statement);
+        }
+    }
+
+    Ok(result_text)
+}
@@ -84,6 +84,7 @@ pub async fn get_theorem_list(
                 }
             }
             DatabaseElement::Statement(statement) => match statement {
+                Statement::ScopeStatement(_) => {}
                 Statement::CommentStatement(comment) => {
                     curr_header_comment_amount += 1;
                     if page * 100 <= theorem_amount {
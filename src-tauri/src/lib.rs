@@ -1,19 +1,27 @@
 use std::{fmt, sync::Arc};
 
 use model::MetamathData;
-use serde::Deserialize;
-use tauri::{async_runtime::Mutex, App, AppHandle, Emitter, Listener, Manager};
+use serde::{Deserialize, Serialize};
+use tauri::{async_runtime::Mutex, App, AppHandle, Emitter, Listener, Manager, RunEvent};
 
 use crate::model::{FolderData, IdManager};
 
+mod analysis;
 mod editor;
 mod explorer;
 mod local_state;
 mod metamath;
 mod model;
 mod search;
+mod session;
+mod settings;
 mod util;
 
+pub use editor::on_edit::{explain_mmp_file_unifications, UnificationExplanation};
+pub use metamath::export::check_database_file_normalized;
+#[cfg(feature = "bench")]
+pub use util::bulk_parsing_benchmark::{benchmark_bulk_parsing, BulkParsingBenchmark};
+
 pub struct AppState {
     metamath_data: Option<MetamathData>,
     // Used to temporarily store MetamathData before the user confirms they wants to open a database
@@ -23,25 +31,115 @@ pub struct AppState {
     stop_temp_database_calculations: Arc<std::sync::Mutex<bool>>,
     id_manager: IdManager,
     open_folder_data: Option<FolderData>,
+    // The settings of the user with the overrides of the open database applied
     settings: Settings,
+    global_settings: Settings,
+    // Set if the stored settings could not be read at startup. Until new settings are saved, the defaults are used
+    settings_load_error: Option<Error>,
+    database_settings_overrides: settings::SettingsOverrides,
+    temp_database_settings_overrides: settings::SettingsOverrides,
 }
 
 pub struct AdditionalStopSignals {
     stop_on_edit: Arc<std::sync::Mutex<bool>>,
 }
 
-#[derive(Deserialize, Default, Clone)]
+// Missing fields are filled in with their defaults, so that settings written by older versions can still be read
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Settings {
     #[serde(rename = "definitionsStartWith")]
     definitons_start_with: String,
     #[serde(rename = "colorUnicodePreview")]
-    _color_unicode_preview: bool,
+    color_unicode_preview: bool,
     #[serde(rename = "showUnifyResultInUnicodePreview")]
     show_unify_result_in_unicode_preview: bool,
     #[serde(rename = "defaultShowAll")]
-    _default_show_all: bool,
+    default_show_all: bool,
     #[serde(rename = "proofFormat")]
     proof_format: ProofFormatOption,
+    #[serde(rename = "formatOnSave")]
+    format_on_save: bool,
+    #[serde(rename = "styleLint")]
+    style_lint: StyleLintSettings,
+    #[serde(rename = "deductionBridgingTheorems")]
+    deduction_bridging_theorems: Vec<String>,
+    #[serde(rename = "exportFormatting")]
+    export_formatting: ExportFormattingSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct StyleLintSettings {
+    #[serde(rename = "labelNaming")]
+    label_naming: bool,
+    #[serde(rename = "discouragedTags")]
+    discouraged_tags: bool,
+    #[serde(rename = "descriptionPresence")]
+    description_presence: bool,
+    #[serde(rename = "contributorLines")]
+    contributor_lines: bool,
+    #[serde(rename = "lineLength")]
+    line_length: bool,
+    #[serde(rename = "maxLineLength")]
+    max_line_length: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ExportFormattingSettings {
+    #[serde(rename = "lineWidth")]
+    line_width: u32,
+    #[serde(rename = "wrapProofs")]
+    wrap_proofs: bool,
+    #[serde(rename = "blankLinesBetweenStatements")]
+    blank_lines_between_statements: u32,
+    #[serde(rename = "blockIndentation")]
+    block_indentation: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            definitons_start_with: "df-".to_string(),
+            color_unicode_preview: true,
+            show_unify_result_in_unicode_preview: true,
+            default_show_all: false,
+            proof_format: ProofFormatOption::Compressed,
+            format_on_save: true,
+            style_lint: StyleLintSettings::default(),
+            deduction_bridging_theorems: ["a1i", "syl", "sylib", "sylibr", "mpd"]
+                .into_iter()
+                .map(|label| label.to_string())
+                .collect(),
+            export_formatting: ExportFormattingSettings::default(),
+        }
+    }
+}
+
+impl Default for StyleLintSettings {
+    fn default() -> Self {
+        StyleLintSettings {
+            label_naming: true,
+            discouraged_tags: true,
+            description_presence: true,
+            contributor_lines: true,
+            line_length: true,
+            max_line_length: 79,
+        }
+    }
+}
+
+// The formatting that statements have always been written with
+impl Default for ExportFormattingSettings {
+    fn default() -> Self {
+        ExportFormattingSettings {
+            line_width: metamath::export::DEFAULT_MAX_LINE_LENGTH as u32,
+            wrap_proofs: true,
+            blank_lines_between_statements: 1,
+            block_indentation: 2,
+        }
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -49,9 +147,26 @@ pub enum ProofFormatOption {
     #[default]
     Uncompressed,
     Compressed,
+    // Compressed, with the labels ordered by how often they are used
+    CompressedNormalized,
+    // Uncompressed, with each step prefixed by the hypothesis it fulfills
+    Explicit,
+    // Uncompressed, with repeated subproofs replaced by references to local labels
+    Packed,
 }
 
 fn app_setup(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
+    let (global_settings, settings_load_error) = match settings::load_settings(app.handle()) {
+        Ok(settings) => (settings, None),
+        Err(error) => {
+            eprintln!(
+                "Could not read the stored settings, using the defaults: {}",
+                error
+            );
+            (Settings::default(), Some(error))
+        }
+    };
+
     app.manage(Mutex::new(AppState {
         metamath_data: None,
         temp_metamath_data: None,
@@ -59,7 +174,11 @@ fn app_setup(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
         stop_temp_database_calculations: Arc::new(std::sync::Mutex::new(false)),
         id_manager: IdManager::new(),
         open_folder_data: None,
-        settings: Settings::default(),
+        settings: global_settings.clone(),
+        global_settings,
+        settings_load_error,
+        database_settings_overrides: settings::SettingsOverrides::default(),
+        temp_database_settings_overrides: settings::SettingsOverrides::default(),
     }));
 
     app.manage(Mutex::new(AdditionalStopSignals {
@@ -78,18 +197,6 @@ fn setup_main_window(window: tauri::Window, app: AppHandle) {
     window.get_webview_window("main").unwrap().show().unwrap();
 }
 
-#[tauri::command]
-async fn set_settings(
-    state: tauri::State<'_, Mutex<AppState>>,
-    settings: Settings,
-) -> Result<(), ()> {
-    let mut app_state = state.lock().await;
-
-    app_state.settings = settings;
-
-    Ok(())
-}
-
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -110,7 +217,13 @@ pub fn run() {
             // editor::set_in_progress_theorem,
             // editor::delete_in_progress_theorem,
             setup_main_window,
-            set_settings,
+            settings::get_settings,
+            settings::get_default_settings,
+            settings::set_settings,
+            settings::get_database_settings_overrides,
+            settings::set_database_settings_overrides,
+            session::get_recent_databases,
+            session::get_previous_session,
             // explorer::add_header,
             explorer::quick_search,
             explorer::get_theorem_list,
@@ -124,6 +237,23 @@ pub fn run() {
             search::search_by_parse_tree_syntax_check,
             search::axiom_autocomplete,
             search::definition_autocomplete,
+            search::expression_autocomplete,
+            analysis::dependency_graph::get_direct_dependencies,
+            analysis::dependency_graph::get_transitive_dependencies,
+            analysis::dependency_graph::get_shortest_dependency_path,
+            analysis::dependency_graph::get_dependency_depth,
+            analysis::dependency_graph::get_dependency_subgraph_export,
+            analysis::dependency_graph::write_dependency_subgraph_export_to_file,
+            analysis::database_diff::diff_database,
+            analysis::duplicates::get_duplicate_report,
+            analysis::proof_lint::lint_theorem_proof,
+            analysis::proof_diff::diff_theorem_proof,
+            analysis::proof_lint::lint_database_proofs,
+            analysis::style_lint::lint_database_style,
+            analysis::style_lint::lint_mmp_style,
+            analysis::usage::get_usage_report,
+            analysis::usage::get_theorems_depending_on,
+            analysis::usage::write_usage_report_csv_to_file,
             editor::open_folder,
             editor::close_folder,
             editor::get_subfolder,
@@ -144,9 +274,17 @@ pub fn run() {
             editor::format::format,
             editor::on_edit::on_edit,
             editor::add_to_database::add_to_database,
+            editor::merge_database::merge_database_changes,
             editor::add_to_database::add_to_database_preview,
             editor::renumber::renumber,
             editor::unify::unify,
+            editor::mmj2::import_mmj2_worksheet,
+            editor::mmj2::export_mmj2_worksheet,
+            editor::work_variables::substitute_work_variable,
+            editor::work_variables::resolve_work_variables,
+            editor::subproof::remove_unused_steps,
+            editor::subproof::extract_subproof,
+            editor::subproof::inline_step,
             // metamath::turn_into_theorem,
             // metamath::text_to_constants,
             // metamath::text_to_variables,
@@ -154,6 +292,7 @@ pub fn run() {
             // metamath::text_to_html_representations,
             metamath::export::new_database,
             metamath::export::export_database,
+            metamath::export::check_database_normalized,
             metamath::mm_parser::open_metamath_database,
             metamath::mm_parser::cancel_open_metamath_database,
             metamath::mm_parser::confirm_open_metamath_database,
@@ -181,8 +320,19 @@ pub fn run() {
             local_state::html_representation::get_html_representations,
         ])
         .setup(|app| app_setup(app))
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // The session is also saved whenever the database, the folder or the open tabs change, so it can be skipped
+            // if a command is still holding the state
+            if let RunEvent::Exit = event {
+                let state = app.state::<Mutex<AppState>>();
+                let app_state = state.try_lock();
+                if let Ok(app_state) = app_state {
+                    session::save_session(app, &app_state);
+                }
+            }
+        });
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -241,6 +391,8 @@ pub enum Error {
     FileOpenError,
     FileReadError,
     FileWriteError,
+    SettingsStoreError, // Returned if the settings could not be read from or written to the settings store
+    InvalidDatabaseSettingsError, // Returned if the settings file beside a database is not valid
 
     // Parsing mmp file errors
     NonAsciiSymbolError, // Returned if there is a non ascii symbol in the text
@@ -298,6 +450,12 @@ pub enum Error {
     TokensAfterAllowDiscouragedError, // Returned if there are tokens after $allowdiscouraged
     MultipleAllowIncompleteError,   // Returned if there are multiple $allowdiscouraged statements
     TokensAfterAllowIncompleteError, // Returned if there are tokens after $allowdiscouraged
+    MultipleAllowProofModificationError, // Returned if there are multiple $allowproofmodification statements
+    TokensAfterAllowProofModificationError, // Returned if there are tokens after $allowproofmodification
+    MultipleDeductionModeError, // Returned if there are multiple $deductionmode statements
+    TokensAfterDeductionModeError, // Returned if there are tokens after $deductionmode
+    MultipleUnicodeInputError,  // Returned if there are multiple $unicodeinput statements
+    TokensAfterUnicodeInputError, // Returned if there are tokens after $unicodeinput
     MultipleLocateAfterError, // Returned if there are multiple $locateafter(-var/-const) statements
     TooFewLocateAfterTokensError, // Returned if there is a $locateafter statement without a follow up token
     TooManyLocateAfterTokensError, // Returned if there is a $locateafter statement with too many follow up tokens
@@ -319,6 +477,9 @@ pub enum Error {
     FloatHypStatementOutOfPlaceError, // Returned if there is a floating hypothesis when there shouldn't be
     AllowDiscouragedOutOfPlaceError, // Returned if there is an allow discouraged statement when there shouldn't be
     AllowIncompleteOutOfPlaceError, // Returned if there is an allow incomplete statement when there shouldn't be
+    AllowProofModificationOutOfPlaceError, // Returned if there is an allow proof modification statement when there shouldn't be
+    DeductionModeOutOfPlaceError, // Returned if there is a deduction mode statement when there shouldn't be
+    UnicodeInputOutOfPlaceError, // Returned if there is a unicode input statement when there shouldn't be
     DistinctVarOutOfPlaceError, // Returned if there is a distinct variable statement when there shouldn't be
     LocateAfterOutOfPlaceError, // Returned if there is a locate after statement when there shouldn't be
     ProofLinesOutOfPlaceError,  // Returned if there are proof lines when there shouldn't be
@@ -326,6 +487,7 @@ pub enum Error {
     UnificationError, // Returned from unification algorithm when a line can't be unified or from the unification command
     SyntaxTheoremUsedError, // Returned if a step ref references a syntax theorem
     DiscouragedTheoremUsedError, // Returned if a step ref references an discouraged theorem without $allowdiscouraged being present
+    ProofModificationDiscouragedError, // Returned if an existing theorem whose proof modification is discouraged is edited without $allowproofmodification being present
     IncompleteTheoremUsedError, // Returned if a step ref references an (recursively) incomplete theorem without $allowincomplete being present
     MultipleProofStatementsError, // Returned if there are multiple proof ($=) statements
     NotAConstantError, // Returned if there is an $locateafterconst statement which parameter is not a constant
@@ -348,7 +510,37 @@ pub enum Error {
     MmpFileEmptyError,      // Returned if you try to add an empty mmp file to the database
     DatabaseHasChangedError, // Returned if you try to add to the database, but the file content has changed since the database was opened
     UnfinishedTheoremError,  // Returned if you try to add an unfisined theorem to the database
-    AddingToInnerScopeError, // Returned if you try to add a statement between 2 statements sharing a scope
+    AddingToInnerScopeError, // Returned if you try to add a statement inside of a scope block that contains a header
+
+    // Database merge errors
+    MergeMissingDependencyError, // Returned if a theorem to merge references a label that neither exists in the open database nor is merged before it
+    MergeStatementChangedError, // Returned if a theorem to merge already exists in the open database with a different assertion, hypotheses or $d statements
+    MergeDependencyOrderError, // Returned if the new proof of a theorem that already exists in the open database references a theorem that comes after it
+    MergeProofModificationDiscouragedError, // Returned if a merge would replace the proof of a theorem whose proof modification is discouraged without allow_proof_modification being set
+
+    // Proof diff errors
+    ProofDiffAssertionMismatchError, // Returned if the theorems to compare have different hypotheses or assertions
+
+    // Work variable errors
+    NoDummyVariableAvailableError, // Returned if there is no unused variable left to replace a work variable with
+
+    // mmj2 worksheet conversion errors
+    Mmj2MissingHeaderError, // Returned if an mmj2 worksheet doesn't start with a "$( <MM> <PROOF_ASST> THEOREM=..." line
+    Mmj2UnsupportedStatementError, // Returned if an mmp file has no $theorem statement, has more than one theorem block or contains a statement that can't be expressed in an mmj2 worksheet, such as $axiom or $header
+
+    // Proof lint warnings
+    UnusedHypothesisWarning, // Returned if an essential hypothesis is never used in the proof
+    UnnecessaryDistinctVarWarning, // Returned if a $d statement contains a pair of variables the proof doesn't need to be distinct
+    MissingDistinctVarWarning, // Returned if the proof needs distinct variables that are not declared in a $d statement
+
+    // Style lint warnings
+    LabelNamingWarning, // Returned if a theorem label starts like an axiom or definition label, or an axiom label doesn't start with "ax-"
+    MissingDiscouragedTagWarning, // Returned if an "OLD" or "ALT" theorem is missing "(New usage is discouraged.)" or "(Proof modification is discouraged.)"
+    MalformedDiscouragedTagWarning, // Returned if a discouraged tag is misspelled, for example missing the parentheses or the period
+    MissingDescriptionWarning,      // Returned if a theorem or axiom has no description text
+    MissingContributedByWarning,    // Returned if a description has no "(Contributed by ...)" line
+    MalformedContributorLineWarning, // Returned if a "(Contributed by ...)", "(Revised by ...)" or "(Proof shortened by ...)" line doesn't have the form "(... by NAME, D-Mon-YYYY.)"
+    LineTooLongWarning, // Returned if a line of a description is longer than the maximum line length
 
     TestError, // Used to replace InternalLogicErrors to find out where they come from
 }
@@ -368,6 +560,21 @@ impl serde::Serialize for Error {
     }
 }
 
+impl Serialize for ProofFormatOption {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.serialize_str(match self {
+            ProofFormatOption::Uncompressed => "uncompressed",
+            ProofFormatOption::Compressed => "compressed",
+            ProofFormatOption::CompressedNormalized => "compressedNormalized",
+            ProofFormatOption::Explicit => "explicit",
+            ProofFormatOption::Packed => "packed",
+        })
+    }
+}
+
 impl<'de> Deserialize<'de> for ProofFormatOption {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -376,8 +583,11 @@ impl<'de> Deserialize<'de> for ProofFormatOption {
         match String::deserialize(deserializer)?.as_str() {
             "uncompressed" => Ok(ProofFormatOption::Uncompressed),
             "compressed" => Ok(ProofFormatOption::Compressed),
+            "compressedNormalized" => Ok(ProofFormatOption::CompressedNormalized),
+            "explicit" => Ok(ProofFormatOption::Explicit),
+            "packed" => Ok(ProofFormatOption::Packed),
             _ => Err(serde::de::Error::custom(
-                "Expected uncompressed or compressed for proofFormat",
+                "Expected uncompressed, compressed, compressedNormalized, explicit or packed for proofFormat",
             )),
         }
     }
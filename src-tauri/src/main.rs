@@ -1,6 +1,114 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use mmt1_lib::ExportFormattingSettings;
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.get(1).is_some_and(|arg| arg == "check-normalized") {
+        attach_to_parent_console();
+        std::process::exit(check_normalized(&args[2..]));
+    }
+
+    if args.get(1).is_some_and(|arg| arg == "explain-unification") {
+        attach_to_parent_console();
+        std::process::exit(explain_unification(&args[2..]));
+    }
+
     mmt1_lib::run()
 }
+
+// Release builds on Windows don't get a console of their own because of the windows subsystem, so the command line
+// paths attach to the console of the calling shell to show their output. Redirected output works without it. Shells
+// don't wait for programs of the windows subsystem, so scripts should use `start /wait` (cmd) or
+// `Start-Process -Wait` (PowerShell) to get the exit code
+#[cfg(windows)]
+fn attach_to_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    // Fails if the process already has a console, like in debug builds, or if there is no parent console
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_to_parent_console() {}
+
+// mmt1 check-normalized <database> [export formatting json]
+// Exits with 1 if the database is not written the way it would be exported, so CI can enforce the formatting. The
+// export formatting is read from a JSON file like the "exportFormatting" setting and defaults to the app's default
+fn check_normalized(args: &[String]) -> i32 {
+    let Some(file_path) = args.first() else {
+        eprintln!("Usage: mmt1 check-normalized <database> [export formatting json]");
+        return 2;
+    };
+
+    let formatting = match args.get(1) {
+        Some(formatting_path) => match std::fs::read_to_string(formatting_path)
+            .ok()
+            .and_then(|formatting_json| serde_json::from_str(&formatting_json).ok())
+        {
+            Some(formatting) => formatting,
+            None => {
+                eprintln!("Could not read export formatting from {}", formatting_path);
+                return 2;
+            }
+        },
+        None => ExportFormattingSettings::default(),
+    };
+
+    match mmt1_lib::check_database_file_normalized(file_path, &formatting) {
+        Ok(None) => {
+            println!("{} is normalized", file_path);
+            0
+        }
+        Ok(Some(line_number)) => {
+            println!(
+                "{} is not normalized, starting at line {}",
+                file_path, line_number
+            );
+            1
+        }
+        Err(error) => {
+            eprintln!("Could not check {}: {}", file_path, error);
+            2
+        }
+    }
+}
+
+// mmt1 explain-unification <database> <mmp file>
+// Prints why the steps of the worksheet can't be proven by their step refs. Exits with 1 if there is such a step
+fn explain_unification(args: &[String]) -> i32 {
+    let (Some(database_path), Some(mmp_path)) = (args.first(), args.get(1)) else {
+        eprintln!("Usage: mmt1 explain-unification <database> <mmp file>");
+        return 2;
+    };
+
+    match mmt1_lib::explain_mmp_file_unifications(database_path, mmp_path) {
+        Ok(explanations) if explanations.is_empty() => {
+            println!("All steps of {} unify with their step refs", mmp_path);
+            0
+        }
+        Ok(explanations) => {
+            for (line_number, explanation) in explanations {
+                println!(
+                    "{}:{}: step does not unify with its step ref",
+                    mmp_path, line_number
+                );
+                print!("{}", explanation);
+            }
+            1
+        }
+        Err(error) => {
+            eprintln!("Could not explain {}: {}", mmp_path, error);
+            2
+        }
+    }
+}
@@ -4,20 +4,32 @@ use std::{
     path::PathBuf,
 };
 
-use tauri::async_runtime::Mutex;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use tauri::{async_runtime::Mutex, AppHandle};
 
 use crate::{
+    metamath::{
+        mm_parser,
+        mmp_parser::stage_6,
+        verify::{self, ProofStep, StepResult, Verifier, VerifierCreationResult},
+    },
     model::{
-        DatabaseElement, Header, HeaderRepresentation, MetamathData, OptimizedMetamathData,
-        SymbolNumberMapping,
+        DatabaseElement, FloatingHypothesis, Header, HeaderRepresentation, MetamathData,
+        OptimizedMetamathData, OriginalSource, ScopeStatement, Statement, SymbolNumberMapping,
+        Theorem, VerificationProofSteps,
     },
-    util::{self, earley_parser_optimized::Grammar},
-    AppState, Error,
+    session,
+    util::{self, earley_parser_optimized::Grammar, unicode_input::UnicodeInputMapping},
+    AppState, Error, ExportFormattingSettings, ProofFormatOption, Settings,
 };
 
+// Line length that text is wrapped at when no export formatting is given
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 80;
+
 #[tauri::command]
 pub async fn new_database(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
     file_path: &str,
 ) -> Result<(HeaderRepresentation, u32), Error> {
     let mut app_state = state.lock().await;
@@ -50,12 +62,15 @@ pub async fn new_database(
             header_data: HashMap::new(),
             symbol_number_mapping: SymbolNumberMapping::default(),
             grammar: Grammar::default(),
+            constant_usage_counts: HashMap::new(),
+            unicode_input_mapping: UnicodeInputMapping::default(),
         },
         database_path: file_path.to_string(),
         grammar_calculations_done: true,
         syntax_typecodes: Vec::new(),
         logical_typecodes: Vec::new(),
         variable_colors: Vec::new(),
+        original_source: None,
     };
 
     let header_rep = metamath_data.database_header.to_representation();
@@ -63,51 +78,476 @@ pub async fn new_database(
 
     app_state.metamath_data = Some(metamath_data);
 
+    session::add_recent_database(&app, file_path);
+    session::save_session(&app, &app_state);
+
     Ok((header_rep, database_id))
 }
 
+// If a proof format is given, all complete and valid proofs are converted to it. Other proofs are exported unchanged.
+// Unless normalize is set, statements that were not changed since the database was loaded keep their original text
 #[tauri::command]
 pub async fn export_database(
     state: tauri::State<'_, Mutex<AppState>>,
     file_path: &str,
+    proof_format: Option<ProofFormatOption>,
+    normalize: bool,
 ) -> Result<(), Error> {
     let app_state = state.lock().await;
     let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
 
-    let database_string = calc_database_string(mm_data);
+    let converted_proofs = match proof_format {
+        Some(proof_format) => calc_converted_proofs(mm_data, proof_format)?,
+        None => HashMap::new(),
+    };
+
+    let database_string = calc_database_string(
+        mm_data,
+        &converted_proofs,
+        &app_state.settings.export_formatting,
+        if normalize {
+            None
+        } else {
+            mm_data.original_source.as_ref()
+        },
+    );
 
     fs::write(file_path, database_string).or(Err(Error::FileWriteError))?;
 
     Ok(())
 }
 
-fn calc_database_string(metamath_data: &MetamathData) -> String {
-    let mut res = String::new();
+// Checks whether the file of the database is written exactly the way it would be exported. Returns the first line
+// (starting at 1) that differs from the exported text, or None if the file is already normalized
+#[tauri::command]
+pub async fn check_database_normalized(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<Option<u32>, Error> {
+    let app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
 
-    for element in metamath_data.database_header.iter() {
-        match element {
-            DatabaseElement::Header(header, depth) => {
-                res.push_str("$(\n");
-                res.push_str(header_line(depth));
-                res.push_str("\n  ");
-                res.push_str(&header.title);
-                res.push('\n');
-                res.push_str(header_line(depth));
+    let file_content = fs::read_to_string(&mm_data.database_path).or(Err(Error::FileReadError))?;
+
+    let database_string = calc_database_string(
+        mm_data,
+        &HashMap::new(),
+        &app_state.settings.export_formatting,
+        None,
+    );
+
+    Ok(first_differing_line(&file_content, &database_string))
+}
+
+// Same check for a database file that is not open in the app, so that CI can enforce the formatting
+pub fn check_database_file_normalized(
+    file_path: &str,
+    formatting: &ExportFormattingSettings,
+) -> Result<Option<u32>, Error> {
+    let mm_data = mm_parser::load_metamath_data(file_path, 0, &Settings::default())?;
+
+    let file_content = fs::read_to_string(file_path).or(Err(Error::FileReadError))?;
+
+    let database_string = calc_database_string(&mm_data, &HashMap::new(), formatting, None);
+
+    Ok(first_differing_line(&file_content, &database_string))
+}
+
+fn first_differing_line(text: &str, normalized_text: &str) -> Option<u32> {
+    let mut lines = text.lines();
+    let mut normalized_lines = normalized_text.lines();
+    let mut line_number: u32 = 1;
+
+    loop {
+        match (lines.next(), normalized_lines.next()) {
+            (None, None) => return None,
+            (Some(line), Some(normalized_line)) if line == normalized_line => {}
+            _ => return Some(line_number),
+        }
+        line_number += 1;
+    }
+}
+
+fn calc_converted_proofs(
+    mm_data: &MetamathData,
+    proof_format: ProofFormatOption,
+) -> Result<HashMap<&str, String>, Error> {
+    let VerificationProofSteps {
+        proof_steps,
+        theorems,
+    } = mm_data.calc_verification_proof_steps(None, None, &mut 0)?;
+
+    let step_hypotheses: HashMap<&str, Vec<String>> =
+        if matches!(proof_format, ProofFormatOption::Explicit) {
+            theorems
+                .iter()
+                .map(|(theorem, _, hypotheses)| {
+                    (
+                        &*theorem.label,
+                        hypotheses
+                            .iter()
+                            .map(|hyp| {
+                                hyp.label
+                                    .as_ref()
+                                    .map(|l| l.to_string())
+                                    .unwrap_or_else(|l| l.clone())
+                            })
+                            .collect(),
+                    )
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+    let converted_proofs: Vec<Option<(&str, String)>> = theorems
+        .into_par_iter()
+        .map(
+            |(theorem, prev_floating_hypotheses_num, compressed_infered_proof_steps)| {
+                // Theorems marked with "(Proof modification is discouraged.)" keep their original proof
+                if theorem.proof.is_none()
+                    || mm_data
+                        .optimized_data
+                        .theorem_data
+                        .get(&theorem.label)
+                        .is_some_and(|theorem_data| theorem_data.is_proof_modification_discouraged)
+                {
+                    return Ok(None);
+                }
+
+                Ok(convert_proof(
+                    theorem,
+                    mm_data,
+                    proof_format,
+                    &step_hypotheses,
+                    &proof_steps,
+                    &mm_data.optimized_data.floating_hypotheses[..prev_floating_hypotheses_num],
+                    compressed_infered_proof_steps,
+                )?
+                .map(|proof| (&*theorem.label, proof)))
+            },
+        )
+        .collect::<Result<Vec<Option<(&str, String)>>, Error>>()?;
+
+    Ok(converted_proofs.into_iter().flatten().collect())
+}
+
+// Returns None if the proof is incomplete or invalid
+fn convert_proof<'a>(
+    theorem: &'a Theorem,
+    mm_data: &'a MetamathData,
+    proof_format: ProofFormatOption,
+    step_hypotheses: &HashMap<&str, Vec<String>>,
+    proof_steps: &'a HashMap<&'a str, ProofStep<'a>>,
+    prev_floating_hypotheses: &'a [FloatingHypothesis],
+    compressed_infered_proof_steps: Vec<ProofStep<'a>>,
+) -> Result<Option<String>, Error> {
+    let mandatory_hypotheses: Vec<String> = compressed_infered_proof_steps
+        .iter()
+        .map(|step| {
+            step.label
+                .as_ref()
+                .map(|l| l.to_string())
+                .unwrap_or_else(|l| l.clone())
+        })
+        .collect();
+
+    let mut verifier = match Verifier::new(
+        theorem,
+        mm_data,
+        verify::Show::None,
+        None,
+        Some(proof_steps),
+        Some(prev_floating_hypotheses),
+        Some(compressed_infered_proof_steps),
+    ) {
+        Ok(VerifierCreationResult::Verifier(v)) => v,
+        Ok(_) | Err(Error::InvalidProofError) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let proof_tree = match verifier.calc_proof_tree() {
+        Ok(proof_tree) => proof_tree,
+        Err(Error::InvalidProofError) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let proof = stage_6::write_proof(
+        &proof_tree,
+        proof_format,
+        &mandatory_hypotheses
+            .iter()
+            .map(|h| h.as_str())
+            .collect::<Vec<&str>>(),
+        step_hypotheses,
+    )?;
+
+    loop {
+        match verifier.proccess_next_step(mm_data) {
+            Ok(StepResult::VerifierFinished) => break,
+            Ok(_) => {}
+            Err(Error::InvalidProofError) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+
+    let stack = verifier.get_stack();
+    if stack.len() != 1
+        || stack
+            .last()
+            .is_none_or(|sl| sl.statement != theorem.assertion)
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(proof))
+}
+
+// If the original source is given, elements that have not changed since the database was loaded are written exactly
+// as they were in the file. All other elements are written with the given formatting
+pub fn calc_database_string(
+    metamath_data: &MetamathData,
+    converted_proofs: &HashMap<&str, String>,
+    formatting: &ExportFormattingSettings,
+    original_source: Option<&OriginalSource>,
+) -> String {
+    let mut statement_seperator = String::from("\n");
+    for _ in 0..formatting.blank_lines_between_statements {
+        statement_seperator.push('\n');
+    }
+
+    let variable_order: HashMap<&str, usize> = metamath_data
+        .optimized_data
+        .floating_hypotheses
+        .iter()
+        .enumerate()
+        .map(|(i, fh)| (fh.variable.as_str(), i))
+        .collect();
+
+    let mut res = String::new();
+    let mut scope_depth: u32 = 0;
+
+    let Some(original_source) = original_source else {
+        for element in metamath_data.database_header.iter() {
+            write_database_element(
+                &mut res,
+                element,
+                converted_proofs,
+                formatting,
+                &variable_order,
+                &mut scope_depth,
+            );
+            // Statements of a block are not seperated by blank lines
+            if scope_depth == 0 {
+                res.push_str(&statement_seperator);
+            } else {
                 res.push('\n');
-                res.push_str("$)\n\n");
             }
-            DatabaseElement::Statement(statement) => {
-                statement.write_mm_string(&mut res);
-                res.push_str("\n\n");
+        }
+
+        return res;
+    };
+
+    let elements: Vec<DatabaseElement> = metamath_data.database_header.iter().collect();
+
+    // None for elements that are written differently than they were loaded, even if they didn't change
+    let fingerprints: Vec<Option<u64>> = elements
+        .iter()
+        .map(|element| match element {
+            DatabaseElement::Statement(Statement::TheoremStatement(theorem))
+                if converted_proofs.contains_key(&*theorem.label) =>
+            {
+                None
+            }
+            _ => Some(element.calc_fingerprint()),
+        })
+        .collect();
+
+    let mut chunks_by_first_fingerprint: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (chunk_i, chunk) in original_source.chunks.iter().enumerate() {
+        if let Some(&first_fingerprint) = chunk.element_fingerprints.first() {
+            chunks_by_first_fingerprint
+                .entry(first_fingerprint)
+                .or_default()
+                .push(chunk_i);
+        }
+    }
+
+    let chunk_matches_at = |chunk_i: usize, element_i: usize| -> bool {
+        let chunk_fingerprints = &original_source.chunks[chunk_i].element_fingerprints;
+
+        fingerprints
+            .get(element_i..element_i + chunk_fingerprints.len())
+            .is_some_and(|element_fingerprints| {
+                element_fingerprints
+                    .iter()
+                    .zip(chunk_fingerprints)
+                    .all(|(ef, cf)| *ef == Some(*cf))
+            })
+    };
+
+    let mut next_chunk_i: usize = 0;
+    let mut element_i: usize = 0;
+
+    while element_i < elements.len() {
+        // Parts of the file without any elements, like empty scope blocks
+        while original_source
+            .chunks
+            .get(next_chunk_i)
+            .is_some_and(|chunk| chunk.element_fingerprints.is_empty())
+        {
+            write_source_chunk(&mut res, original_source, next_chunk_i);
+            next_chunk_i += 1;
+        }
+
+        // Chunks that are skipped belonged to elements that were deleted or changed
+        let matching_chunk_i = if next_chunk_i < original_source.chunks.len()
+            && chunk_matches_at(next_chunk_i, element_i)
+        {
+            Some(next_chunk_i)
+        } else {
+            fingerprints[element_i]
+                .and_then(|fingerprint| chunks_by_first_fingerprint.get(&fingerprint))
+                .and_then(|chunk_indices| {
+                    let first_candidate = chunk_indices.partition_point(|&ci| ci < next_chunk_i);
+                    chunk_indices[first_candidate..]
+                        .iter()
+                        .copied()
+                        .find(|&ci| chunk_matches_at(ci, element_i))
+                })
+        };
+
+        match matching_chunk_i {
+            Some(chunk_i) => {
+                write_source_chunk(&mut res, original_source, chunk_i);
+                for element in elements
+                    .iter()
+                    .skip(element_i)
+                    .take(original_source.chunks[chunk_i].element_fingerprints.len())
+                {
+                    scope_depth = calc_scope_depth_after(element, scope_depth);
+                }
+                element_i += original_source.chunks[chunk_i].element_fingerprints.len();
+                next_chunk_i = chunk_i + 1;
+            }
+            None => {
+                if !res.is_empty() {
+                    if scope_depth == 0 {
+                        res.push_str(&statement_seperator);
+                    } else {
+                        res.push('\n');
+                    }
+                }
+                write_database_element(
+                    &mut res,
+                    elements[element_i],
+                    converted_proofs,
+                    formatting,
+                    &variable_order,
+                    &mut scope_depth,
+                );
+                element_i += 1;
             }
         }
     }
 
+    if next_chunk_i == original_source.chunks.len() {
+        res.push_str(&original_source.file_content[original_source.trailing_text_start..]);
+    }
+
+    if !res.is_empty() && !res.ends_with('\n') {
+        res.push('\n');
+    }
+
     res
 }
 
-pub fn write_text_wrapped(target: &mut String, text: &str, line_prefix: &str) {
-    let max_line_length = 80;
+fn write_source_chunk(target: &mut String, original_source: &OriginalSource, chunk_i: usize) {
+    let chunk = &original_source.chunks[chunk_i];
+    target.push_str(&original_source.file_content[chunk.start..chunk.end]);
+}
+
+fn calc_scope_depth_after(element: &DatabaseElement, scope_depth: u32) -> u32 {
+    match element {
+        DatabaseElement::Statement(Statement::ScopeStatement(ScopeStatement::OpeningScope)) => {
+            scope_depth + 1
+        }
+        DatabaseElement::Statement(Statement::ScopeStatement(ScopeStatement::ClosingScope)) => {
+            scope_depth.saturating_sub(1)
+        }
+        _ => scope_depth,
+    }
+}
+
+// Updates scope_depth to the depth after the element. Opening statements of blocks are written with the depth before
+// them and closing statements with the depth after them
+fn write_database_element(
+    target: &mut String,
+    element: DatabaseElement,
+    converted_proofs: &HashMap<&str, String>,
+    formatting: &ExportFormattingSettings,
+    variable_order: &HashMap<&str, usize>,
+    scope_depth: &mut u32,
+) {
+    let depth_before = *scope_depth;
+    *scope_depth = calc_scope_depth_after(&element, depth_before);
+    let write_depth = std::cmp::min(depth_before, *scope_depth);
+
+    match element {
+        DatabaseElement::Header(header, depth) => {
+            let line_width = formatting.line_width as usize;
+            target.push_str("$(\n");
+            Header::write_header_banner_string(target, depth, line_width).ok();
+            target.push_str("\n  ");
+            target.push_str(&header.title);
+            target.push('\n');
+            Header::write_header_banner_string(target, depth, line_width).ok();
+            target.push('\n');
+            target.push_str("$)");
+        }
+        DatabaseElement::Statement(Statement::TheoremStatement(theorem))
+            if converted_proofs.contains_key(&*theorem.label)
+                || calc_grouped_distincts(&theorem.distincts, variable_order)
+                    != theorem.distincts =>
+        {
+            let mut converted_theorem = theorem.clone();
+            if let Some(converted_proof) = converted_proofs.get(&*theorem.label) {
+                converted_theorem.proof = Some(converted_proof.clone());
+            }
+            converted_theorem.distincts =
+                calc_grouped_distincts(&theorem.distincts, variable_order);
+            Statement::TheoremStatement(converted_theorem).write_mm_string_in_scope(
+                target,
+                formatting,
+                write_depth,
+            );
+        }
+        DatabaseElement::Statement(statement) => {
+            statement.write_mm_string_in_scope(target, formatting, write_depth);
+        }
+    }
+}
+
+// Groups the distinct variable conditions of a theorem into as few $d statements as possible
+fn calc_grouped_distincts(
+    distincts: &Vec<String>,
+    variable_order: &HashMap<&str, usize>,
+) -> Vec<String> {
+    util::group_distinct_variable_pairs(
+        &util::calc_distinct_variable_pairs(distincts),
+        &HashSet::new(),
+        variable_order,
+    )
+    .into_iter()
+    .map(|group| group.join(" "))
+    .collect()
+}
+
+pub fn write_text_wrapped(
+    target: &mut String,
+    text: &str,
+    line_prefix: &str,
+    max_line_length: usize,
+) {
     let mut curr_line_length = last_line_length(&target);
 
     for token in text.split_ascii_whitespace() {
@@ -124,8 +564,12 @@ pub fn write_text_wrapped(target: &mut String, text: &str, line_prefix: &str) {
     }
 }
 
-pub fn write_text_wrapped_no_whitespace(target: &mut String, text: &str, line_prefix: &str) {
-    let max_line_length = 80;
+pub fn write_text_wrapped_no_whitespace(
+    target: &mut String,
+    text: &str,
+    line_prefix: &str,
+    max_line_length: usize,
+) {
     let mut curr_line_length = last_line_length(&target);
 
     for char in text.chars() {
@@ -141,8 +585,12 @@ pub fn write_text_wrapped_no_whitespace(target: &mut String, text: &str, line_pr
     }
 }
 
-pub fn write_text_wrapped_maintain_paragraphs(target: &mut String, text: &str, line_prefix: &str) {
-    let max_line_length = 80;
+pub fn write_text_wrapped_maintain_paragraphs(
+    target: &mut String,
+    text: &str,
+    line_prefix: &str,
+    max_line_length: usize,
+) {
     let mut curr_line_length = last_line_length(&target);
 
     let mut i: usize = 0;
@@ -213,13 +661,3 @@ fn last_line_length(text: &str) -> usize {
 
     len
 }
-
-fn header_line(depth: u32) -> &'static str {
-    match depth {
-        1 => "###############################################################################",
-        2 => "#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#*#",
-        3 => "=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=",
-        4 => "-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-.-",
-        _ => "",
-    }
-}
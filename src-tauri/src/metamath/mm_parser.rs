@@ -11,10 +11,12 @@ use crate::{
     model::{
         ColorInformation, Comment, Constant, FloatingHypothesis, Header, HeaderPath,
         HeaderRepresentation, HtmlRepresentation, Hypothesis, LogicalTypecode, MetamathData,
-        OptimizedMetamathData, Statement, SymbolNumberMapping, SyntaxTypecode, Theorem,
-        TheoremParseTrees, Variable, VariableColor,
+        OptimizedMetamathData, OriginalSource, ScopeStatement, SourceChunk, Statement,
+        SymbolNumberMapping, SyntaxTypecode, Theorem, TheoremParseTrees, Variable, VariableColor,
     },
-    util::{self, earley_parser_optimized::Grammar},
+    session,
+    settings::{self, SettingsOverrides},
+    util::{self, earley_parser_optimized::Grammar, unicode_input::UnicodeInputMapping},
     AppState, Error, Settings,
 };
 
@@ -40,7 +42,10 @@ pub async fn open_metamath_database(
     let database_id = app_state.id_manager.get_next_id();
     app_state.stop_temp_database_calculations = Arc::new(std::sync::Mutex::new(false));
     let stop = app_state.stop_temp_database_calculations.clone();
-    let settings = app_state.settings.clone();
+    let settings_overrides = settings::load_database_settings_overrides(mm_file_path)?;
+    let settings = app_state
+        .global_settings
+        .with_overrides(&settings_overrides);
     drop(app_state);
 
     let mut mm_parser = MmParser::new(mm_file_path, Some(app), Some(stop))?;
@@ -50,6 +55,7 @@ pub async fn open_metamath_database(
 
     let mut app_state = state.lock().await;
     app_state.temp_metamath_data = Some(metamath_data);
+    app_state.temp_database_settings_overrides = settings_overrides;
 
     Ok((
         database_id,
@@ -77,6 +83,7 @@ pub async fn cancel_open_metamath_database(
 #[tauri::command]
 pub async fn confirm_open_metamath_database(
     state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
 ) -> Result<
     (
         u32,
@@ -104,14 +111,24 @@ pub async fn confirm_open_metamath_database(
 
     let color_information = metamath_data.calc_color_information(true);
 
+    session::add_recent_database(&app, &metamath_data.database_path);
+
     app_state.metamath_data = Some(metamath_data);
 
+    app_state.database_settings_overrides =
+        std::mem::take(&mut app_state.temp_database_settings_overrides);
+    app_state.settings = app_state
+        .global_settings
+        .with_overrides(&app_state.database_settings_overrides);
+
     *app_state
         .stop_database_calculations
         .lock()
         .or(Err(Error::InternalLogicError))? = true;
     app_state.stop_database_calculations = app_state.stop_temp_database_calculations.clone();
 
+    session::save_session(&app, &app_state);
+
     Ok((
         database_id,
         theorem_amount,
@@ -156,10 +173,14 @@ pub async fn perform_grammar_calculations(
     let syntax_typecodes = mm_data.syntax_typecodes.clone();
     let logical_typecodes = mm_data.logical_typecodes.clone();
     let floating_hypotheses = mm_data.optimized_data.floating_hypotheses.clone();
+    let html_representations = mm_data.html_representations.clone();
 
     drop(app_state);
 
     let symbol_number_mapping = SymbolNumberMapping::calc_mapping(&database_header);
+    let constant_usage_counts = database_header.calc_constant_usage_counts();
+    let unicode_input_mapping =
+        UnicodeInputMapping::new(&symbol_number_mapping, &html_representations);
 
     let Some((grammar, parse_trees)) = Grammar::calc_grammar_and_parse_trees(
         &database_header,
@@ -202,6 +223,8 @@ pub async fn perform_grammar_calculations(
     mm_data.grammar_calculations_done = true;
     mm_data.optimized_data.symbol_number_mapping = symbol_number_mapping;
     mm_data.optimized_data.grammar = grammar;
+    mm_data.optimized_data.constant_usage_counts = constant_usage_counts;
+    mm_data.optimized_data.unicode_input_mapping = unicode_input_mapping;
     for (label, assertion_parsed, hypotheses_parsed) in parse_trees {
         mm_data
             .optimized_data
@@ -229,9 +252,73 @@ pub async fn close_metamath_database(
         .map_err(|_| Error::InternalLogicError)? = true;
     app_state.metamath_data = None;
 
+    app_state.database_settings_overrides = SettingsOverrides::default();
+    app_state.settings = app_state.global_settings.clone();
+
     Ok(())
 }
 
+// Parses a database that is only used for comparisons, without grammar calculations and without reporting progress
+pub fn load_metamath_data(
+    file_path: &str,
+    database_id: u32,
+    settings: &Settings,
+) -> Result<MetamathData, Error> {
+    let mut mm_parser = MmParser::new(file_path, None, None)?;
+    mm_parser.process_all_statements()?;
+    let (metamath_data, _, _, _) =
+        mm_parser.consume_early_before_grammar_calculations(database_id, settings)?;
+
+    Ok(metamath_data)
+}
+
+// Parses a database and performs the grammar calculations right away, for use outside of the app
+pub fn load_metamath_data_with_grammar(
+    file_path: &str,
+    settings: &Settings,
+) -> Result<MetamathData, Error> {
+    let mut mm_data = load_metamath_data(file_path, 0, settings)?;
+
+    let symbol_number_mapping = SymbolNumberMapping::calc_mapping(&mm_data.database_header);
+
+    let Some((grammar, parse_trees)) = Grammar::calc_grammar_and_parse_trees(
+        &mm_data.database_header,
+        &symbol_number_mapping,
+        &mm_data.optimized_data.floating_hypotheses,
+        &mm_data.syntax_typecodes,
+        &mm_data.logical_typecodes,
+        mm_data.optimized_data.theorem_amount,
+        mm_data.database_id,
+        None,
+        None,
+    )?
+    else {
+        return Err(Error::InternalLogicError);
+    };
+
+    for (label, assertion_parsed, hypotheses_parsed) in parse_trees {
+        mm_data
+            .optimized_data
+            .theorem_data
+            .get_mut(label)
+            .ok_or(Error::InternalLogicError)?
+            .parse_trees = Some(TheoremParseTrees {
+            assertion_parsed,
+            hypotheses_parsed,
+        })
+    }
+
+    mm_data.optimized_data.unicode_input_mapping =
+        UnicodeInputMapping::new(&symbol_number_mapping, &mm_data.html_representations);
+    mm_data.optimized_data.constant_usage_counts =
+        mm_data.database_header.calc_constant_usage_counts();
+    mm_data.optimized_data.symbol_number_mapping = symbol_number_mapping;
+    mm_data.optimized_data.grammar = grammar;
+    mm_data.grammar_calculations_done = true;
+
+    Ok(mm_data)
+}
+
 pub struct MmParser {
     file_content: String,
     next_token_i: usize,
@@ -264,6 +351,12 @@ pub struct MmParser {
     invalid_html: Vec<HtmlRepresentation>,
     html_allowed_tags_and_attributes: HashMap<String, HashSet<String>>,
     css_allowed_properties: HashSet<String>,
+    // Start of the part of the file that belongs to the next top level statement, header or scope block
+    source_chunk_start: usize,
+    // Number of database elements added since source_chunk_start
+    source_chunk_elements: usize,
+    // Start, end and number of database elements of all finished parts of the file
+    source_chunks: Vec<(usize, usize, usize)>,
     app: Option<AppHandle>,
     // Only used when using the process_all_statements or consume_early_before_grammar_calculations functions
     stop: Option<Arc<std::sync::Mutex<bool>>>,
@@ -341,6 +434,9 @@ impl MmParser {
             invalid_html: Vec::new(),
             html_allowed_tags_and_attributes,
             css_allowed_properties,
+            source_chunk_start: 0,
+            source_chunk_elements: 0,
+            source_chunks: Vec::new(),
             app,
             stop,
         })
@@ -361,7 +457,7 @@ impl MmParser {
                     comment_processed = true;
                     self.process_comment_statement()?
                 }
-                "${" => self.process_opening_scope_statement(),
+                "${" => self.process_opening_scope_statement()?,
                 "$}" => self.process_closing_scope_statement()?,
                 "$c" => self.process_constant_statement()?,
                 "$v" => self.process_variable_statement()?,
@@ -417,6 +513,10 @@ impl MmParser {
                 self.next_description = None;
             }
 
+            if self.scope == 0 && self.next_label.is_none() {
+                self.end_source_chunk();
+            }
+
             if let Some(ref app_handle) = self.app {
                 // Should always produce value between 0 and 100
                 let curr_progress = ((self.curr_line_amount * 100) / self.total_line_amount) as u8;
@@ -432,6 +532,18 @@ impl MmParser {
         }
     }
 
+    fn end_source_chunk(&mut self) {
+        if self.source_chunk_start < self.next_token_i {
+            self.source_chunks.push((
+                self.source_chunk_start,
+                self.next_token_i,
+                self.source_chunk_elements,
+            ));
+            self.source_chunk_start = self.next_token_i;
+            self.source_chunk_elements = 0;
+        }
+    }
+
     pub fn process_all_statements(&mut self) -> Result<(), Error> {
         let mut statements_processed = 0;
         while let Some(_) = self.process_next_statement()? {
@@ -503,10 +615,19 @@ impl MmParser {
             app_handle.emit("mm-parser-progress", 100).ok();
         }
 
+        let database_hash = util::str_to_hash_string(&self.file_content);
+
+        let original_source = calc_original_source(
+            &self.database_header,
+            self.file_content,
+            self.source_chunks,
+            self.source_chunk_start,
+        );
+
         let mut metamath_data = MetamathData {
             database_id,
             database_path: self.database_path,
-            database_hash: util::str_to_hash_string(&self.file_content),
+            database_hash,
             database_header: self.database_header,
             html_representations: self.html_representations,
             optimized_data: OptimizedMetamathData {
@@ -525,12 +646,15 @@ impl MmParser {
                 header_data: HashMap::new(),
                 symbol_number_mapping: SymbolNumberMapping::default(),
                 grammar: Grammar::default(),
+                constant_usage_counts: HashMap::new(),
+                unicode_input_mapping: UnicodeInputMapping::default(),
             },
             grammar_calculations_done: false,
             syntax_typecodes: self.syntax_typecodes,
             logical_typecodes: self.logical_typecodes,
             variable_colors: self.variable_colors,
             alt_variable_colors: self.alt_variable_colors,
+            original_source,
         };
 
         let invalid_description_html = metamath_data.calc_optimized_theorem_data(
@@ -626,11 +750,13 @@ impl MmParser {
         }
 
         self.next_description = Some(comment.clone());
-        if self.scope == 0 {
-            self.curr_header()?
-                .content
-                .push(Statement::CommentStatement(Comment { text: comment }));
-        }
+        let comment_statement = if self.scope == 0 {
+            Statement::CommentStatement(Comment { text: comment })
+        } else {
+            Statement::ScopeStatement(ScopeStatement::Comment(Comment { text: comment }))
+        };
+        self.curr_header()?.content.push(comment_statement);
+        self.source_chunk_elements += 1;
         Ok(StatementProcessed::CommentStatement)
     }
 
@@ -865,13 +991,14 @@ impl MmParser {
             });
             self.next_description = None;
             self.curr_header_path = next_header_path;
+            self.source_chunk_elements += 1;
             Ok(())
         } else {
             Err(Error::UnclosedHeaderError)
         }
     }
 
-    fn process_opening_scope_statement(&mut self) -> StatementProcessed {
+    fn process_opening_scope_statement(&mut self) -> Result<StatementProcessed, Error> {
         self.scope += 1;
         self.active_vars.push(HashSet::new());
         self.active_float_hyps.push(Vec::new());
@@ -879,7 +1006,9 @@ impl MmParser {
         self.active_hyps.push(Vec::new());
         self.temp_active_vars.push(Vec::new());
 
-        StatementProcessed::OpeningScopeStatement
+        self.push_scope_statement(ScopeStatement::OpeningScope)?;
+
+        Ok(StatementProcessed::OpeningScopeStatement)
     }
 
     fn process_closing_scope_statement(&mut self) -> Result<StatementProcessed, Error> {
@@ -907,9 +1036,20 @@ impl MmParser {
         self.active_hyps.pop();
         self.temp_active_vars.pop();
 
+        self.push_scope_statement(ScopeStatement::ClosingScope)?;
+
         Ok(StatementProcessed::ClosingScopeStatement)
     }
 
+    fn push_scope_statement(&mut self, scope_statement: ScopeStatement) -> Result<(), Error> {
+        self.curr_header()?
+            .content
+            .push(Statement::ScopeStatement(scope_statement));
+        self.source_chunk_elements += 1;
+
+        Ok(())
+    }
+
     fn process_constant_statement(&mut self) -> Result<StatementProcessed, Error> {
         if self.scope != 0 {
             return Err(Error::ConstStatementScopeError);
@@ -954,6 +1094,7 @@ impl MmParser {
         self.curr_header()?
             .content
             .push(Statement::ConstantStatement(constants));
+        self.source_chunk_elements += 1;
 
         Ok(StatementProcessed::ConstantStatement)
     }
@@ -1006,8 +1147,10 @@ impl MmParser {
             self.curr_header()?
                 .content
                 .push(Statement::VariableStatement(variables));
+            self.source_chunk_elements += 1;
         } else {
-            self.temp_active_vars[self.scope - 1].push(variables);
+            self.temp_active_vars[self.scope - 1].push(variables.clone());
+            self.push_scope_statement(ScopeStatement::Variables(variables))?;
         }
 
         Ok(StatementProcessed::VariableStatement)
@@ -1074,6 +1217,13 @@ impl MmParser {
                     typecode: typecode.clone(),
                     variable: variable.clone(),
                 }));
+            self.source_chunk_elements += 1;
+        } else {
+            self.push_scope_statement(ScopeStatement::FloatingHypothesis(FloatingHypothesis {
+                label: label.clone(),
+                typecode: typecode.clone(),
+                variable: variable.clone(),
+            }))?;
         }
 
         self.active_float_hyps[self.scope].push(FloatingHypothesis {
@@ -1111,6 +1261,13 @@ impl MmParser {
 
         let expression = self.advance_expression_until("$.")?;
 
+        if self.scope != 0 {
+            self.push_scope_statement(ScopeStatement::EssentialHypothesis(Hypothesis {
+                label: label.clone(),
+                expression: expression.clone(),
+            }))?;
+        }
+
         self.active_hyps[self.scope].push(Hypothesis { label, expression });
 
         Ok(StatementProcessed::EssentialHypothesisStatement)
@@ -1158,6 +1315,10 @@ impl MmParser {
             return Err(Error::ZeroOrOneSymbolDisjError);
         }
 
+        if self.scope != 0 {
+            self.push_scope_statement(ScopeStatement::DistinctVariables(dist.clone()))?;
+        }
+
         self.active_dists[self.scope].push(dist);
 
         Ok(StatementProcessed::DistinctVariableStatement)
@@ -1200,8 +1361,16 @@ impl MmParser {
 
         let description = match self.next_description.clone() {
             Some(d) => {
+                self.curr_header()?.content.pop();
+
                 if self.scope == 0 {
-                    self.curr_header()?.content.pop();
+                    // The description is written together with the theorem
+                    if let Some((start, _, elements)) = self.source_chunks.pop() {
+                        self.source_chunk_start = start;
+                        self.source_chunk_elements = elements - 1;
+                    }
+                } else {
+                    self.source_chunk_elements -= 1;
                 }
                 d
             }
@@ -1246,6 +1415,7 @@ impl MmParser {
                 assertion,
                 proof,
             }));
+        self.source_chunk_elements += 1;
         self.theorem_amount += 1;
 
         Ok(StatementProcessed::TheoremStatement)
@@ -1276,3 +1446,37 @@ impl MmParser {
         return self.scope;
     }
 }
+
+// Returns None if the parts of the file don't match the parsed database elements, in which case the database can only
+// be exported by writing every statement anew
+fn calc_original_source(
+    database_header: &Header,
+    file_content: String,
+    source_chunks: Vec<(usize, usize, usize)>,
+    trailing_text_start: usize,
+) -> Option<OriginalSource> {
+    let mut fingerprints = database_header.iter().map(|de| de.calc_fingerprint());
+
+    let chunks = source_chunks
+        .into_iter()
+        .map(|(start, end, elements)| {
+            let element_fingerprints: Vec<u64> = fingerprints.by_ref().take(elements).collect();
+
+            (element_fingerprints.len() == elements).then_some(SourceChunk {
+                start,
+                end,
+                element_fingerprints,
+            })
+        })
+        .collect::<Option<Vec<SourceChunk>>>()?;
+
+    if fingerprints.next().is_some() {
+        return None;
+    }
+
+    Some(OriginalSource {
+        file_content,
+        chunks,
+        trailing_text_start,
+    })
+}
@@ -1,15 +1,21 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
-    editor::on_edit::DetailedError,
-    model::{Comment, Constant, FloatingHypothesis, HeaderPath, MetamathData, ParseTree, Variable},
+    editor::on_edit::{DetailedError, UnificationExplanation},
+    model::{
+        Comment, Constant, FloatingHypothesis, HeaderPath, MetamathData, OptimizedTheoremData,
+        ParseTree, Theorem, Variable,
+    },
     Error, Settings,
 };
 
 pub mod calc_indention;
 mod stage_1;
 mod stage_2;
-mod stage_3;
+pub mod stage_3;
 mod stage_4;
 mod stage_5;
 pub mod stage_6;
@@ -18,6 +24,126 @@ pub fn new<'a>(text: &'a str) -> MmpParserStage0<'a> {
     MmpParserStage0 { text }
 }
 
+// Splits a worksheet into blocks that start at a line beginning with $theorem or $axiom, together with the number of
+// lines before each block. The comments directly before a label are the description of its theorem and start the
+// block, everything else before the second block belongs to the first one. If there are less than two labels, the
+// whole text is returned as a single block
+pub fn split_into_theorem_blocks(text: &str) -> Vec<(&str, u32)> {
+    let mut block_starts: Vec<(usize, u32)> = Vec::new();
+    // Start, line index and whether it is a comment, for every statement
+    let mut statement_starts: Vec<(usize, u32, bool)> = Vec::new();
+    let mut line_start: usize = 0;
+
+    for (line_i, line) in text.split_inclusive('\n').enumerate() {
+        if !line.starts_with(|c: char| c.is_ascii_whitespace()) {
+            if matches!(
+                line.split_ascii_whitespace().next(),
+                Some("$theorem") | Some("$axiom")
+            ) {
+                let block_start = statement_starts
+                    .iter()
+                    .rev()
+                    .take_while(|(_, _, is_comment)| *is_comment)
+                    .last()
+                    .map(|&(start, lines_before, _)| (start, lines_before))
+                    .unwrap_or((line_start, line_i as u32));
+
+                block_starts.push(block_start);
+            }
+
+            statement_starts.push((line_start, line_i as u32, line.starts_with('*')));
+        }
+
+        line_start += line.len();
+    }
+
+    if block_starts.len() < 2 {
+        return vec![(text, 0)];
+    }
+
+    block_starts[0] = (0, 0);
+
+    block_starts
+        .iter()
+        .enumerate()
+        .map(|(i, &(start, lines_before))| {
+            let end = block_starts
+                .get(i + 1)
+                .map(|(next_start, _)| *next_start)
+                .unwrap_or(text.len());
+
+            (&text[start..end], lines_before)
+        })
+        .collect()
+}
+
+// Appends a block to the text of a worksheet, separated from the previous block by one empty line
+pub fn push_theorem_block(text: &mut String, block: &str) {
+    if !text.is_empty() {
+        while text.ends_with(|c: char| c.is_ascii_whitespace()) {
+            text.pop();
+        }
+        text.push_str("\n\n");
+    }
+    text.push_str(block);
+}
+
+// The theorems of the earlier blocks of a worksheet. Later blocks can reference them without them being added to the
+// database, they are treated as if they were located after every theorem of the database
+#[derive(Default)]
+pub struct WorksheetTheorems {
+    theorems: Vec<Theorem>,
+    theorem_data: HashMap<String, OptimizedTheoremData>,
+}
+
+impl WorksheetTheorems {
+    // Theorems whose label is already taken, either in the database or by an earlier block, are skipped, since the
+    // existing theorem is the one later blocks reference
+    pub fn add_theorem(
+        &mut self,
+        mm_data: &MetamathData,
+        settings: &Settings,
+        theorem: Theorem,
+    ) -> Result<(), Error> {
+        if mm_data
+            .optimized_data
+            .theorem_data
+            .contains_key(&theorem.label)
+            || self.theorem_data.contains_key(&theorem.label)
+        {
+            return Ok(());
+        }
+
+        let theorem_data = mm_data.calc_optimized_theorem_data_of_unadded_theorem(
+            &theorem,
+            |label| self.theorem_data(mm_data, label),
+            settings,
+        )?;
+
+        self.theorem_data
+            .insert(theorem.label.clone(), theorem_data);
+        self.theorems.push(theorem);
+
+        Ok(())
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        self.theorem_data.contains_key(label)
+    }
+
+    pub fn theorem_data<'a>(
+        &'a self,
+        mm_data: &'a MetamathData,
+        label: &str,
+    ) -> Option<&'a OptimizedTheoremData> {
+        mm_data
+            .optimized_data
+            .theorem_data
+            .get(label)
+            .or_else(|| self.theorem_data.get(label))
+    }
+}
+
 pub struct MmpParserStage0<'a> {
     pub text: &'a str,
 }
@@ -60,6 +186,9 @@ pub struct MmpParserStage2Success<'a> {
     pub label: Option<MmpLabel<'a>>,
     pub allow_discouraged: bool,
     pub allow_incomplete: bool,
+    pub allow_proof_modification: bool,
+    pub deduction_mode: bool,
+    pub unicode_input: bool,
     pub locate_after: Option<LocateAfterRef<'a>>,
     pub distinct_vars: Vec<&'a str>,
     pub proof_lines: Vec<ProofLine<'a>>,
@@ -103,6 +232,9 @@ pub enum MmpStatement {
     DistinctVar,
     AllowDiscouraged,
     AllowIncomplete,
+    AllowProofModification,
+    DeductionMode,
+    UnicodeInput,
     LocateAfter,
     Constant,
     Variable,
@@ -122,7 +254,20 @@ impl<'a> MmpParserStage2Success<'a> {
         stage_1: &MmpParserStage1Success<'a>,
         mm_data: &MetamathData,
     ) -> Result<MmpParserStage3<'a>, Error> {
-        stage_3::stage_3(stage_1, self, mm_data)
+        stage_3::stage_3(stage_1, self, mm_data, None)
+    }
+
+    // Like next_stage, but the block can also reference the theorems of the earlier blocks of its worksheet
+    pub fn next_stage_in_worksheet<'b>(
+        &self,
+        stage_1: &MmpParserStage1Success<'a>,
+        mm_data: &MetamathData,
+        worksheet_theorems: &'b WorksheetTheorems,
+    ) -> Result<MmpParserStage3<'b>, Error>
+    where
+        'a: 'b,
+    {
+        stage_3::stage_3(stage_1, self, mm_data, Some(worksheet_theorems))
     }
 }
 
@@ -167,6 +312,7 @@ pub struct MmpParserStage3Theorem<'a> {
     // pub indention: Vec<u32>,
     pub axiom_dependencies: Vec<(String, u32)>,
     pub definition_dependencies: Vec<(String, u32)>,
+    pub worksheet_theorems: Option<&'a WorksheetTheorems>,
 }
 
 pub struct MmpParserStage3Fail {
@@ -174,6 +320,50 @@ pub struct MmpParserStage3Fail {
 }
 
 impl<'a> MmpParserStage3Theorem<'a> {
+    // Theorems that can be referenced by the proof. The theorems of the earlier blocks of the worksheet come after the
+    // database, so they are only included if there is no locate-after or it refers to one of them
+    pub fn theorem_locate_after_iter<'b>(
+        &'b self,
+        mm_data: &'b MetamathData,
+        locate_after: Option<LocateAfterRef<'b>>,
+    ) -> impl Iterator<Item = &'b Theorem> {
+        let worksheet_theorems: &[Theorem] = self
+            .worksheet_theorems
+            .map(|worksheet_theorems| worksheet_theorems.theorems.as_slice())
+            .unwrap_or(&[]);
+
+        let worksheet_theorems_end = match locate_after {
+            None => worksheet_theorems.len(),
+            Some(LocateAfterRef::LocateAfter(label)) => worksheet_theorems
+                .iter()
+                .position(|theorem| theorem.label == label)
+                .map_or(0, |i| i + 1),
+            Some(_) => 0,
+        };
+
+        let database_locate_after = if worksheet_theorems_end == 0 {
+            locate_after
+        } else {
+            None
+        };
+
+        mm_data
+            .database_header
+            .theorem_locate_after_iter(database_locate_after)
+            .chain(worksheet_theorems[..worksheet_theorems_end].iter())
+    }
+
+    pub fn theorem_data<'b>(
+        &'b self,
+        mm_data: &'b MetamathData,
+        label: &str,
+    ) -> Option<&'b OptimizedTheoremData> {
+        match self.worksheet_theorems {
+            Some(worksheet_theorems) => worksheet_theorems.theorem_data(mm_data, label),
+            None => mm_data.optimized_data.theorem_data.get(label),
+        }
+    }
+
     pub fn next_stage(
         &self,
         stage_1: &MmpParserStage1Success,
@@ -225,9 +415,10 @@ impl MmpParserStage4Success {
         stage_2: &MmpParserStage2Success,
         stage_3: &MmpParserStage3Theorem,
         mm_data: &MetamathData,
+        settings: &Settings,
         stop: Option<Arc<std::sync::Mutex<bool>>>,
     ) -> Result<MmpParserStage5, Error> {
-        stage_5::stage_5(stage_2, stage_3, self, mm_data, stop)
+        stage_5::stage_5(stage_2, stage_3, self, mm_data, settings, stop)
     }
 }
 
@@ -235,6 +426,8 @@ impl MmpParserStage4Success {
 pub struct MmpParserStage5 {
     pub unify_result: Vec<UnifyLine>,
     pub unify_reference_numbers: Vec<Option<u32>>,
+    // Proof line index and explanation for every proof line that couldn't be unified with its step ref
+    pub unification_explanations: Vec<(usize, UnificationExplanation)>,
 }
 
 #[derive(Debug)]
@@ -261,6 +454,14 @@ impl MmpParserStage5 {
     ) -> Result<MmpParserStage6, Error> {
         stage_6::stage_6(stage_3, stage_4, self, mm_data, settings)
     }
+
+    pub fn calc_required_distinct_variable_pairs(
+        &self,
+        stage_3: &MmpParserStage3Theorem,
+        mm_data: &MetamathData,
+    ) -> Result<HashSet<(String, String)>, Error> {
+        stage_5::calc_required_distinct_variable_pairs(&self.unify_result, stage_3, mm_data)
+    }
 }
 
 pub struct MmpParserStage6 {
@@ -3,7 +3,9 @@ use crate::{editor::on_edit::DetailedError, Error};
 use super::{MmpParserStage0, MmpParserStage1, MmpParserStage1Fail, MmpParserStage1Success};
 
 pub fn stage_1<'a>(stage_0: &MmpParserStage0<'a>) -> Result<MmpParserStage1<'a>, Error> {
-    if !stage_0.text.is_ascii() {
+    // Non ascii characters are only allowed in the proof lines of files with a $unicodeinput statement, which is
+    // checked in stage 2
+    if !stage_0.text.is_ascii() && !has_unicode_input_statement(stage_0.text) {
         let text_end_pos = text_end_pos(stage_0.text);
 
         return Ok(MmpParserStage1::Fail(MmpParserStage1Fail {
@@ -13,6 +15,8 @@ pub fn stage_1<'a>(stage_0: &MmpParserStage0<'a>) -> Result<MmpParserStage1<'a>,
                 start_column: 1,
                 end_line_number: text_end_pos.0,
                 end_column: text_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             },
         }));
     }
@@ -50,6 +54,8 @@ pub fn stage_1<'a>(stage_0: &MmpParserStage0<'a>) -> Result<MmpParserStage1<'a>,
                 start_column: 1,
                 end_line_number: number_of_lines_before_first_statement,
                 end_column: last_line_length + 1,
+                explanation: None,
+                expected_symbols: None,
             },
         }));
     }
@@ -86,6 +92,13 @@ pub fn stage_1<'a>(stage_0: &MmpParserStage0<'a>) -> Result<MmpParserStage1<'a>,
     }))
 }
 
+fn has_unicode_input_statement(text: &str) -> bool {
+    text.lines().any(|line| {
+        line.strip_prefix("$unicodeinput")
+            .is_some_and(|rest| rest.chars().next().is_none_or(|c| c.is_whitespace()))
+    })
+}
+
 fn text_end_pos(text: &str) -> (u32, u32) {
     let mut column = 0;
     let mut line = 1;
@@ -8,6 +8,9 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
     let mut label: Option<MmpLabel> = None;
     let mut allow_discouraged: bool = false;
     let mut allow_incomplete: bool = false;
+    let mut allow_proof_modification: bool = false;
+    let mut deduction_mode: bool = false;
+    let mut unicode_input: bool = false;
     let mut locate_after: Option<LocateAfterRef> = None;
     let mut distinct_vars: Vec<&str> = Vec::new();
     let mut constants: Option<&str> = None;
@@ -28,6 +31,21 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
 
         let last_non_whitespace_pos = util::last_non_whitespace_pos(statement_str);
 
+        // Stage 1 only allows non ascii characters if there is a $unicodeinput statement
+        if !statement_str.is_ascii()
+            && (statement_str.starts_with('$') || statement_str.starts_with('*'))
+        {
+            errors.push(DetailedError {
+                error_type: Error::NonAsciiSymbolError,
+                start_line_number: current_line,
+                start_column: 1,
+                end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                end_column: last_non_whitespace_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
+            });
+        }
+
         match token_iter.next().ok_or(Error::InternalLogicError)? {
             "$c" => {
                 if constants.is_some() {
@@ -37,6 +55,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -49,6 +69,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -64,6 +86,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -83,6 +107,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line,
                         end_column: 3, // Length of "$f" + 1
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 } else if second_token.is_none() || third_token.is_none() {
                     let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
@@ -93,6 +119,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: second_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 } else if fourth_token.is_some() {
                     let fifth_token_start_pos = util::nth_token_start_pos(statement_str, 4);
@@ -103,6 +131,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: fifth_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 } else if !util::is_valid_label(first_token.ok_or(Error::InternalLogicError)?) {
                     let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
@@ -114,6 +144,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: second_token_start_pos.1,
                         end_line_number: current_line + second_token_end_pos.0 - 1,
                         end_column: second_token_end_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 } else {
                     floating_hypotheses.push(&statement_str[3..statement_str.len()]);
@@ -129,6 +161,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -145,6 +179,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                                 start_column: second_token_start_pos.1,
                                 end_line_number: current_line + second_token_end_pos.0 - 1,
                                 end_column: second_token_end_pos.1 + 1,
+                                explanation: None,
+                                expected_symbols: None,
                             });
                         }
                     } else {
@@ -157,6 +193,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: second_token_start_pos.1,
                             end_line_number: current_line + second_token_end_pos.0 - 1,
                             end_column: second_token_end_pos.1 + 1,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                     }
 
@@ -195,6 +233,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: 1,
                             end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                             end_column: last_non_whitespace_pos.1 + 1,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                         // Make sure label is set to Some(_) so that future label statements will be flagged as errors
                         // Since return_info is false, the content within Some(_) does not matter
@@ -207,6 +247,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                     // Make sure label is set to Some(_) so that future label statements will be flagged as errors
                     // Since return_info is false, the content within Some(_) does not matter
@@ -291,6 +333,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -305,6 +349,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: second_token_start_pos.1,
                             end_line_number: current_line + second_token_end_pos.0 - 1,
                             end_column: second_token_end_pos.1 + 1,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                     }
 
@@ -316,6 +362,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line,
                         end_column: 9, // Length of "$theorem" + 1
+                        explanation: None,
+                        expected_symbols: None,
                     });
                     // Make sure label is set to Some(_) so that future label statements will be flagged as errors
                     // Since return_info is false, the content within Some(_) does not matter
@@ -331,6 +379,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -344,6 +394,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -358,6 +410,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: second_token_start_pos.1,
                             end_line_number: current_line + second_token_end_pos.0 - 1,
                             end_column: second_token_end_pos.1 + 1,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                     }
 
@@ -369,6 +423,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line,
                         end_column: 9, // Length of "$theorem" + 1
+                        explanation: None,
+                        expected_symbols: None,
                     });
                     // Make sure label is set to Some(_) so that future label statements will be flagged as errors
                     // Since return_info is false, the content within Some(_) does not matter
@@ -384,6 +440,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -398,6 +456,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 } else {
                     distinct_vars.push(&statement_str[2..statement_str.len()]);
@@ -413,6 +473,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -427,6 +489,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: second_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -440,6 +504,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -454,11 +520,106 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: second_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
                 statements.push((MmpStatement::AllowIncomplete, current_line));
             }
+            "$allowproofmodification" => {
+                if allow_proof_modification {
+                    errors.push(DetailedError {
+                        error_type: Error::MultipleAllowProofModificationError,
+                        start_line_number: current_line,
+                        start_column: 1,
+                        end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                        end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
+                    });
+                }
+
+                allow_proof_modification = true;
+
+                if token_iter.next().is_some() {
+                    let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
+
+                    errors.push(DetailedError {
+                        error_type: Error::TokensAfterAllowProofModificationError,
+                        start_line_number: current_line + second_token_start_pos.0 - 1,
+                        start_column: second_token_start_pos.1,
+                        end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                        end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
+                    });
+                }
+
+                statements.push((MmpStatement::AllowProofModification, current_line));
+            }
+            "$deductionmode" => {
+                if deduction_mode {
+                    errors.push(DetailedError {
+                        error_type: Error::MultipleDeductionModeError,
+                        start_line_number: current_line,
+                        start_column: 1,
+                        end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                        end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
+                    });
+                }
+
+                deduction_mode = true;
+
+                if token_iter.next().is_some() {
+                    let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
+
+                    errors.push(DetailedError {
+                        error_type: Error::TokensAfterDeductionModeError,
+                        start_line_number: current_line + second_token_start_pos.0 - 1,
+                        start_column: second_token_start_pos.1,
+                        end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                        end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
+                    });
+                }
+
+                statements.push((MmpStatement::DeductionMode, current_line));
+            }
+            "$unicodeinput" => {
+                if unicode_input {
+                    errors.push(DetailedError {
+                        error_type: Error::MultipleUnicodeInputError,
+                        start_line_number: current_line,
+                        start_column: 1,
+                        end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                        end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
+                    });
+                }
+
+                unicode_input = true;
+
+                if token_iter.next().is_some() {
+                    let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
+
+                    errors.push(DetailedError {
+                        error_type: Error::TokensAfterUnicodeInputError,
+                        start_line_number: current_line + second_token_start_pos.0 - 1,
+                        start_column: second_token_start_pos.1,
+                        end_line_number: current_line + last_non_whitespace_pos.0 - 1,
+                        end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
+                    });
+                }
+
+                statements.push((MmpStatement::UnicodeInput, current_line));
+            }
             "$locateafter" => {
                 if locate_after.is_some() {
                     errors.push(DetailedError {
@@ -467,6 +628,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -479,6 +642,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     // Make sure locate_after is set to Some(_) so that future locate-after statements will be flagged as errors
@@ -495,6 +660,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -508,6 +675,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -520,6 +689,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     // Make sure locate_after is set to Some(_) so that future locate-after statements will be flagged as errors
@@ -536,6 +707,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -549,6 +722,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -561,6 +736,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     // Make sure locate_after is set to Some(_) so that future locate-after statements will be flagged as errors
@@ -577,6 +754,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -590,6 +769,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -604,6 +785,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: second_token_start_pos.1,
                             end_line_number: current_line + second_token_end_pos.0 - 1,
                             end_column: second_token_end_pos.1 + 1,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                     }
 
@@ -615,6 +798,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     // Make sure locate_after is set to Some(_) so that future locate-after statements will be flagged as errors
@@ -631,6 +816,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -644,6 +831,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -658,6 +847,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: second_token_start_pos.1,
                             end_line_number: current_line + second_token_end_pos.0 - 1,
                             end_column: second_token_end_pos.1 + 1,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                     }
 
@@ -669,6 +860,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     // Make sure locate_after is set to Some(_) so that future locate-after statements will be flagged as errors
@@ -685,6 +878,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: third_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -698,6 +893,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -712,6 +909,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: second_token_start_pos.1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -725,6 +924,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + last_non_whitespace_pos.0 - 1,
                         end_column: last_non_whitespace_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     })
                 }
 
@@ -745,6 +946,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                     start_column: 1,
                     end_line_number: current_line,
                     end_column: first_token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
             step_prefix => {
@@ -758,6 +961,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1,
                         end_line_number: current_line + first_token_end_pos.0 - 1,
                         end_column: first_token_end_pos.1 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -779,6 +984,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                             start_column: 2,
                             end_line_number: current_line,
                             end_column: new_step_name.len() as u32 + 2,
+                            explanation: None,
+                            expected_symbols: None,
                         });
                     }
                     new_step_name
@@ -794,6 +1001,8 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
                         start_column: 1 + is_hypothesis as u32,
                         end_line_number: current_line,
                         end_column: is_hypothesis as u32 + step_name.len() as u32 + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -943,6 +1152,9 @@ pub fn stage_2<'a>(stage_1: &MmpParserStage1Success<'a>) -> Result<MmpParserStag
             label,
             allow_discouraged,
             allow_incomplete,
+            allow_proof_modification,
+            deduction_mode,
+            unicode_input,
             locate_after,
             distinct_vars,
             constants,
@@ -13,24 +13,35 @@ use crate::{
 use super::{
     MmpLabel, MmpParserStage1Success, MmpParserStage2Success, MmpParserStage3,
     MmpParserStage3Comment, MmpParserStage3Fail, MmpParserStage3Header, MmpParserStage3Success,
-    MmpParserStage3Theorem, MmpStatement, ProofLine,
+    MmpParserStage3Theorem, MmpStatement, ProofLine, WorksheetTheorems,
 };
 
 pub fn stage_3<'a>(
     stage_1: &MmpParserStage1Success<'a>,
     stage_2: &MmpParserStage2Success<'a>,
     mm_data: &MetamathData,
+    worksheet_theorems: Option<&'a WorksheetTheorems>,
 ) -> Result<MmpParserStage3<'a>, Error> {
     match stage_2.label {
         Some(MmpLabel::Header { header_path, title }) => {
             stage_3_header(stage_1, stage_2, mm_data, header_path, title)
         }
-        Some(MmpLabel::Axiom(axiom_label)) => {
-            stage_3_theorem(stage_1, stage_2, axiom_label, true, mm_data)
-        }
-        Some(MmpLabel::Theorem(theorem_label)) => {
-            stage_3_theorem(stage_1, stage_2, theorem_label, false, mm_data)
-        }
+        Some(MmpLabel::Axiom(axiom_label)) => stage_3_theorem(
+            stage_1,
+            stage_2,
+            axiom_label,
+            true,
+            mm_data,
+            worksheet_theorems,
+        ),
+        Some(MmpLabel::Theorem(theorem_label)) => stage_3_theorem(
+            stage_1,
+            stage_2,
+            theorem_label,
+            false,
+            mm_data,
+            worksheet_theorems,
+        ),
         None => stage_3_no_label(stage_1, stage_2, mm_data),
     }
 }
@@ -95,6 +106,8 @@ fn calc_statement_out_of_place_errors(
                 start_column: 1,
                 end_line_number: *line_number + last_non_whitespace_pos.0 - 1,
                 end_column: last_non_whitespace_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
     }
@@ -151,6 +164,33 @@ fn calc_header_statement_out_of_place_errors(
             MmpStatement::AllowIncomplete,
         );
     }
+    if stage_2.allow_proof_modification {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::AllowProofModificationOutOfPlaceError,
+            MmpStatement::AllowProofModification,
+        );
+    }
+    if stage_2.deduction_mode {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::DeductionModeOutOfPlaceError,
+            MmpStatement::DeductionMode,
+        );
+    }
+    if stage_2.unicode_input {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::UnicodeInputOutOfPlaceError,
+            MmpStatement::UnicodeInput,
+        );
+    }
     if stage_2.locate_after.is_some() {
         calc_statement_out_of_place_errors(
             &mut errors,
@@ -200,6 +240,8 @@ fn calc_label_error(
                 start_column: first_token_start_pos.1,
                 end_line_number: *line_number + first_token_end_pos.0 - 1,
                 end_column: first_token_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
     }
@@ -213,6 +255,7 @@ fn stage_3_theorem<'a>(
     label: &'a str,
     is_axiom: bool,
     metamath_data: &MetamathData,
+    worksheet_theorems: Option<&'a WorksheetTheorems>,
 ) -> Result<MmpParserStage3<'a>, Error> {
     let mut errors: Vec<DetailedError> = Vec::new();
 
@@ -276,6 +319,8 @@ fn stage_3_theorem<'a>(
                 start_column: second_token_start_pos.1,
                 end_line_number: line_number + second_token_end_pos.0 - 1,
                 end_column: second_token_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -293,6 +338,8 @@ fn stage_3_theorem<'a>(
                 start_column: third_token_start_pos.1,
                 end_line_number: line_number + third_token_end_pos.0 - 1,
                 end_column: third_token_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -311,6 +358,8 @@ fn stage_3_theorem<'a>(
                 start_column: fourth_token_start_pos.1,
                 end_line_number: line_number + fourth_token_end_pos.0 - 1,
                 end_column: fourth_token_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -329,6 +378,8 @@ fn stage_3_theorem<'a>(
                 start_column: fourth_token_start_pos.1,
                 end_line_number: line_number + fourth_token_end_pos.0 - 1,
                 end_column: fourth_token_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -352,6 +403,7 @@ fn stage_3_theorem<'a>(
             &mut errors,
             locate_after,
             metamath_data,
+            worksheet_theorems,
             statement_str,
             line_number,
         )?;
@@ -382,11 +434,65 @@ fn stage_3_theorem<'a>(
             start_column: second_token_start_pos.1,
             end_line_number: line_number + second_token_end_pos.0 - 1,
             end_column: second_token_end_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
+        });
+    }
+
+    if is_axiom && stage_2.allow_proof_modification {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::AllowProofModificationOutOfPlaceError,
+            MmpStatement::AllowProofModification,
+        );
+    }
+    if is_axiom && stage_2.deduction_mode {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::DeductionModeOutOfPlaceError,
+            MmpStatement::DeductionMode,
+        );
+    }
+
+    // Editing an existing theorem will replace its proof, which needs explicit consent for
+    // theorems marked with "(Proof modification is discouraged.)"
+    if !is_axiom
+        && !stage_2.allow_proof_modification
+        && metamath_data
+            .optimized_data
+            .theorem_data
+            .get(label)
+            .is_some_and(|theorem_data| theorem_data.is_proof_modification_discouraged)
+    {
+        let Some((statement_str, line_number)) = calc_statement_str_and_line_number(
+            &stage_1.statements,
+            &stage_2.statements,
+            MmpStatement::MmpLabel,
+            0,
+        ) else {
+            return Err(Error::InternalLogicError);
+        };
+
+        let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
+        let second_token_end_pos = util::nth_token_end_pos(statement_str, 1);
+
+        errors.push(DetailedError {
+            error_type: Error::ProofModificationDiscouragedError,
+            start_line_number: line_number + second_token_start_pos.0 - 1,
+            start_column: second_token_start_pos.1,
+            end_line_number: line_number + second_token_end_pos.0 - 1,
+            end_column: second_token_end_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
         });
     }
 
     let (axiom_dependencies, definition_dependencies) =
-        calc_dependencies(&stage_2.proof_lines, metamath_data);
+        calc_dependencies(&stage_2.proof_lines, metamath_data, worksheet_theorems);
 
     Ok(if errors.is_empty() {
         MmpParserStage3::Success(MmpParserStage3Success::Theorem(MmpParserStage3Theorem {
@@ -396,6 +502,7 @@ fn stage_3_theorem<'a>(
             temp_floating_hypotheses,
             axiom_dependencies,
             definition_dependencies,
+            worksheet_theorems,
         }))
     } else {
         MmpParserStage3::Fail(MmpParserStage3Fail { errors })
@@ -406,6 +513,7 @@ fn calc_locate_after_errors(
     errors: &mut Vec<DetailedError>,
     locate_after: LocateAfterRef,
     metamath_data: &MetamathData,
+    worksheet_theorems: Option<&WorksheetTheorems>,
     statement_str: &str,
     line_number: u32,
 ) -> Result<(), Error> {
@@ -426,6 +534,8 @@ fn calc_locate_after_errors(
                     start_column: second_token_start_pos.1,
                     end_line_number: line_number + second_token_end_pos.0 - 1,
                     end_column: second_token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
         }
@@ -458,6 +568,8 @@ fn calc_locate_after_errors(
                     start_column: second_token_start_pos.1,
                     end_line_number: line_number + second_token_end_pos.0 - 1,
                     end_column: second_token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
         }
@@ -476,6 +588,8 @@ fn calc_locate_after_errors(
                     start_column: second_token_start_pos.1,
                     end_line_number: line_number + second_token_end_pos.0 - 1,
                     end_column: second_token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
         }
@@ -494,6 +608,8 @@ fn calc_locate_after_errors(
                     start_column: second_token_start_pos.1,
                     end_line_number: line_number + second_token_end_pos.0 - 1,
                     end_column: second_token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
         }
@@ -506,6 +622,8 @@ fn calc_locate_after_errors(
                     .database_header
                     .theorem_iter()
                     .all(|t| t.label != label)
+                && worksheet_theorems
+                    .is_none_or(|worksheet_theorems| !worksheet_theorems.contains(label))
             {
                 let second_token_start_pos = util::nth_token_start_pos(statement_str, 1);
                 let second_token_end_pos = util::nth_token_end_pos(statement_str, 1);
@@ -516,6 +634,8 @@ fn calc_locate_after_errors(
                     start_column: second_token_start_pos.1,
                     end_line_number: line_number + second_token_end_pos.0 - 1,
                     end_column: second_token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
         }
@@ -527,6 +647,7 @@ fn calc_locate_after_errors(
 fn calc_dependencies(
     proof_lines: &Vec<ProofLine>,
     metamath_data: &MetamathData,
+    worksheet_theorems: Option<&WorksheetTheorems>,
 ) -> (Vec<(String, u32)>, Vec<(String, u32)>) {
     let mut already_seen: HashSet<&str> = HashSet::new();
 
@@ -547,7 +668,10 @@ fn calc_dependencies(
         .collect();
 
     let (axiom_theorem_indexes, definition_theorem_indexes) =
-        Theorem::calc_dependencies_from_labels(&step_refs, &metamath_data.optimized_data);
+        Theorem::calc_dependencies_from_labels(&step_refs, |label| match worksheet_theorems {
+            Some(worksheet_theorems) => worksheet_theorems.theorem_data(metamath_data, label),
+            None => metamath_data.optimized_data.theorem_data.get(label),
+        });
 
     (
         metamath_data
@@ -635,6 +759,33 @@ fn stage_3_floating_hypothesis<'a>(
             MmpStatement::AllowIncomplete,
         );
     }
+    if stage_2.allow_proof_modification {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::AllowProofModificationOutOfPlaceError,
+            MmpStatement::AllowProofModification,
+        );
+    }
+    if stage_2.deduction_mode {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::DeductionModeOutOfPlaceError,
+            MmpStatement::DeductionMode,
+        );
+    }
+    if stage_2.unicode_input {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::UnicodeInputOutOfPlaceError,
+            MmpStatement::UnicodeInput,
+        );
+    }
     if !stage_2.distinct_vars.is_empty() {
         calc_statement_out_of_place_errors(
             &mut errors,
@@ -667,6 +818,7 @@ fn stage_3_floating_hypothesis<'a>(
             &mut errors,
             locate_after,
             mm_data,
+            None,
             statement_str,
             line_number,
         )?;
@@ -716,6 +868,8 @@ fn stage_3_floating_hypothesis<'a>(
             start_column: second_token_start_pos.1,
             end_line_number: line_number + second_token_end_pos.0 - 1,
             end_column: second_token_end_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
         });
     }
 
@@ -733,6 +887,8 @@ fn stage_3_floating_hypothesis<'a>(
             start_column: third_token_start_pos.1,
             end_line_number: line_number + third_token_end_pos.0 - 1,
             end_column: third_token_end_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
         });
     }
 
@@ -750,6 +906,8 @@ fn stage_3_floating_hypothesis<'a>(
             start_column: fourth_token_start_pos.1,
             end_line_number: line_number + fourth_token_end_pos.0 - 1,
             end_column: fourth_token_end_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
         });
     }
 
@@ -767,6 +925,8 @@ fn stage_3_floating_hypothesis<'a>(
             start_column: fourth_token_start_pos.1,
             end_line_number: line_number + fourth_token_end_pos.0 - 1,
             end_column: fourth_token_end_pos.1 + 1,
+            explanation: None,
+            expected_symbols: None,
         });
     }
 
@@ -783,7 +943,7 @@ fn stage_3_floating_hypothesis<'a>(
     })
 }
 
-fn calc_statement_str_and_line_number<'a>(
+pub fn calc_statement_str_and_line_number<'a>(
     statement_strs: &Vec<&'a str>,
     statements: &Vec<(MmpStatement, u32)>,
     searched_for_statement_type: MmpStatement,
@@ -842,6 +1002,33 @@ fn stage_3_variables<'a>(
             MmpStatement::AllowIncomplete,
         );
     }
+    if stage_2.allow_proof_modification {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::AllowProofModificationOutOfPlaceError,
+            MmpStatement::AllowProofModification,
+        );
+    }
+    if stage_2.deduction_mode {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::DeductionModeOutOfPlaceError,
+            MmpStatement::DeductionMode,
+        );
+    }
+    if stage_2.unicode_input {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::UnicodeInputOutOfPlaceError,
+            MmpStatement::UnicodeInput,
+        );
+    }
     if !stage_2.distinct_vars.is_empty() {
         calc_statement_out_of_place_errors(
             &mut errors,
@@ -874,6 +1061,7 @@ fn stage_3_variables<'a>(
             &mut errors,
             locate_after,
             mm_data,
+            None,
             statement_str,
             line_number,
         )?;
@@ -952,6 +1140,8 @@ fn calc_non_new_math_symbol_errors(
                         start_column: current_token_start_column,
                         end_line_number: line,
                         end_column: column,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
                 if !util::is_valid_math_symbol(&current_token) {
@@ -961,6 +1151,8 @@ fn calc_non_new_math_symbol_errors(
                         start_column: current_token_start_column,
                         end_line_number: line,
                         end_column: column,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
                 if tokens_seen.iter().any(|s| *s == current_token) {
@@ -970,6 +1162,8 @@ fn calc_non_new_math_symbol_errors(
                         start_column: current_token_start_column,
                         end_line_number: line,
                         end_column: column,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
 
@@ -1012,6 +1206,33 @@ fn stage_3_constants<'a>(
             MmpStatement::AllowIncomplete,
         );
     }
+    if stage_2.allow_proof_modification {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::AllowProofModificationOutOfPlaceError,
+            MmpStatement::AllowProofModification,
+        );
+    }
+    if stage_2.deduction_mode {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::DeductionModeOutOfPlaceError,
+            MmpStatement::DeductionMode,
+        );
+    }
+    if stage_2.unicode_input {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::UnicodeInputOutOfPlaceError,
+            MmpStatement::UnicodeInput,
+        );
+    }
     if !stage_2.distinct_vars.is_empty() {
         calc_statement_out_of_place_errors(
             &mut errors,
@@ -1044,6 +1265,7 @@ fn stage_3_constants<'a>(
             &mut errors,
             locate_after,
             mm_data,
+            None,
             statement_str,
             line_number,
         )?;
@@ -1108,6 +1330,33 @@ fn stage_3_comment<'a>(
             MmpStatement::AllowIncomplete,
         );
     }
+    if stage_2.allow_proof_modification {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::AllowProofModificationOutOfPlaceError,
+            MmpStatement::AllowProofModification,
+        );
+    }
+    if stage_2.deduction_mode {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::DeductionModeOutOfPlaceError,
+            MmpStatement::DeductionMode,
+        );
+    }
+    if stage_2.unicode_input {
+        calc_statement_out_of_place_errors(
+            &mut errors,
+            stage_1,
+            stage_2,
+            Error::UnicodeInputOutOfPlaceError,
+            MmpStatement::UnicodeInput,
+        );
+    }
     if !stage_2.distinct_vars.is_empty() {
         calc_statement_out_of_place_errors(
             &mut errors,
@@ -1140,6 +1389,7 @@ fn stage_3_comment<'a>(
             &mut errors,
             locate_after,
             mm_data,
+            None,
             statement_str,
             line_number,
         )?;
@@ -4,7 +4,8 @@ use crate::{
     editor::on_edit::DetailedError,
     metamath::mmp_parser::{MmpStatement, ProofLine, ProofLineStatus},
     model::{MetamathData, ParseTree, ProofType, SymbolNumberMapping, Theorem, TheoremType},
-    util, Error,
+    util::{self, unicode_input},
+    Error,
 };
 
 use super::{
@@ -81,6 +82,8 @@ pub fn stage_4(
                     + proof_line.advanced_unification as u32
                     + proof_line.is_hypothesis as u32
                     + proof_line.step_name.len() as u32,
+                explanation: None,
+                expected_symbols: None,
             });
 
             error_status.0 = true;
@@ -114,6 +117,8 @@ pub fn stage_4(
                                 start_column: start_column,
                                 end_line_number: line_number,
                                 end_column: start_column + hyp.len() as u32,
+                                explanation: None,
+                                expected_symbols: None,
                             });
 
                             error_status.1 = true;
@@ -134,6 +139,8 @@ pub fn stage_4(
                     - proof_line.hypotheses.len() as u32,
                 end_line_number: line_number,
                 end_column: step_prefix_len - proof_line.step_ref.len() as u32,
+                explanation: None,
+                expected_symbols: None,
             });
 
             error_status.1 = true;
@@ -154,6 +161,8 @@ pub fn stage_4(
                 start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                 end_line_number: line_number,
                 end_column: step_prefix_len + 1,
+                explanation: None,
+                expected_symbols: None,
             });
 
             error_status.2 = true;
@@ -163,19 +172,16 @@ pub fn stage_4(
         let mut theorem: Option<&Theorem> = None;
 
         if !proof_line.is_hypothesis && proof_line.step_ref != "" {
-            if let Some((theorem_i, theorem_ref)) = mm_data
-                .database_header
-                .theorem_locate_after_iter(stage_2.locate_after)
+            if let Some((theorem_i, theorem_ref)) = stage_3
+                .theorem_locate_after_iter(mm_data, stage_2.locate_after)
                 .enumerate()
                 .find(|(_, t)| t.label == proof_line.step_ref)
             {
                 theorem = Some(theorem_ref);
                 reference_numbers.push(Some((theorem_i + 1) as u32));
 
-                let theorem_data = mm_data
-                    .optimized_data
-                    .theorem_data
-                    .get(&theorem_ref.label)
+                let theorem_data = stage_3
+                    .theorem_data(mm_data, &theorem_ref.label)
                     .ok_or(Error::InternalLogicError)?;
 
                 if theorem_data.parse_trees.is_none() {
@@ -185,6 +191,8 @@ pub fn stage_4(
                         start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                         end_line_number: line_number,
                         end_column: step_prefix_len + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     error_status.2 = true;
@@ -197,6 +205,8 @@ pub fn stage_4(
                         start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                         end_line_number: line_number,
                         end_column: step_prefix_len + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     error_status.2 = true;
@@ -216,6 +226,8 @@ pub fn stage_4(
                         start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                         end_line_number: line_number,
                         end_column: step_prefix_len + 1,
+                        explanation: None,
+                        expected_symbols: None,
                     });
 
                     error_status.2 = true;
@@ -231,6 +243,8 @@ pub fn stage_4(
                             - proof_line.hypotheses.len() as u32,
                         end_line_number: line_number,
                         end_column: step_prefix_len - proof_line.step_ref.len() as u32,
+                        explanation: None,
+                        expected_symbols: None,
                     });
                 }
             } else {
@@ -240,6 +254,8 @@ pub fn stage_4(
                     start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                     end_line_number: line_number,
                     end_column: step_prefix_len + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
 
                 error_status.2 = true;
@@ -261,6 +277,7 @@ pub fn stage_4(
             statement_str,
             line_number,
             mm_data,
+            stage_2.unicode_input,
         )?;
 
         //calc previw_confirmation
@@ -294,10 +311,8 @@ pub fn stage_4(
                         if let Some(parse_tree_ref) = parse_tree.as_ref() {
                             proof_line_parse_trees.push(parse_tree_ref);
 
-                            let optimized_theorem_data = mm_data
-                                .optimized_data
-                                .theorem_data
-                                .get(&theorem_ref.label)
+                            let optimized_theorem_data = stage_3
+                                .theorem_data(mm_data, &theorem_ref.label)
                                 .ok_or(Error::InternalLogicError)?;
 
                             if let Some(parse_trees) = optimized_theorem_data.parse_trees.as_ref() {
@@ -385,8 +400,29 @@ fn calc_parse_tree_and_handle_errors(
     statement_str: &str,
     line_number: u32,
     mm_data: &MetamathData,
+    unicode_input: bool,
 ) -> Result<(), Error> {
-    match mm_data.expression_to_parse_tree(proof_line.expression) {
+    let mut parse_result = mm_data.expression_to_parse_tree(proof_line.expression);
+
+    // With $unicodeinput, expressions that aren't written as space separated metamath tokens are converted before
+    // reporting any errors
+    if unicode_input
+        && matches!(
+            parse_result,
+            Err(Error::NonSymbolInExpressionError
+                | Error::ExpressionParseError
+                | Error::InvalidWorkVariableError
+                | Error::InvalidTypecodeError)
+        )
+    {
+        if let Some(expression) =
+            unicode_input::unicode_expression_to_tokens(proof_line.expression, mm_data)?
+        {
+            parse_result = mm_data.expression_to_parse_tree(&expression);
+        }
+    }
+
+    match parse_result {
         Ok(pt) => *parse_tree = Some(pt),
         Err(Error::MissingExpressionError) => {}
         Err(Error::NonSymbolInExpressionError) => {
@@ -403,15 +439,53 @@ fn calc_parse_tree_and_handle_errors(
             error_status.3 = true;
         }
         Err(Error::ExpressionParseError) => {
-            let last_non_whitespace_pos = util::last_non_whitespace_pos(statement_str);
+            let parse_failure = mm_data.calc_expression_parse_failure(proof_line.expression)?;
 
-            errors.push(DetailedError {
-                error_type: Error::ExpressionParseError,
-                start_line_number: line_number,
-                start_column: last_non_whitespace_pos.1 + 1,
-                end_line_number: line_number,
-                end_column: last_non_whitespace_pos.1 + 2,
-            });
+            let symbol_number_mapping = &mm_data.optimized_data.symbol_number_mapping;
+
+            let expected_symbols = parse_failure
+                .expected_symbols
+                .iter()
+                .map(|&symbol_i| {
+                    let symbol = symbol_number_mapping
+                        .symbols
+                        .get(&symbol_i)
+                        .ok_or(Error::InternalLogicError)?;
+
+                    // Typecodes are prefixed with a '$' to distinguish them from the constants of the same name
+                    Ok(symbol.strip_prefix('$').unwrap_or(symbol).to_string())
+                })
+                .collect::<Result<Vec<String>, Error>>()?;
+
+            // The first token of the statement is the step prefix, the second one the typecode
+            let offending_token_i = parse_failure.position + 2;
+
+            if (offending_token_i as usize) < statement_str.split_ascii_whitespace().count() {
+                let token_start_pos = util::nth_token_start_pos(statement_str, offending_token_i);
+                let token_end_pos = util::nth_token_end_pos(statement_str, offending_token_i);
+
+                errors.push(DetailedError {
+                    error_type: Error::ExpressionParseError,
+                    start_line_number: line_number + token_start_pos.0 - 1,
+                    start_column: token_start_pos.1,
+                    end_line_number: line_number + token_end_pos.0 - 1,
+                    end_column: token_end_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: Some(expected_symbols),
+                });
+            } else {
+                let last_non_whitespace_pos = util::last_non_whitespace_pos(statement_str);
+
+                errors.push(DetailedError {
+                    error_type: Error::ExpressionParseError,
+                    start_line_number: line_number + last_non_whitespace_pos.0 - 1,
+                    start_column: last_non_whitespace_pos.1 + 1,
+                    end_line_number: line_number + last_non_whitespace_pos.0 - 1,
+                    end_column: last_non_whitespace_pos.1 + 2,
+                    explanation: None,
+                    expected_symbols: Some(expected_symbols),
+                });
+            }
 
             error_status.3 = true;
         }
@@ -438,6 +512,8 @@ fn calc_parse_tree_and_handle_errors(
                 start_column: second_token_start_pos.1,
                 end_line_number: line_number + second_token_end_pos.0 - 1,
                 end_column: second_token_end_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
 
             error_status.3 = true;
@@ -523,6 +599,8 @@ fn check_symbol_for_error(
                 start_column: current_token_start_column,
                 end_line_number: line,
                 end_column: column,
+                explanation: None,
+                expected_symbols: None,
             });
         }
     } else {
@@ -533,6 +611,8 @@ fn check_symbol_for_error(
                 start_column: current_token_start_column,
                 end_line_number: line,
                 end_column: column,
+                explanation: None,
+                expected_symbols: None,
             });
         }
     }
@@ -608,6 +688,8 @@ pub fn stage_4_axiom(
                     + proof_line.advanced_unification as u32
                     + proof_line.is_hypothesis as u32
                     + proof_line.step_name.len() as u32,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -626,6 +708,8 @@ pub fn stage_4_axiom(
                 start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                 end_line_number: line_number,
                 end_column: step_prefix_len + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -640,6 +724,8 @@ pub fn stage_4_axiom(
                         - proof_line.hypotheses.len() as u32,
                     end_line_number: line_number,
                     end_column: step_prefix_len - proof_line.step_ref.len() as u32,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
 
@@ -653,6 +739,7 @@ pub fn stage_4_axiom(
                     statement_str,
                     line_number,
                     mm_data,
+                    stage_2.unicode_input,
                 )?;
             } else {
                 let last_non_whitespace_pos = util::last_non_whitespace_pos(statement_str);
@@ -663,6 +750,8 @@ pub fn stage_4_axiom(
                     start_column: 0,
                     end_line_number: line_number + last_non_whitespace_pos.0 - 1,
                     end_column: last_non_whitespace_pos.1 + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
         } else if proof_line.step_name == "qed" {
@@ -676,6 +765,8 @@ pub fn stage_4_axiom(
                         - proof_line.hypotheses.len() as u32,
                     end_line_number: line_number,
                     end_column: step_prefix_len - proof_line.step_ref.len() as u32,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
 
@@ -686,6 +777,8 @@ pub fn stage_4_axiom(
                     start_column: step_prefix_len - proof_line.step_ref.len() as u32 + 1,
                     end_line_number: line_number,
                     end_column: step_prefix_len + 1,
+                    explanation: None,
+                    expected_symbols: None,
                 });
             }
 
@@ -699,6 +792,7 @@ pub fn stage_4_axiom(
                     statement_str,
                     line_number,
                     mm_data,
+                    stage_2.unicode_input,
                 )?;
             }
         } else {
@@ -710,6 +804,8 @@ pub fn stage_4_axiom(
                 start_column: 0,
                 end_line_number: line_number + last_non_whitespace_pos.0 - 1,
                 end_column: last_non_whitespace_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -726,6 +822,8 @@ pub fn stage_4_axiom(
                 start_column: second_token_start_pos.1,
                 end_line_number: line_number + last_non_whitespace_pos.0 - 1,
                 end_column: last_non_whitespace_pos.1 + 1,
+                explanation: None,
+                expected_symbols: None,
             });
         }
 
@@ -4,6 +4,7 @@ use std::{
 };
 
 use crate::{
+    editor::on_edit::UnificationExplanation,
     metamath::mmp_parser::{
         LocateAfterRef, MmpParserStage2Success, MmpParserStage3Theorem, MmpParserStage4Success,
         MmpParserStage5, ProofLine, ProofLineStatus, UnifyLine,
@@ -14,14 +15,18 @@ use crate::{
         work_variable_manager::WorkVariableManager,
         StrIterToSpaceSeperatedString,
     },
-    Error,
+    Error, Settings,
 };
 
+// How many bridging theorems can be chained to prove a step in deduction mode
+const MAX_BRIDGING_STEPS: u32 = 2;
+
 pub fn stage_5(
     stage_2: &MmpParserStage2Success,
     stage_3: &MmpParserStage3Theorem,
     stage_4: &MmpParserStage4Success,
     mm_data: &MetamathData,
+    settings: &Settings,
     stop: Option<Arc<std::sync::Mutex<bool>>>,
 ) -> Result<MmpParserStage5, Error> {
     if stage_3.is_axiom {
@@ -76,6 +81,8 @@ pub fn stage_5(
 
     let mut new_unify_lines: Vec<UnifyLine> = Vec::new();
 
+    let mut failed_step_names: HashSet<String> = HashSet::new();
+
     if stop
         .as_ref()
         .is_some_and(|stop| stop.lock().is_ok_and(|stop| *stop))
@@ -84,14 +91,48 @@ pub fn stage_5(
     }
 
     while let Some(mut unify_line) = unify_lines.pop() {
+        let mut unification_failed = false;
+
         if unify_line.step_ref != "" && !unify_line.is_hypothesis {
-            match unify_step_with_reference(
+            let mut unify_result = unify_step_with_reference(
                 &unify_line,
                 &new_unify_lines,
                 mm_data,
+                stage_3,
                 &mut work_variable_manager,
                 &mut step_name_manager,
-            ) {
+            );
+
+            if stage_2.deduction_mode
+                && matches!(unify_result, Err(Error::UnificationError))
+                && step_ref_matches_consequent(
+                    &unify_line,
+                    mm_data,
+                    stage_3,
+                    &work_variable_manager,
+                )?
+            {
+                if let Some((bridging_theorem_label, bridging_unify_result)) =
+                    unify_step_with_bridging_theorem(
+                        &unify_line,
+                        &new_unify_lines,
+                        mm_data,
+                        stage_3,
+                        settings,
+                        &mut work_variable_manager,
+                        &mut step_name_manager,
+                        MAX_BRIDGING_STEPS,
+                    )?
+                {
+                    unify_line.step_ref = bridging_theorem_label;
+
+                    set_unify_status(&mut unify_line.status, 2)?;
+
+                    unify_result = Ok(bridging_unify_result);
+                }
+            }
+
+            match unify_result {
                 Ok((substitutions, hypotheses, new_lines, new_parse_trees)) => {
                     let work_variables_to_substitute: HashSet<WorkVariable> =
                         substitutions.keys().map(|wv| *wv).collect();
@@ -141,7 +182,9 @@ pub fn stage_5(
                         nl
                     }));
                 }
-                Err(Error::UnificationError) => {}
+                Err(Error::UnificationError) => {
+                    unification_failed = true;
+                }
                 Err(error) => return Err(error),
             }
         }
@@ -152,6 +195,10 @@ pub fn stage_5(
             set_unify_status(&mut unify_line.status, 0)?;
         }
 
+        if unification_failed {
+            failed_step_names.insert(unify_line.step_name.clone());
+        }
+
         new_unify_lines.push(unify_line);
 
         if stop
@@ -173,6 +220,7 @@ pub fn stage_5(
                 stage_2.allow_discouraged,
                 stage_2.allow_incomplete,
                 mm_data,
+                stage_3,
                 stage_2.locate_after,
                 &stage_4.distinct_variable_pairs,
                 &mut hypotheses_name_manager,
@@ -249,17 +297,40 @@ pub fn stage_5(
     let unify_reference_numbers = unify_lines
         .iter()
         .map(|ul| {
-            mm_data
-                .database_header
-                .theorem_iter()
+            stage_3
+                .theorem_locate_after_iter(mm_data, None)
                 .position(|t| t.label == ul.step_ref)
                 .map(|i| (i + 1) as u32)
         })
         .collect();
 
+    let mut unification_explanations = Vec::new();
+
+    for (proof_line_i, (unify_line_i, unify_line)) in unify_lines
+        .iter()
+        .enumerate()
+        .filter(|(_, ul)| !ul.new_line)
+        .enumerate()
+    {
+        if unify_line.deleted_line || !failed_step_names.contains(&unify_line.step_name) {
+            continue;
+        }
+
+        if let Some(explanation) = calc_unification_explanation(
+            unify_line,
+            &unify_lines[..unify_line_i],
+            mm_data,
+            stage_3,
+            &stage_4.distinct_variable_pairs,
+        )? {
+            unification_explanations.push((proof_line_i, explanation));
+        }
+    }
+
     Ok(MmpParserStage5 {
         unify_result: unify_lines,
         unify_reference_numbers,
+        unification_explanations,
     })
 }
 
@@ -308,25 +379,24 @@ fn set_unify_status_recursively_correct(
     }
 }
 
+// Substitutions, hypotheses of the step, new lines and new parse trees of existing lines
+type UnifyStepResult = (
+    HashMap<WorkVariable, ParseTreeNode>,
+    Vec<String>,
+    Vec<UnifyLine>,
+    HashMap<String, ParseTree>,
+);
+
 fn unify_step_with_reference(
     unify_line: &UnifyLine,
     previous_unify_lines: &Vec<UnifyLine>,
     mm_data: &MetamathData,
+    stage_3: &MmpParserStage3Theorem,
     work_variable_manager: &mut WorkVariableManager,
     step_name_manager: &mut StepNameManager,
-) -> Result<
-    (
-        HashMap<WorkVariable, ParseTreeNode>,
-        Vec<String>,
-        Vec<UnifyLine>,
-        HashMap<String, ParseTree>,
-    ),
-    Error,
-> {
-    let theorem_data = mm_data
-        .optimized_data
-        .theorem_data
-        .get(&unify_line.step_ref)
+) -> Result<UnifyStepResult, Error> {
+    let theorem_data = stage_3
+        .theorem_data(mm_data, &unify_line.step_ref)
         .ok_or(Error::InternalLogicError)?;
 
     let theorem_parse_trees = theorem_data
@@ -443,6 +513,619 @@ fn unify_step_with_reference(
     Ok((substitutions, hypotheses, new_lines, new_parse_trees))
 }
 
+// Used in deduction mode, if a step can't be unified with its step ref directly. Tries the bridging theorems from the
+// settings in order: The step is proven by the bridging theorem and one of the new hypothesis lines of the bridging
+// theorem is proven by the original step ref, for example "a1i" turns a closed form "|- ps" into "|- ( ph -> ps )".
+// If bridging_steps is greater than 1, that hypothesis line may itself be bridged, so that for example "mpd" and then
+// "a1i" turn "|- ( ps -> ch )" into "|- ( ph -> ch )", leaving "|- ( ph -> ps )" to be proven
+fn unify_step_with_bridging_theorem(
+    unify_line: &UnifyLine,
+    previous_unify_lines: &Vec<UnifyLine>,
+    mm_data: &MetamathData,
+    stage_3: &MmpParserStage3Theorem,
+    settings: &Settings,
+    work_variable_manager: &mut WorkVariableManager,
+    step_name_manager: &mut StepNameManager,
+    bridging_steps: u32,
+) -> Result<Option<(String, UnifyStepResult)>, Error> {
+    if bridging_steps == 0 {
+        return Ok(None);
+    }
+
+    for bridging_theorem_label in &settings.deduction_bridging_theorems {
+        if *bridging_theorem_label == unify_line.step_ref
+            || !stage_3
+                .theorem_data(mm_data, bridging_theorem_label)
+                .is_some_and(|theorem_data| theorem_data.parse_trees.is_some())
+        {
+            continue;
+        }
+
+        let mut bridging_work_variable_manager = work_variable_manager.clone();
+        let mut bridging_step_name_manager = step_name_manager.clone();
+
+        let bridging_unify_line = UnifyLine {
+            new_line: unify_line.new_line,
+            deleted_line: unify_line.deleted_line,
+            advanced_unification: unify_line.advanced_unification,
+            is_hypothesis: false,
+            step_name: unify_line.step_name.clone(),
+            hypotheses: Vec::new(),
+            step_ref: bridging_theorem_label.clone(),
+            parse_tree: unify_line.parse_tree.clone(),
+            old_assertion: None,
+            status: unify_line.status,
+        };
+
+        let (
+            bridging_substitutions,
+            bridging_hypotheses,
+            bridging_new_lines,
+            bridging_new_parse_trees,
+        ) = match unify_step_with_reference(
+            &bridging_unify_line,
+            previous_unify_lines,
+            mm_data,
+            stage_3,
+            &mut bridging_work_variable_manager,
+            &mut bridging_step_name_manager,
+        ) {
+            Ok(result) => result,
+            Err(Error::UnificationError) => continue,
+            Err(error) => return Err(error),
+        };
+
+        for (i, bridging_new_line) in bridging_new_lines.iter().enumerate() {
+            let Some(parse_tree) = &bridging_new_line.parse_tree else {
+                continue;
+            };
+
+            let mut reference_work_variable_manager = bridging_work_variable_manager.clone();
+            let mut reference_step_name_manager = bridging_step_name_manager.clone();
+
+            let reference_unify_line = UnifyLine {
+                new_line: true,
+                deleted_line: false,
+                advanced_unification: false,
+                is_hypothesis: false,
+                step_name: bridging_new_line.step_name.clone(),
+                hypotheses: unify_line.hypotheses.clone(),
+                step_ref: unify_line.step_ref.clone(),
+                parse_tree: Some(ParseTree {
+                    typecode: parse_tree.typecode,
+                    top_node: parse_tree
+                        .top_node
+                        .clone_and_apply_substitutions(&bridging_substitutions),
+                }),
+                old_assertion: None,
+                status: bridging_new_line.status,
+            };
+
+            let (
+                reference_step_ref,
+                (
+                    reference_substitutions,
+                    reference_hypotheses,
+                    reference_new_lines,
+                    reference_new_parse_trees,
+                ),
+            ) = match unify_step_with_reference(
+                &reference_unify_line,
+                previous_unify_lines,
+                mm_data,
+                stage_3,
+                &mut reference_work_variable_manager,
+                &mut reference_step_name_manager,
+            ) {
+                Ok(result) => (unify_line.step_ref.clone(), result),
+                Err(Error::UnificationError) => {
+                    reference_work_variable_manager = bridging_work_variable_manager.clone();
+                    reference_step_name_manager = bridging_step_name_manager.clone();
+
+                    match unify_step_with_bridging_theorem(
+                        &reference_unify_line,
+                        previous_unify_lines,
+                        mm_data,
+                        stage_3,
+                        settings,
+                        &mut reference_work_variable_manager,
+                        &mut reference_step_name_manager,
+                        bridging_steps - 1,
+                    )? {
+                        Some(result) => result,
+                        None => continue,
+                    }
+                }
+                Err(error) => return Err(error),
+            };
+
+            // Combine both substitutions, so that they can be applied at once
+            let mut substitutions: HashMap<WorkVariable, ParseTreeNode> = bridging_substitutions
+                .iter()
+                .map(|(work_var, node)| {
+                    (
+                        *work_var,
+                        node.clone_and_apply_substitutions(&reference_substitutions),
+                    )
+                })
+                .collect();
+
+            for (work_var, node) in reference_substitutions {
+                substitutions.entry(work_var).or_insert(node);
+            }
+
+            let mut new_lines = reference_new_lines;
+
+            for (j, bridging_new_line) in bridging_new_lines.into_iter().enumerate() {
+                if j == i {
+                    new_lines.push(UnifyLine {
+                        advanced_unification: false,
+                        hypotheses: reference_hypotheses.clone(),
+                        step_ref: reference_step_ref.clone(),
+                        ..bridging_new_line
+                    });
+                } else {
+                    new_lines.push(bridging_new_line);
+                }
+            }
+
+            let mut new_parse_trees = bridging_new_parse_trees;
+            new_parse_trees.extend(reference_new_parse_trees);
+
+            *work_variable_manager = reference_work_variable_manager;
+            *step_name_manager = reference_step_name_manager;
+
+            return Ok(Some((
+                bridging_theorem_label.clone(),
+                (
+                    substitutions,
+                    bridging_hypotheses,
+                    new_lines,
+                    new_parse_trees,
+                ),
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+// Bridging theorems are only tried for a step "|- ( ph -> X )" whose step ref is in closed form, meaning that the
+// assertion of the step ref can be unified with X. Otherwise the step ref is most likely wrong and the unification error
+// is reported
+fn step_ref_matches_consequent(
+    unify_line: &UnifyLine,
+    mm_data: &MetamathData,
+    stage_3: &MmpParserStage3Theorem,
+    work_variable_manager: &WorkVariableManager,
+) -> Result<bool, Error> {
+    let Some(parse_tree) = &unify_line.parse_tree else {
+        return Ok(false);
+    };
+
+    let Some(consequent) = implication_consequent(&parse_tree.top_node, mm_data) else {
+        return Ok(false);
+    };
+
+    let Some(parse_trees) = stage_3
+        .theorem_data(mm_data, &unify_line.step_ref)
+        .and_then(|theorem_data| theorem_data.parse_trees.as_ref())
+    else {
+        return Ok(false);
+    };
+
+    let Some(assertion) = parse_trees
+        .to_cloned_parse_tree_vec_replace_floating_hypotheses(
+            &mm_data.optimized_data.symbol_number_mapping,
+            &mm_data.optimized_data.grammar,
+            &mut work_variable_manager.clone(),
+        )?
+        .pop()
+    else {
+        return Ok(false);
+    };
+
+    match martelli_montanari_unification(
+        vec![assertion],
+        vec![ParseTree {
+            typecode: parse_tree.typecode,
+            top_node: consequent.clone(),
+        }],
+    ) {
+        Ok(_) => Ok(true),
+        Err(Error::UnificationError) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+// Returns X if the node is "( ph -> X )"
+fn implication_consequent<'a>(
+    node: &'a ParseTreeNode,
+    mm_data: &MetamathData,
+) -> Option<&'a ParseTreeNode> {
+    let ParseTreeNode::Node { rule_i, sub_nodes } = node else {
+        return None;
+    };
+
+    let rule = mm_data.optimized_data.grammar.rules.get(*rule_i as usize)?;
+
+    let right_side: Vec<&str> = rule
+        .right_side
+        .iter()
+        .map(|symbol| {
+            mm_data
+                .optimized_data
+                .symbol_number_mapping
+                .symbols
+                .get(&symbol.symbol_i)
+                .map(|symbol| symbol.as_str())
+        })
+        .collect::<Option<Vec<&str>>>()?;
+
+    // The antecedent and the consequent are the first and second sub node
+    if matches!(right_side[..], ["(", _, "->", _, ")"]) {
+        sub_nodes.get(1)
+    } else {
+        None
+    }
+}
+
+// Explains why a proof line can't be proven by its step ref: Either the first subterm where one of the step ref's
+// hypotheses or its assertion clashes with the proof line, or the $d statement of the step ref that is violated
+fn calc_unification_explanation(
+    unify_line: &UnifyLine,
+    previous_unify_lines: &[UnifyLine],
+    mm_data: &MetamathData,
+    stage_3: &MmpParserStage3Theorem,
+    distinct_variable_pairs: &HashSet<(String, String)>,
+) -> Result<Option<UnificationExplanation>, Error> {
+    let Some(theorem_data) = stage_3.theorem_data(mm_data, &unify_line.step_ref) else {
+        return Ok(None);
+    };
+
+    let Some(parse_trees) = theorem_data.parse_trees.as_ref() else {
+        return Ok(None);
+    };
+
+    if unify_line.hypotheses.len() > parse_trees.hypotheses_parsed.len() {
+        return Ok(None);
+    }
+
+    let grammar = &mm_data.optimized_data.grammar;
+
+    let proof_line_parse_trees: Vec<Option<&ParseTree>> = parse_trees
+        .hypotheses_parsed
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            unify_line
+                .hypotheses
+                .get(i)
+                .filter(|hyp| *hyp != "?")
+                .and_then(|hyp| previous_unify_lines.iter().find(|ul| &ul.step_name == hyp))
+                .and_then(|ul| ul.parse_tree.as_ref())
+        })
+        .chain(std::iter::once(unify_line.parse_tree.as_ref()))
+        .collect();
+
+    let mut substitutions: HashMap<u32, &ParseTreeNode> = HashMap::new();
+
+    for (theorem_parse_tree, proof_line_parse_tree) in parse_trees
+        .hypotheses_parsed
+        .iter()
+        .chain(std::iter::once(&parse_trees.assertion_parsed))
+        .zip(proof_line_parse_trees)
+    {
+        let Some(proof_line_parse_tree) = proof_line_parse_tree else {
+            continue;
+        };
+
+        if let Some((node, other_node)) = calc_first_clash(
+            &theorem_parse_tree.top_node,
+            &proof_line_parse_tree.top_node,
+            &mut substitutions,
+            grammar,
+        )? {
+            return Ok(Some(UnificationExplanation {
+                pattern: theorem_parse_tree
+                    .to_expression(&mm_data.optimized_data.symbol_number_mapping, grammar)?,
+                substitution: calc_substitution_strings(&substitutions, mm_data)?,
+                clash: Some((
+                    node_to_expression(node, mm_data)?,
+                    node_to_expression(other_node, mm_data)?,
+                )),
+                distinct_variable_pair: None,
+            }));
+        }
+    }
+
+    let variables_in_substitutions: HashMap<&str, HashSet<u32>> = substitutions
+        .iter()
+        .map(|(&rule_i, &node)| {
+            Ok((
+                floating_hypothesis_rule_variable(rule_i, mm_data)?,
+                node.get_floating_hypotheses_rules(grammar)?,
+            ))
+        })
+        .collect::<Result<HashMap<&str, HashSet<u32>>, Error>>()?;
+
+    let mut theorem_distinct_variable_pairs: Vec<&(String, String)> =
+        theorem_data.distinct_variable_pairs.iter().collect();
+    theorem_distinct_variable_pairs.sort();
+
+    for (var_1, var_2) in theorem_distinct_variable_pairs {
+        let (Some(var_1_vars), Some(var_2_vars)) = (
+            variables_in_substitutions.get(var_1.as_str()),
+            variables_in_substitutions.get(var_2.as_str()),
+        ) else {
+            continue;
+        };
+
+        for &var_1_var in var_1_vars {
+            for &var_2_var in var_2_vars {
+                let var_1_var_str = floating_hypothesis_rule_variable(var_1_var, mm_data)?;
+                let var_2_var_str = floating_hypothesis_rule_variable(var_2_var, mm_data)?;
+
+                if var_1_var == var_2_var
+                    || !distinct_variable_pairs
+                        .contains(&(var_1_var_str.to_string(), var_2_var_str.to_string()))
+                {
+                    return Ok(Some(UnificationExplanation {
+                        pattern: format!("$d {} {}", var_1, var_2),
+                        substitution: calc_substitution_strings(&substitutions, mm_data)?,
+                        clash: None,
+                        distinct_variable_pair: Some((
+                            var_1_var_str.to_string(),
+                            var_2_var_str.to_string(),
+                        )),
+                    }));
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// Walks both trees in pre-order and returns the first pair of nodes that can't be matched. Variables of the pattern
+// are matched against the substitutions found so far, work variables in the other tree match anything
+fn calc_first_clash<'a, 'b>(
+    pattern: &'a ParseTreeNode,
+    other: &'b ParseTreeNode,
+    substitutions: &mut HashMap<u32, &'b ParseTreeNode>,
+    grammar: &Grammar,
+) -> Result<Option<(&'a ParseTreeNode, &'b ParseTreeNode)>, Error> {
+    let mut nodes_to_check: Vec<(&ParseTreeNode, &ParseTreeNode)> = vec![(pattern, other)];
+
+    while let Some((node, other_node)) = nodes_to_check.pop() {
+        let ParseTreeNode::Node { rule_i, sub_nodes } = node else {
+            return Err(Error::InternalLogicError);
+        };
+        let rule = grammar
+            .rules
+            .get(*rule_i as usize)
+            .ok_or(Error::InternalLogicError)?;
+
+        if rule.is_floating_hypothesis {
+            match substitutions.get(rule_i) {
+                Some(&sub) => {
+                    if sub != other_node
+                        && !sub.has_work_variables()
+                        && !other_node.has_work_variables()
+                    {
+                        return Ok(Some((node, other_node)));
+                    }
+                }
+                None => {
+                    let other_typecode_i = match other_node {
+                        ParseTreeNode::Node {
+                            rule_i: other_rule_i,
+                            ..
+                        } => {
+                            grammar
+                                .rules
+                                .get(*other_rule_i as usize)
+                                .ok_or(Error::InternalLogicError)?
+                                .left_side
+                                .symbol_i
+                        }
+                        ParseTreeNode::WorkVariable(work_variable) => work_variable.typecode_i,
+                    };
+
+                    if rule.left_side.symbol_i != other_typecode_i {
+                        return Ok(Some((node, other_node)));
+                    }
+
+                    substitutions.insert(*rule_i, other_node);
+                }
+            }
+        } else if let ParseTreeNode::Node {
+            rule_i: other_rule_i,
+            sub_nodes: other_sub_nodes,
+        } = other_node
+        {
+            if *rule_i != *other_rule_i || sub_nodes.len() != other_sub_nodes.len() {
+                return Ok(Some((node, other_node)));
+            }
+
+            for (sub_node, other_sub_node) in sub_nodes.iter().zip(other_sub_nodes.iter()).rev() {
+                nodes_to_check.push((sub_node, other_sub_node));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// Calculates the distinct variable pairs the proof needs, by substituting every step into its step ref and collecting
+// the variables that end up in the substitutions of the step ref's distinct variables. Steps that still contain work
+// variables are skipped
+pub fn calc_required_distinct_variable_pairs(
+    unify_lines: &[UnifyLine],
+    stage_3: &MmpParserStage3Theorem,
+    mm_data: &MetamathData,
+) -> Result<HashSet<(String, String)>, Error> {
+    let mut required_distinct_variable_pairs: HashSet<(String, String)> = HashSet::new();
+
+    for unify_line in unify_lines {
+        if unify_line.deleted_line || unify_line.is_hypothesis || unify_line.step_ref.is_empty() {
+            continue;
+        }
+
+        let Some(theorem_data) = stage_3.theorem_data(mm_data, &unify_line.step_ref) else {
+            continue;
+        };
+
+        if theorem_data.distinct_variable_pairs.is_empty() {
+            continue;
+        }
+
+        let Some(parse_trees) = theorem_data.parse_trees.as_ref() else {
+            continue;
+        };
+
+        let Some(unify_line_parse_trees) = unify_line
+            .hypotheses
+            .iter()
+            .map(|hyp| {
+                unify_lines
+                    .iter()
+                    .find(|ul| !ul.deleted_line && &ul.step_name == hyp)
+                    .and_then(|ul| ul.parse_tree.as_ref())
+            })
+            .chain(std::iter::once(unify_line.parse_tree.as_ref()))
+            .collect::<Option<Vec<&ParseTree>>>()
+        else {
+            continue;
+        };
+
+        if unify_line_parse_trees
+            .iter()
+            .any(|pt| pt.top_node.has_work_variables())
+        {
+            continue;
+        }
+
+        let Some(substitutions) = ParseTree::calc_substitutions(
+            &parse_trees.to_ref_parse_tree_vec(),
+            &unify_line_parse_trees,
+            &HashSet::new(),
+            &HashSet::new(),
+            &mm_data.optimized_data.grammar,
+            &mm_data.optimized_data.symbol_number_mapping,
+        )?
+        else {
+            continue;
+        };
+
+        let variables_in_substitutions: HashMap<&str, HashSet<u32>> = substitutions
+            .iter()
+            .map(|(&rule_i, &node)| {
+                Ok((
+                    floating_hypothesis_rule_variable(rule_i, mm_data)?,
+                    node.get_floating_hypotheses_rules(&mm_data.optimized_data.grammar)?,
+                ))
+            })
+            .collect::<Result<HashMap<&str, HashSet<u32>>, Error>>()?;
+
+        for (var_1, var_2) in &theorem_data.distinct_variable_pairs {
+            let (Some(var_1_vars), Some(var_2_vars)) = (
+                variables_in_substitutions.get(var_1.as_str()),
+                variables_in_substitutions.get(var_2.as_str()),
+            ) else {
+                continue;
+            };
+
+            for &var_1_var in var_1_vars {
+                for &var_2_var in var_2_vars {
+                    if var_1_var != var_2_var {
+                        let var_1_var_str = floating_hypothesis_rule_variable(var_1_var, mm_data)?;
+                        let var_2_var_str = floating_hypothesis_rule_variable(var_2_var, mm_data)?;
+
+                        required_distinct_variable_pairs
+                            .insert((var_1_var_str.to_string(), var_2_var_str.to_string()));
+                        required_distinct_variable_pairs
+                            .insert((var_2_var_str.to_string(), var_1_var_str.to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(required_distinct_variable_pairs)
+}
+
+fn calc_substitution_strings(
+    substitutions: &HashMap<u32, &ParseTreeNode>,
+    mm_data: &MetamathData,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut substitution_strings = substitutions
+        .iter()
+        .map(|(&rule_i, &node)| {
+            Ok((
+                floating_hypothesis_rule_variable(rule_i, mm_data)?.to_string(),
+                node_to_expression(node, mm_data)?,
+            ))
+        })
+        .collect::<Result<Vec<(String, String)>, Error>>()?;
+
+    substitution_strings.sort();
+
+    Ok(substitution_strings)
+}
+
+fn floating_hypothesis_rule_variable(rule_i: u32, mm_data: &MetamathData) -> Result<&str, Error> {
+    mm_data
+        .optimized_data
+        .symbol_number_mapping
+        .symbols
+        .get(
+            &mm_data
+                .optimized_data
+                .grammar
+                .rules
+                .get(rule_i as usize)
+                .ok_or(Error::InternalLogicError)?
+                .right_side
+                .first()
+                .ok_or(Error::InternalLogicError)?
+                .symbol_i,
+        )
+        .map(|symbol| symbol.as_str())
+        .ok_or(Error::InternalLogicError)
+}
+
+// Converts a subterm into a string, without the leading typecode
+fn node_to_expression(node: &ParseTreeNode, mm_data: &MetamathData) -> Result<String, Error> {
+    let typecode = match node {
+        ParseTreeNode::Node { rule_i, .. } => {
+            mm_data
+                .optimized_data
+                .grammar
+                .rules
+                .get(*rule_i as usize)
+                .ok_or(Error::InternalLogicError)?
+                .left_side
+                .symbol_i
+        }
+        ParseTreeNode::WorkVariable(work_variable) => work_variable.typecode_i,
+    };
+
+    let expression = ParseTree {
+        typecode,
+        top_node: node.clone(),
+    }
+    .to_expression(
+        &mm_data.optimized_data.symbol_number_mapping,
+        &mm_data.optimized_data.grammar,
+    )?;
+
+    Ok(expression
+        .split_once(' ')
+        .map(|(_, subterm)| subterm.to_string())
+        .unwrap_or(expression))
+}
+
 fn martelli_montanari_unification(
     mut theorem_parse_trees: Vec<ParseTree>,
     mut proof_line_parse_trees: Vec<ParseTree>,
@@ -573,6 +1256,7 @@ fn derive_step_ref(
     allow_discouraged: bool,
     allow_incomplete: bool,
     mm_data: &MetamathData,
+    stage_3: &MmpParserStage3Theorem,
     locate_after: Option<LocateAfterRef>,
     distinct_variable_pairs: &HashSet<(String, String)>,
     hypotheses_name_manager: &mut HypothesesNameManager,
@@ -603,14 +1287,9 @@ fn derive_step_ref(
                     if let Some(ref parse_tree) = unify_line.parse_tree {
                         proof_line_parse_trees.push(parse_tree);
 
-                        for theorem in mm_data
-                            .database_header
-                            .theorem_locate_after_iter(locate_after)
-                        {
-                            let theorem_data = mm_data
-                                .optimized_data
-                                .theorem_data
-                                .get(&theorem.label)
+                        for theorem in stage_3.theorem_locate_after_iter(mm_data, locate_after) {
+                            let theorem_data = stage_3
+                                .theorem_data(mm_data, &theorem.label)
                                 .ok_or(Error::InternalLogicError)?;
 
                             if !allow_discouraged && theorem_data.is_discouraged {
@@ -682,14 +1361,9 @@ fn derive_step_ref(
                     return Ok(None);
                 };
 
-                for theorem in mm_data
-                    .database_header
-                    .theorem_locate_after_iter(locate_after)
-                {
-                    let theorem_data = mm_data
-                        .optimized_data
-                        .theorem_data
-                        .get(&theorem.label)
+                for theorem in stage_3.theorem_locate_after_iter(mm_data, locate_after) {
+                    let theorem_data = stage_3
+                        .theorem_data(mm_data, &theorem.label)
                         .ok_or(Error::InternalLogicError)?;
 
                     if !allow_discouraged && theorem_data.is_discouraged {
@@ -1138,9 +1812,11 @@ pub fn stage_5_axiom(
     Ok(MmpParserStage5 {
         unify_result: unify_lines,
         unify_reference_numbers: Vec::new(),
+        unification_explanations: Vec::new(),
     })
 }
 
+#[derive(Clone)]
 struct StepNameManager {
     next_step_name_num: u32,
 }
@@ -6,7 +6,7 @@ use crate::{
             MmpParserStage3Theorem, MmpParserStage4Success, MmpParserStage5, MmpParserStage6,
             UnifyLine,
         },
-        verify::ProofNumber,
+        verify::{ProofNumber, Verifier},
     },
     model::{MetamathData, ParseTree, ParseTreeNode},
     util::StrIterToSpaceSeperatedString,
@@ -27,25 +27,62 @@ pub fn stage_6(
     let Some(proof_tree) = calc_proof_tree(
         &stage_5.unify_result,
         &stage_4.distinct_variable_pairs,
+        stage_3,
         mm_data,
     )?
     else {
         return Ok(MmpParserStage6 { proof: None });
     };
 
+    let mandatory_hypotheses = calc_mandatory_hypotheses(&stage_5.unify_result, mm_data);
+
+    let step_hypotheses = if matches!(settings.proof_format, ProofFormatOption::Explicit) {
+        calc_step_hypotheses(&proof_tree, stage_3, mm_data)?
+    } else {
+        HashMap::new()
+    };
+
     Ok(MmpParserStage6 {
-        proof: Some(match settings.proof_format {
-            ProofFormatOption::Uncompressed => calc_uncompressed_proof(&proof_tree),
-            ProofFormatOption::Compressed => {
-                calc_compressed_proof(proof_tree, &stage_5.unify_result, mm_data)?
-            }
-        }),
+        proof: Some(write_proof(
+            &proof_tree,
+            settings.proof_format,
+            &mandatory_hypotheses,
+            &step_hypotheses,
+        )?),
+    })
+}
+
+// Writes the proof tree in the given format. mandatory_hypotheses are the labels of the floating and essential
+// hypotheses of the theorem that is proven, which are only needed for the compressed formats. step_hypotheses maps
+// the label of every step with children to the labels of its hypotheses, which is only needed for the explicit
+// format
+pub fn write_proof(
+    proof_tree: &ProofTree,
+    proof_format: ProofFormatOption,
+    mandatory_hypotheses: &[&str],
+    step_hypotheses: &HashMap<&str, Vec<String>>,
+) -> Result<String, Error> {
+    Ok(match proof_format {
+        ProofFormatOption::Uncompressed => calc_uncompressed_proof(proof_tree),
+        ProofFormatOption::Compressed => {
+            calc_compressed_proof(proof_tree, mandatory_hypotheses, false)?
+        }
+        ProofFormatOption::CompressedNormalized => {
+            calc_compressed_proof(proof_tree, mandatory_hypotheses, true)?
+        }
+        ProofFormatOption::Explicit => {
+            let mut steps = Vec::new();
+            add_explicit_proof_steps(proof_tree, None, step_hypotheses, &mut steps)?;
+            steps.into_iter().fold_to_space_seperated_string()
+        }
+        ProofFormatOption::Packed => calc_packed_proof(proof_tree)?,
     })
 }
 
 fn calc_proof_tree<'a>(
     unify_result: &'a Vec<UnifyLine>,
     distinct_variable_pairs: &HashSet<(String, String)>,
+    stage_3: &MmpParserStage3Theorem,
     mm_data: &'a MetamathData,
 ) -> Result<Option<ProofTree<'a>>, Error> {
     let Some(qed_step_i) = unify_result.iter().position(|ul| ul.step_name == "qed") else {
@@ -109,10 +146,8 @@ fn calc_proof_tree<'a>(
                 continue;
             };
 
-            let theorem_data = mm_data
-                .optimized_data
-                .theorem_data
-                .get(&unify_line.step_ref)
+            let theorem_data = stage_3
+                .theorem_data(mm_data, &unify_line.step_ref)
                 .ok_or(Error::InternalLogicError)?;
 
             let theorem_parse_trees = theorem_data
@@ -210,11 +245,12 @@ fn calc_uncompressed_proof(proof_tree: &ProofTree) -> String {
         .fold_to_space_seperated_string()
 }
 
-fn calc_compressed_proof(
-    proof_tree: ProofTree,
-    unify_result: &Vec<UnifyLine>,
-    mm_data: &MetamathData,
-) -> Result<String, Error> {
+// The labels of the floating hypotheses of all variables used in the theorem, followed by the labels of its
+// hypotheses
+fn calc_mandatory_hypotheses<'a>(
+    unify_result: &'a Vec<UnifyLine>,
+    mm_data: &'a MetamathData,
+) -> Vec<&'a str> {
     let vars_in_theorem: HashSet<&str> = unify_result
         .iter()
         .filter(|ul| ul.is_hypothesis || ul.step_name == "qed")
@@ -245,48 +281,173 @@ fn calc_compressed_proof(
         })
         .collect();
 
-    let floating_hypotheses: Vec<&str> = mm_data
+    mm_data
         .optimized_data
         .floating_hypotheses
         .iter()
         .filter(|fh| vars_in_theorem.contains(&*fh.variable))
         .map(|fh| &*fh.label)
-        .collect();
+        .chain(
+            unify_result
+                .iter()
+                .filter(|ul| ul.is_hypothesis)
+                .map(|ul| &*ul.step_ref),
+        )
+        .collect()
+}
 
-    let hypotheses: Vec<&str> = unify_result
+// Looks up the hypotheses of every theorem that is used as a step with children in the proof tree
+fn calc_step_hypotheses<'a>(
+    proof_tree: &ProofTree<'a>,
+    stage_3: &MmpParserStage3Theorem,
+    mm_data: &MetamathData,
+) -> Result<HashMap<&'a str, Vec<String>>, Error> {
+    let step_labels: HashSet<&str> = proof_tree
         .iter()
-        .filter(|ul| ul.is_hypothesis)
-        .map(|ul| &*ul.step_ref)
+        .filter(|pt| !pt.children.is_empty())
+        .map(|pt| pt.label)
         .collect();
 
+    let mut step_hypotheses = HashMap::new();
+
+    for theorem in stage_3.theorem_locate_after_iter(mm_data, None) {
+        let Some(&label) = step_labels.get(&*theorem.label) else {
+            continue;
+        };
+
+        let hypotheses = Verifier::calc_all_hypotheses_of_theorem(
+            theorem,
+            mm_data,
+            Some(&mm_data.optimized_data.floating_hypotheses),
+        )?;
+
+        step_hypotheses.insert(
+            label,
+            hypotheses
+                .into_iter()
+                .map(|(_, hyp_label)| hyp_label.map(|l| l.to_string()).unwrap_or_else(|l| l))
+                .collect(),
+        );
+    }
+
+    Ok(step_hypotheses)
+}
+
+fn add_explicit_proof_steps(
+    proof_tree: &ProofTree,
+    hypothesis_label: Option<&str>,
+    step_hypotheses: &HashMap<&str, Vec<String>>,
+    steps: &mut Vec<String>,
+) -> Result<(), Error> {
+    if !proof_tree.children.is_empty() {
+        let hypotheses = step_hypotheses
+            .get(proof_tree.label)
+            .ok_or(Error::InternalLogicError)?;
+
+        if hypotheses.len() != proof_tree.children.len() {
+            return Err(Error::InternalLogicError);
+        }
+
+        for (child, hypothesis) in proof_tree.children.iter().zip(hypotheses) {
+            add_explicit_proof_steps(child, Some(hypothesis), step_hypotheses, steps)?;
+        }
+    }
+
+    steps.push(match hypothesis_label {
+        Some(hypothesis_label) => format!("{}={}", hypothesis_label, proof_tree.label),
+        None => proof_tree.label.to_string(),
+    });
+
+    Ok(())
+}
+
+fn calc_packed_proof(proof_tree: &ProofTree) -> Result<String, Error> {
     let mut nodes_seen: HashMap<&ProofTree<'_>, usize> = HashMap::new();
-    let compressed_proof_tree = compress_proof_tree(&proof_tree, &mut nodes_seen);
+    let compressed_proof_tree = compress_proof_tree(proof_tree, &mut nodes_seen);
+    let referenced_subproofs = calc_referenced_subproofs(&compressed_proof_tree);
 
-    let mut steps: Vec<&str> = Vec::new();
-    let mut compressed_steps: Vec<usize> = Vec::new();
+    // Local labels are numbered in the order their steps appear
+    let mut local_labels: Vec<usize> = Vec::new();
+    let mut steps: Vec<String> = Vec::new();
 
     for node in compressed_proof_tree.iter() {
         match node {
-            CompressedProofTree::ProofTree(pt, _) => {
-                if floating_hypotheses
-                    .iter()
-                    .chain(hypotheses.iter())
-                    .chain(steps.iter())
-                    .find(|s| **s == pt.label)
-                    .is_none()
-                {
-                    steps.push(pt.label);
-                }
+            CompressedProofTree::ProofTree(pt, Some(compressed_i))
+                if referenced_subproofs.binary_search(compressed_i).is_ok() =>
+            {
+                local_labels.push(*compressed_i);
+                steps.push(format!("{}:{}", local_labels.len(), pt.label));
             }
+            CompressedProofTree::ProofTree(pt, _) => steps.push(pt.label.to_string()),
             CompressedProofTree::Compressed(compressed_i) => {
-                match compressed_steps.binary_search(compressed_i) {
-                    Ok(_) => {}
-                    Err(i) => compressed_steps.insert(i, *compressed_i),
-                }
+                let local_label = local_labels
+                    .iter()
+                    .position(|ci| ci == compressed_i)
+                    .ok_or(Error::InternalLogicError)?
+                    + 1;
+                steps.push(local_label.to_string());
             }
         }
     }
 
+    Ok(steps.into_iter().fold_to_space_seperated_string())
+}
+
+// Returns the sorted indices of all subproofs that are referenced again after their first appearance
+fn calc_referenced_subproofs(compressed_proof_tree: &CompressedProofTree) -> Vec<usize> {
+    let mut referenced_subproofs: Vec<usize> = Vec::new();
+
+    for node in compressed_proof_tree.iter() {
+        if let CompressedProofTree::Compressed(compressed_i) = node {
+            if let Err(i) = referenced_subproofs.binary_search(compressed_i) {
+                referenced_subproofs.insert(i, *compressed_i);
+            }
+        }
+    }
+
+    referenced_subproofs
+}
+
+// If normalize_label_order is set, the labels in parentheses are ordered by how often they are used, so that the most
+// used labels get the shortest numbers. Labels used equally often stay in the order of their first use
+fn calc_compressed_proof(
+    proof_tree: &ProofTree,
+    mandatory_hypotheses: &[&str],
+    normalize_label_order: bool,
+) -> Result<String, Error> {
+    let mut nodes_seen: HashMap<&ProofTree<'_>, usize> = HashMap::new();
+    let compressed_proof_tree = compress_proof_tree(proof_tree, &mut nodes_seen);
+
+    let mut steps: Vec<&str> = Vec::new();
+
+    for node in compressed_proof_tree.iter() {
+        if let CompressedProofTree::ProofTree(pt, _) = node {
+            if mandatory_hypotheses
+                .iter()
+                .chain(steps.iter())
+                .find(|s| **s == pt.label)
+                .is_none()
+            {
+                steps.push(pt.label);
+            }
+        }
+    }
+
+    if normalize_label_order {
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+
+        for node in compressed_proof_tree.iter() {
+            if let CompressedProofTree::ProofTree(pt, _) = node {
+                *uses.entry(pt.label).or_default() += 1;
+            }
+        }
+
+        // sort_by_key is stable
+        steps.sort_by_key(|step| std::cmp::Reverse(uses.get(step).copied().unwrap_or(0)));
+    }
+
+    let compressed_steps = calc_referenced_subproofs(&compressed_proof_tree);
+
     let mut compressed_steps_in_order: Vec<usize> = Vec::new();
 
     for node in compressed_proof_tree.iter() {
@@ -302,9 +463,8 @@ fn calc_compressed_proof(
     for node in compressed_proof_tree.iter() {
         let step_num = match node {
             CompressedProofTree::ProofTree(pt, i) => ProofNumber {
-                number: (floating_hypotheses
+                number: (mandatory_hypotheses
                     .iter()
-                    .chain(hypotheses.iter())
                     .chain(steps.iter())
                     .position(|s| *s == pt.label)
                     .ok_or(Error::InternalLogicError)?
@@ -312,8 +472,7 @@ fn calc_compressed_proof(
                 save: i.is_some_and(|i| compressed_steps.binary_search(&i).is_ok()),
             },
             CompressedProofTree::Compressed(compressed_i) => ProofNumber {
-                number: (floating_hypotheses.len()
-                    + hypotheses.len()
+                number: (mandatory_hypotheses.len()
                     + steps.len()
                     + compressed_steps_in_order
                         .iter()
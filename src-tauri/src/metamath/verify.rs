@@ -1,7 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::{
-    metamath::mmp_parser::LocateAfterRef,
+    metamath::mmp_parser::{stage_6::ProofTree, LocateAfterRef},
     model::{self, FloatingHypothesis, MetamathData, Theorem},
     util::StrIterToSpaceSeperatedString,
     Error,
@@ -16,6 +16,15 @@ pub struct Verifier<'a> {
     proof_lines_returned: usize,
     previous_proof_line_mapping: HashMap<String, usize>,
     show: Show,
+    usage: Option<Box<ProofUsage<'a>>>,
+}
+
+// Only tracked if requested with track_usage, since it slows down verification
+#[derive(Default)]
+pub struct ProofUsage<'a> {
+    pub used_labels: HashSet<&'a str>,
+    // Ordered pairs, with the lexicographically smaller variable first
+    pub required_distinct_var_pairs: HashSet<(String, String)>,
 }
 
 #[derive(Debug, Clone)]
@@ -124,6 +133,7 @@ impl<'a> Verifier<'a> {
             proof_lines_returned: 0,
             previous_proof_line_mapping: HashMap::new(),
             show,
+            usage: None,
         }))
     }
 
@@ -437,37 +447,53 @@ impl<'a> Verifier<'a> {
         let mut proof_steps: Vec<ProofStep> = Vec::new();
         let mut proof_step_numbers: Vec<ProofNumber> = Vec::new();
 
+        // Maps local labels to the order in which their steps are saved
+        let mut local_labels: HashMap<&str, usize> = HashMap::new();
+        // Indices into proof_step_numbers and the saved step they reference. Saved steps are numbered after all
+        // label steps, so the numbers can only be calculated at the end
+        let mut local_label_references: Vec<(usize, usize)> = Vec::new();
+
         let mut incomplete = false;
 
         for token in proof.split_ascii_whitespace() {
-            if token == "?" {
+            let (local_label, label) = split_uncompressed_proof_token(token);
+
+            if label == "?" {
                 incomplete = true;
                 continue;
             }
 
+            if local_label.is_none() {
+                if let Some(&save_i) = local_labels.get(label) {
+                    local_label_references.push((proof_step_numbers.len(), save_i));
+                    proof_step_numbers.push(ProofNumber {
+                        number: 0,
+                        save: false,
+                    });
+                    continue;
+                }
+            }
+
             if let Some((i, _)) = proof_steps
                 .iter()
                 .enumerate()
-                .find(|(_, ps)| ps.label.as_ref().map(|s| *s).unwrap_or_else(|s| s) == token)
+                .find(|(_, ps)| ps.label.as_ref().map(|s| *s).unwrap_or_else(|s| s) == label)
             {
                 proof_step_numbers.push(ProofNumber {
                     number: (i + 1) as u32,
                     save: false,
                 });
+            } else if let Some(proof_step) =
+                already_calculated_proof_steps.and_then(|steps| steps.get(label))
+            {
+                proof_steps.push(proof_step.clone());
+                proof_step_numbers.push(ProofNumber {
+                    number: proof_steps.len() as u32,
+                    save: false,
+                });
             } else {
-                if let Some(computed_proof_steps) = already_calculated_proof_steps {
-                    if let Some(proof_step) = computed_proof_steps.get(token) {
-                        proof_steps.push(proof_step.clone());
-                        proof_step_numbers.push(ProofNumber {
-                            number: proof_steps.len() as u32,
-                            save: false,
-                        });
-                        continue;
-                    }
-                }
-
                 proof_steps.push(Verifier::calc_proof_step_from_label(
-                    token,
+                    label,
                     theorem,
                     metamath_data,
                     prev_flaoting_hypotheses,
@@ -477,6 +503,27 @@ impl<'a> Verifier<'a> {
                     save: false,
                 })
             }
+
+            if let Some(local_label) = local_label {
+                if local_labels
+                    .insert(local_label, local_labels.len())
+                    .is_some()
+                {
+                    return Err(Error::InvalidProofError);
+                }
+
+                proof_step_numbers
+                    .last_mut()
+                    .ok_or(Error::InternalLogicError)?
+                    .save = true;
+            }
+        }
+
+        for (step_number_i, save_i) in local_label_references {
+            proof_step_numbers
+                .get_mut(step_number_i)
+                .ok_or(Error::InternalLogicError)?
+                .number = (proof_steps.len() + save_i + 1) as u32;
         }
 
         Ok(if !incomplete {
@@ -575,6 +622,53 @@ impl<'a> Verifier<'a> {
         Err(Error::InvalidProofError)
     }
 
+    // Rebuilds the proof tree from the steps of the proof. Has to be called before any step is processed
+    pub fn calc_proof_tree(&self) -> Result<ProofTree<'_>, Error> {
+        let mut stack: Vec<ProofTree> = Vec::new();
+        let mut saved_subproofs: Vec<ProofTree> = Vec::new();
+
+        for step_number in &self.step_numbers {
+            let step_i = (step_number.number - 1) as usize;
+
+            let node = if let Some(step) = self.proof_steps.get(step_i) {
+                if stack.len() < step.hypotheses.len() {
+                    return Err(Error::InvalidProofError);
+                }
+
+                ProofTree {
+                    label: step.label.as_ref().map(|s| *s).unwrap_or_else(|s| s),
+                    children: stack.split_off(stack.len() - step.hypotheses.len()),
+                }
+            } else {
+                saved_subproofs
+                    .get(step_i - self.proof_steps.len())
+                    .ok_or(Error::InvalidProofError)?
+                    .clone()
+            };
+
+            if step_number.save {
+                saved_subproofs.push(node.clone());
+            }
+
+            stack.push(node);
+        }
+
+        match (stack.pop(), stack.is_empty()) {
+            (Some(proof_tree), true) => Ok(proof_tree),
+            _ => Err(Error::InvalidProofError),
+        }
+    }
+
+    // Records used labels and distinct variable pairs while verifying. Missing distinct variable
+    // conditions will no longer make the proof invalid, but will be added to the required pairs
+    pub fn track_usage(&mut self) {
+        self.usage = Some(Box::default());
+    }
+
+    pub fn take_usage(&mut self) -> Option<ProofUsage<'a>> {
+        self.usage.take().map(|usage| *usage)
+    }
+
     pub fn proccess_next_step(
         &mut self,
         metamath_data: &MetamathData,
@@ -590,6 +684,12 @@ impl<'a> Verifier<'a> {
             .ok_or(Error::InvalidProofError)?;
         let mut hypotheses_nums: Vec<usize> = Vec::new();
 
+        if let Some(usage) = self.usage.as_mut() {
+            if let Ok(label) = step.label {
+                usage.used_labels.insert(label);
+            }
+        }
+
         let next_stack_statement = if step.hypotheses.len() == 0 {
             step.statement
                 .clone()
@@ -601,6 +701,9 @@ impl<'a> Verifier<'a> {
                 &self.stack,
                 self.theorem_distinct_var_conditions,
                 metamath_data,
+                self.usage
+                    .as_mut()
+                    .map(|usage| &mut usage.required_distinct_var_pairs),
             )?;
             hypotheses_nums = new_hypotheses_nums;
 
@@ -695,6 +798,7 @@ impl<'a> Verifier<'a> {
         stack: &Vec<StackLine>,
         distinct_var_conditions: &HashSet<(String, String)>,
         metamath_data: &MetamathData,
+        mut required_distinct_var_pairs: Option<&mut HashSet<(String, String)>>,
     ) -> Result<(String, Vec<usize>), Error> {
         if stack.len() < step.hypotheses.len() {
             return Err(Error::InvalidProofError);
@@ -776,9 +880,18 @@ impl<'a> Verifier<'a> {
                     if let Some(var_2_sub_vars) = substitutions_variables.get(&**var_2) {
                         for &var_1_var in var_1_sub_vars.iter() {
                             for &var_2_var in var_2_sub_vars.iter() {
-                                if var_1_var == var_2_var
-                                    || !distinct_var_conditions
-                                        .contains(&(var_1_var.to_string(), var_2_var.to_string()))
+                                if var_1_var == var_2_var {
+                                    return Err(Error::InvalidProofError);
+                                }
+
+                                if let Some(pairs) = required_distinct_var_pairs.as_mut() {
+                                    pairs.insert(if var_1_var < var_2_var {
+                                        (var_1_var.to_string(), var_2_var.to_string())
+                                    } else {
+                                        (var_2_var.to_string(), var_1_var.to_string())
+                                    });
+                                } else if !distinct_var_conditions
+                                    .contains(&(var_1_var.to_string(), var_2_var.to_string()))
                                 {
                                     return Err(Error::InvalidProofError);
                                 }
@@ -842,7 +955,7 @@ impl<'a> Verifier<'a> {
             .fold_to_space_seperated_string()
     }
 
-    fn get_stack(&self) -> &Vec<StackLine> {
+    pub fn get_stack(&self) -> &Vec<StackLine> {
         &self.stack
     }
 
@@ -891,3 +1004,18 @@ impl<'a> Verifier<'a> {
         })
     }
 }
+
+// Steps of uncompressed proofs can be prefixed with a local label that later steps use to reference them (packed
+// format), and with the label of the hypothesis they fulfill (explicit format), for example "5:maj=ax-mp". Returns the
+// local label and the label of the step
+pub fn split_uncompressed_proof_token(token: &str) -> (Option<&str>, &str) {
+    let (local_label, step) = match token.split_once(':') {
+        Some((local_label, step)) => (Some(local_label), step),
+        None => (None, token),
+    };
+
+    (
+        local_label,
+        step.split_once('=').map_or(step, |(_, label)| label),
+    )
+}
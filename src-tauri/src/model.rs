@@ -1,7 +1,8 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fmt::Display,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt::{Display, Write},
     fs::File,
+    hash::Hasher,
     path::PathBuf,
     sync::Arc,
 };
@@ -20,13 +21,14 @@ use crate::{
         mm_parser::html_validation,
         mmp_parser::{stage_6::ProofTree, LocateAfterRef},
         verify::{
-            ProofStep, Show, StepResult, VerificationResult, Verifier, VerifierCreationResult,
+            self, ProofStep, Show, StepResult, VerificationResult, Verifier, VerifierCreationResult,
         },
     },
     util::{
         self, description_parser,
         earley_parser_optimized::{
-            self, EarleyOptimizedData, Grammar, GrammarRule, InputSymbol, Symbol, WorkVariable,
+            self, EarleyOptimizedData, EarleyParseFailure, Grammar, GrammarRule, InputSymbol,
+            Symbol, WorkVariable,
         },
         header_iterators::{
             ConstantIterator, ConstantLocateAfterIterator, FloatingHypothesisIterator,
@@ -34,11 +36,13 @@ use crate::{
             TheoremIterator, TheoremLocateAfterIterator, VariableIterator,
             VariableLocateAfterIterator,
         },
+        packrat_parser,
         parse_tree_node_iterator::ParseTreeNodeIterator,
+        unicode_input::UnicodeInputMapping,
         work_variable_manager::WorkVariableManager,
         StrIterToDelimiterSeperatedString, StrIterToSpaceSeperatedString,
     },
-    Error, Settings,
+    Error, ExportFormattingSettings, Settings,
 };
 use Statement::*;
 
@@ -55,13 +59,35 @@ pub struct MetamathData {
     pub logical_typecodes: Vec<LogicalTypecode>,
     pub variable_colors: Vec<VariableColor>,
     pub alt_variable_colors: Vec<VariableColor>,
+    // None for databases that were not loaded from a file
+    pub original_source: Option<OriginalSource>,
+}
+
+// The text of the database file as it was loaded, split into the parts that the top level statements, headers and
+// scope blocks were parsed from. Used to export unchanged statements exactly as they were written
+#[derive(Debug, Default)]
+pub struct OriginalSource {
+    pub file_content: String,
+    pub chunks: Vec<SourceChunk>,
+    // Whitespace after the last statement
+    pub trailing_text_start: usize,
+}
+
+#[derive(Debug)]
+pub struct SourceChunk {
+    // Includes the whitespace in front of the first token
+    pub start: usize,
+    pub end: usize,
+    // Fingerprints of the database elements that were parsed from this part of the file, in order. Scope blocks can
+    // contain any number of theorems
+    pub element_fingerprints: Vec<u64>,
 }
 
 pub struct IdManager {
     next_id: u32,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct OptimizedMetamathData {
     pub variables: HashSet<String>,
     pub floating_hypotheses: Vec<FloatingHypothesis>,
@@ -70,12 +96,22 @@ pub struct OptimizedMetamathData {
     pub header_data: HashMap<String, OptimizedHeaderData>,
     pub symbol_number_mapping: SymbolNumberMapping,
     pub grammar: Grammar,
+    // How often each constant is used in hypotheses and assertions. Calculated together with the grammar
+    pub constant_usage_counts: HashMap<String, u32>,
+    pub unicode_input_mapping: UnicodeInputMapping,
 }
 
-#[derive(Debug)]
+pub struct VerificationProofSteps<'a> {
+    pub proof_steps: HashMap<&'a str, ProofStep<'a>>,
+    // Theorem, number of prior floating hypotheses and the proof steps of its hypotheses
+    pub theorems: Vec<(&'a Theorem, usize, Vec<ProofStep<'a>>)>,
+}
+
+#[derive(Debug, Clone)]
 pub struct OptimizedTheoremData {
     pub theorem_type: TheoremType,
     pub is_discouraged: bool,
+    pub is_proof_modification_discouraged: bool,
     pub parse_trees: Option<TheoremParseTrees>,
     pub distinct_variable_pairs: HashSet<(String, String)>,
     pub axiom_dependencies: Vec<usize>,
@@ -99,7 +135,7 @@ pub enum ProofType {
     Incomplete,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TheoremParseTrees {
     pub hypotheses_parsed: Vec<ParseTree>,
     pub assertion_parsed: ParseTree,
@@ -132,12 +168,12 @@ pub enum ParsedDescriptionSegment {
     HtmlCharacterRef(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OptimizedHeaderData {
     pub description_parsed: Vec<ParsedDescriptionSegment>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct SymbolNumberMapping {
     pub symbols: HashMap<u32, String>,
     pub numbers: HashMap<String, u32>,
@@ -155,6 +191,21 @@ pub enum Statement {
     VariableStatement(Vec<Variable>),
     FloatingHypohesisStatement(FloatingHypothesis),
     TheoremStatement(Theorem),
+    ScopeStatement(ScopeStatement),
+}
+
+// The statements of ${ $} blocks, in the order they appear in the file. Theorems inside of a block still carry all
+// local variables, hypotheses and distinct variable conditions that are active for them, so that they can be verified
+// on their own
+#[derive(Debug, Clone)]
+pub enum ScopeStatement {
+    OpeningScope,
+    ClosingScope,
+    Comment(Comment),
+    Variables(Vec<Variable>),
+    FloatingHypothesis(FloatingHypothesis),
+    EssentialHypothesis(Hypothesis),
+    DistinctVariables(String),
 }
 
 #[derive(Clone, Copy)]
@@ -234,6 +285,7 @@ pub enum HeaderContentType {
     VariableStatement,
     FloatingHypothesisStatement,
     TheoremStatement,
+    ScopeStatement,
 }
 
 #[derive(Debug, Clone)]
@@ -387,6 +439,8 @@ pub struct TheoremListEntry {
     pub hypotheses: Vec<String>,
     pub assertion: String,
     pub description_parsed: Vec<ParsedDescriptionSegment>,
+    pub is_discouraged: bool,
+    pub is_proof_modification_discouraged: bool,
 }
 
 pub struct FolderData {
@@ -398,6 +452,16 @@ impl MetamathData {
     pub fn symbols_not_already_taken(&self, symbols: &Vec<&str>) -> bool {
         self.database_header.iter().all(|c| match c {
             DatabaseElement::Statement(s) => match s {
+                Statement::ScopeStatement(ScopeStatement::Variables(vars)) => {
+                    !vars.iter().any(|v| symbols.contains(&&*v.symbol))
+                }
+                Statement::ScopeStatement(ScopeStatement::FloatingHypothesis(fh)) => {
+                    !symbols.contains(&&*fh.label)
+                }
+                Statement::ScopeStatement(ScopeStatement::EssentialHypothesis(hyp)) => {
+                    !symbols.contains(&&*hyp.label)
+                }
+                Statement::ScopeStatement(_) => true,
                 Statement::CommentStatement(_) => true,
                 Statement::ConstantStatement(consts) => {
                     for c in consts {
@@ -471,6 +535,33 @@ impl MetamathData {
             )
     }
 
+    pub fn calc_expression_next_terminals(&self, expression: &str) -> Result<Vec<u32>, Error> {
+        self.optimized_data
+            .symbol_number_mapping
+            .calc_expression_next_terminals(
+                expression,
+                &self.optimized_data.grammar,
+                &self.optimized_data.floating_hypotheses,
+                &self.syntax_typecodes,
+                &self.logical_typecodes,
+            )
+    }
+
+    pub fn calc_expression_parse_failure(
+        &self,
+        expression: &str,
+    ) -> Result<EarleyParseFailure, Error> {
+        self.optimized_data
+            .symbol_number_mapping
+            .calc_expression_parse_failure(
+                expression,
+                &self.optimized_data.grammar,
+                &self.optimized_data.floating_hypotheses,
+                &self.syntax_typecodes,
+                &self.logical_typecodes,
+            )
+    }
+
     pub fn calc_optimized_theorem_data(
         &mut self,
         app: Option<&AppHandle>,
@@ -485,6 +576,9 @@ impl MetamathData {
 
         for (i, theorem) in self.database_header.theorem_iter().enumerate() {
             let is_discouraged = theorem.description.contains("(New usage is discouraged.)");
+            let is_proof_modification_discouraged = theorem
+                .description
+                .contains("(Proof modification is discouraged.)");
 
             let (description_parsed, invalid_html) = description_parser::parse_description(
                 &theorem.description,
@@ -509,6 +603,7 @@ impl MetamathData {
             let optimized_theorem_data = OptimizedTheoremData {
                 theorem_type,
                 is_discouraged,
+                is_proof_modification_discouraged,
                 distinct_variable_pairs,
                 parse_trees: None,
                 axiom_dependencies,
@@ -550,104 +645,10 @@ impl MetamathData {
                 .ok();
         }
 
-        let mut proof_steps: HashMap<&str, ProofStep> = HashMap::new();
-        let mut theorem_i: usize = 0;
-        let mut floating_hypothesis_i: usize = 0;
-        let mut theorem_vec: Vec<(&Theorem, usize, Vec<ProofStep>)> = Vec::new();
-
-        last_reported_progress = 0;
-
-        for database_element in self.database_header.iter() {
-            match database_element {
-                DatabaseElement::Statement(s) => match s {
-                    Statement::CommentStatement(_) => {}
-                    Statement::ConstantStatement(_) => {}
-                    Statement::VariableStatement(_) => {}
-                    Statement::FloatingHypohesisStatement(fh) => {
-                        floating_hypothesis_i += 1;
-
-                        proof_steps.insert(
-                            &fh.label,
-                            ProofStep {
-                                label: Ok(&fh.label),
-                                label_theorem_number: None,
-                                hypotheses: Vec::new(),
-                                statement: Err(fh.to_assertions_string()),
-                                distinct_var_conditions: None,
-                            },
-                        );
-                    }
-                    Statement::TheoremStatement(theorem) => {
-                        let theorem_data = self
-                            .optimized_data
-                            .theorem_data
-                            .get(&theorem.label)
-                            .ok_or(Error::InternalLogicError)?;
-
-                        let label_theorem_hypotheses = Verifier::calc_all_hypotheses_of_theorem(
-                            theorem,
-                            self,
-                            Some(&self.optimized_data.floating_hypotheses[..floating_hypothesis_i]),
-                        )?;
-
-                        let compressed_infered_proof_steps: Vec<ProofStep> =
-                            label_theorem_hypotheses
-                                .clone()
-                                .into_iter()
-                                .map(|(hypothesis, label)| ProofStep {
-                                    label,
-                                    label_theorem_number: None,
-                                    hypotheses: Vec::new(),
-                                    statement: hypothesis.statement,
-                                    distinct_var_conditions: None,
-                                })
-                                .collect();
-
-                        proof_steps.insert(
-                            &theorem.label,
-                            ProofStep {
-                                label: Ok(&theorem.label),
-                                label_theorem_number: Some((theorem_i + 1) as u32),
-                                hypotheses: label_theorem_hypotheses
-                                    .into_iter()
-                                    .map(|(hyp, _label)| hyp)
-                                    .collect(),
-                                statement: Ok(&theorem.assertion),
-                                distinct_var_conditions: Some(
-                                    &theorem_data.distinct_variable_pairs,
-                                ),
-                            },
-                        );
-
-                        theorem_vec.push((
-                            theorem,
-                            floating_hypothesis_i,
-                            compressed_infered_proof_steps,
-                        ));
-
-                        if let Some(ref stop_arc) = stop {
-                            let stop_bool = stop_arc.lock().or(Err(Error::InternalLogicError))?;
-                            if *stop_bool {
-                                return Err(Error::OpenDatabaseStoppedEarlyError);
-                            }
-                        }
-
-                        if let Some(app_handle) = app {
-                            let progress = ((theorem_i as u32) * 100)
-                                / (self.optimized_data.theorem_amount * 2);
-
-                            if progress > last_reported_progress {
-                                app_handle.emit("verification-progress", progress).ok();
-                                last_reported_progress = progress;
-                            }
-                        }
-
-                        theorem_i += 1;
-                    }
-                },
-                DatabaseElement::Header(_, _) => {}
-            }
-        }
+        let VerificationProofSteps {
+            proof_steps,
+            theorems: theorem_vec,
+        } = self.calc_verification_proof_steps(app, stop.as_ref(), &mut last_reported_progress)?;
 
         struct VerifictationProgress {
             pub last_reported_progress: u32,
@@ -749,6 +750,120 @@ impl MetamathData {
         Ok(invalid_description_html)
     }
 
+    // Precalculates the proof steps of all floating hypotheses and theorems, so that proofs can be
+    // verified without searching the database for every label
+    pub fn calc_verification_proof_steps<'a>(
+        &'a self,
+        app: Option<&AppHandle>,
+        stop: Option<&Arc<std::sync::Mutex<bool>>>,
+        last_reported_progress: &mut u32,
+    ) -> Result<VerificationProofSteps<'a>, Error> {
+        let mut proof_steps: HashMap<&str, ProofStep> = HashMap::new();
+        let mut theorem_i: usize = 0;
+        let mut floating_hypothesis_i: usize = 0;
+        let mut theorem_vec: Vec<(&Theorem, usize, Vec<ProofStep>)> = Vec::new();
+
+        *last_reported_progress = 0;
+
+        for database_element in self.database_header.iter() {
+            match database_element {
+                DatabaseElement::Statement(s) => match s {
+                    Statement::ScopeStatement(_) => {}
+                    Statement::CommentStatement(_) => {}
+                    Statement::ConstantStatement(_) => {}
+                    Statement::VariableStatement(_) => {}
+                    Statement::FloatingHypohesisStatement(fh) => {
+                        floating_hypothesis_i += 1;
+
+                        proof_steps.insert(
+                            &fh.label,
+                            ProofStep {
+                                label: Ok(&fh.label),
+                                label_theorem_number: None,
+                                hypotheses: Vec::new(),
+                                statement: Err(fh.to_assertions_string()),
+                                distinct_var_conditions: None,
+                            },
+                        );
+                    }
+                    Statement::TheoremStatement(theorem) => {
+                        let theorem_data = self
+                            .optimized_data
+                            .theorem_data
+                            .get(&theorem.label)
+                            .ok_or(Error::InternalLogicError)?;
+
+                        let label_theorem_hypotheses = Verifier::calc_all_hypotheses_of_theorem(
+                            theorem,
+                            self,
+                            Some(&self.optimized_data.floating_hypotheses[..floating_hypothesis_i]),
+                        )?;
+
+                        let compressed_infered_proof_steps: Vec<ProofStep> =
+                            label_theorem_hypotheses
+                                .clone()
+                                .into_iter()
+                                .map(|(hypothesis, label)| ProofStep {
+                                    label,
+                                    label_theorem_number: None,
+                                    hypotheses: Vec::new(),
+                                    statement: hypothesis.statement,
+                                    distinct_var_conditions: None,
+                                })
+                                .collect();
+
+                        proof_steps.insert(
+                            &theorem.label,
+                            ProofStep {
+                                label: Ok(&theorem.label),
+                                label_theorem_number: Some((theorem_i + 1) as u32),
+                                hypotheses: label_theorem_hypotheses
+                                    .into_iter()
+                                    .map(|(hyp, _label)| hyp)
+                                    .collect(),
+                                statement: Ok(&theorem.assertion),
+                                distinct_var_conditions: Some(
+                                    &theorem_data.distinct_variable_pairs,
+                                ),
+                            },
+                        );
+
+                        theorem_vec.push((
+                            theorem,
+                            floating_hypothesis_i,
+                            compressed_infered_proof_steps,
+                        ));
+
+                        if let Some(stop_arc) = stop {
+                            let stop_bool = stop_arc.lock().or(Err(Error::InternalLogicError))?;
+                            if *stop_bool {
+                                return Err(Error::OpenDatabaseStoppedEarlyError);
+                            }
+                        }
+
+                        if let Some(app_handle) = app {
+                            let progress = ((theorem_i as u32) * 100)
+                                / (self.optimized_data.theorem_amount * 2);
+
+                            if progress > *last_reported_progress {
+                                app_handle.emit("verification-progress", progress).ok();
+                                *last_reported_progress = progress;
+                            }
+                        }
+
+                        theorem_i += 1;
+                    }
+                },
+                DatabaseElement::Header(_, _) => {}
+            }
+        }
+
+        Ok(VerificationProofSteps {
+            proof_steps,
+            theorems: theorem_vec,
+        })
+    }
+
     pub fn update_optimized_theorem_data(
         &mut self,
         theorem_label: &str,
@@ -765,6 +880,9 @@ impl MetamathData {
             .ok_or(Error::InternalLogicError)?;
 
         let is_discouraged = theorem.description.contains("(New usage is discouraged.)");
+        let is_proof_modification_discouraged = theorem
+            .description
+            .contains("(Proof modification is discouraged.)");
 
         let (description_parsed, _) = description_parser::parse_description(
             &theorem.description,
@@ -827,6 +945,7 @@ impl MetamathData {
         let optimized_theorem_data = OptimizedTheoremData {
             theorem_type,
             is_discouraged,
+            is_proof_modification_discouraged,
             distinct_variable_pairs,
             parse_trees,
             axiom_dependencies,
@@ -844,6 +963,223 @@ impl MetamathData {
         Ok(is_syntax_axiom)
     }
 
+    // Replaces the proof and description of a theorem while keeping its place in the database. The new proof is
+    // verified, and the dependencies of all later theorems are recalculated, since they may depend on it. Returns the
+    // old proof and description
+    pub fn replace_theorem_proof_and_description(
+        &mut self,
+        theorem_label: &str,
+        proof: Option<String>,
+        description: String,
+    ) -> Result<(Option<String>, String), Error> {
+        let (allowed_tags_and_attributes, allowed_css_properties) =
+            html_validation::create_rule_structs();
+
+        let theorem = self
+            .database_header
+            .find_theorem_by_label_mut(theorem_label)
+            .ok_or(Error::NotFoundError)?;
+
+        let old_proof = std::mem::replace(&mut theorem.proof, proof);
+        let old_description = std::mem::replace(&mut theorem.description, description);
+
+        let (theorem_i, theorem) = self
+            .database_header
+            .find_theorem_and_index_by_label(theorem_label)
+            .ok_or(Error::InternalLogicError)?;
+
+        let verify_result = Verifier::verify_proof(
+            theorem,
+            self,
+            Some(&util::calc_distinct_variable_pairs(&theorem.distincts)),
+            None,
+            None,
+            None,
+        );
+
+        let proof_type = match verify_result {
+            Ok(VerificationResult::Correct) => ProofType::Correct,
+            Ok(VerificationResult::Incomplete) => ProofType::Incomplete,
+            Ok(VerificationResult::Incorrect) | Err(_) => {
+                let theorem = self
+                    .database_header
+                    .find_theorem_by_label_mut(theorem_label)
+                    .ok_or(Error::InternalLogicError)?;
+                theorem.proof = old_proof;
+                theorem.description = old_description;
+                return Err(Error::InvalidProofError);
+            }
+        };
+
+        let (description_parsed, _) = description_parser::parse_description(
+            &theorem.description,
+            &self.database_header,
+            &allowed_tags_and_attributes,
+            &allowed_css_properties,
+        );
+
+        let old_labels: Vec<&str> = old_proof
+            .as_deref()
+            .map(Theorem::calc_proof_labels)
+            .unwrap_or_default();
+        let new_labels: Vec<&str> = theorem
+            .proof
+            .as_deref()
+            .map(Theorem::calc_proof_labels)
+            .unwrap_or_default();
+
+        for label in &old_labels {
+            if let Some(theorem_data) = self.optimized_data.theorem_data.get_mut(*label) {
+                theorem_data.references.retain(|&i| i != theorem_i);
+            }
+        }
+        for label in &new_labels {
+            if let Some(theorem_data) = self.optimized_data.theorem_data.get_mut(*label) {
+                theorem_data.references.push(theorem_i);
+                theorem_data.references.sort();
+            }
+        }
+
+        let theorem_data = self
+            .optimized_data
+            .theorem_data
+            .get_mut(theorem_label)
+            .ok_or(Error::InternalLogicError)?;
+
+        theorem_data.is_discouraged = theorem.description.contains("(New usage is discouraged.)");
+        theorem_data.is_proof_modification_discouraged = theorem
+            .description
+            .contains("(Proof modification is discouraged.)");
+        theorem_data.description_parsed = description_parsed;
+        if let TheoremType::Theorem(proof_type_ref) = &mut theorem_data.theorem_type {
+            *proof_type_ref = proof_type;
+        }
+
+        self.recalc_dependencies_starting_at(theorem_i);
+
+        Ok((old_proof, old_description))
+    }
+
+    // Recalculates the axiom and definition dependencies and whether proofs are recursively incomplete, for all
+    // theorems starting at the given index
+    fn recalc_dependencies_starting_at(&mut self, start_theorem_i: usize) {
+        for theorem in self.database_header.theorem_iter().skip(start_theorem_i) {
+            let Some(proof) = theorem.proof.as_ref() else {
+                continue;
+            };
+
+            let (axiom_dependencies, definition_dependencies) =
+                Theorem::calc_dependencies_from_labels(
+                    &Theorem::calc_proof_labels(proof),
+                    |label| self.optimized_data.theorem_data.get(label),
+                );
+
+            let recursively_incomplete = theorem.calc_recursively_incomplete(self);
+
+            let Some(theorem_data) = self.optimized_data.theorem_data.get_mut(&theorem.label)
+            else {
+                continue;
+            };
+
+            theorem_data.axiom_dependencies = axiom_dependencies;
+            theorem_data.definition_dependencies = definition_dependencies;
+
+            if let TheoremType::Theorem(
+                proof_type @ (ProofType::Correct | ProofType::CorrectButRecursivelyIncomplete),
+            ) = &mut theorem_data.theorem_type
+            {
+                *proof_type = if recursively_incomplete {
+                    ProofType::CorrectButRecursivelyIncomplete
+                } else {
+                    ProofType::Correct
+                };
+            }
+        }
+    }
+
+    // Calculates the optimized data of a theorem that is not part of the database, without changing the database.
+    // The proof is not verified again, it is expected to come from a unified mmp file. Since the theorem has no index
+    // in the database, it adds no axiom or definition dependencies of its own
+    pub fn calc_optimized_theorem_data_of_unadded_theorem<'a>(
+        &'a self,
+        theorem: &Theorem,
+        theorem_data: impl Fn(&str) -> Option<&'a OptimizedTheoremData>,
+        settings: &Settings,
+    ) -> Result<OptimizedTheoremData, Error> {
+        let (allowed_tags_and_attributes, allowed_css_properties) =
+            html_validation::create_rule_structs();
+
+        let is_discouraged = theorem.description.contains("(New usage is discouraged.)");
+        let is_proof_modification_discouraged = theorem
+            .description
+            .contains("(Proof modification is discouraged.)");
+
+        let (description_parsed, _) = description_parser::parse_description(
+            &theorem.description,
+            &self.database_header,
+            &allowed_tags_and_attributes,
+            &allowed_css_properties,
+        );
+
+        let mut theorem_type = theorem.calc_theorem_type_without_verification(self, settings)?;
+
+        let (axiom_dependencies, definition_dependencies) = match theorem.proof.as_ref() {
+            Some(proof) => {
+                let labels = Theorem::calc_proof_labels(proof);
+
+                if let TheoremType::Theorem(proof_type) = &mut theorem_type {
+                    if proof.contains('?') {
+                        *proof_type = ProofType::Incomplete;
+                    } else if labels.iter().any(|label| {
+                        theorem_data(label).is_some_and(|data| {
+                            matches!(
+                                data.theorem_type,
+                                TheoremType::Theorem(
+                                    ProofType::CorrectButRecursivelyIncomplete
+                                        | ProofType::Incomplete
+                                )
+                            )
+                        })
+                    }) {
+                        *proof_type = ProofType::CorrectButRecursivelyIncomplete;
+                    }
+                }
+
+                Theorem::calc_dependencies_from_labels(&labels, &theorem_data)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let parse_trees = if matches!(theorem_type, TheoremType::Theorem(_)) {
+            let (assertion_parsed, hypotheses_parsed) = theorem.calc_parse_trees(
+                &self.optimized_data.grammar,
+                &self.optimized_data.symbol_number_mapping,
+                &self.optimized_data.floating_hypotheses,
+                &self.syntax_typecodes,
+                &self.logical_typecodes,
+            )?;
+
+            Some(TheoremParseTrees {
+                hypotheses_parsed,
+                assertion_parsed,
+            })
+        } else {
+            None
+        };
+
+        Ok(OptimizedTheoremData {
+            theorem_type,
+            is_discouraged,
+            is_proof_modification_discouraged,
+            distinct_variable_pairs: util::calc_distinct_variable_pairs(&theorem.distincts),
+            parse_trees,
+            axiom_dependencies,
+            definition_dependencies,
+            references: Vec::new(),
+            description_parsed,
+        })
+    }
+
     pub fn calc_optimized_header_data(
         &mut self,
         allowed_tags_and_attributes: &HashMap<String, HashSet<String>>,
@@ -1057,8 +1393,68 @@ impl TheoremParseTrees {
     }
 }
 
+impl DatabaseElement<'_> {
+    // Changes whenever anything about the element changes that could change how it is written to a file
+    pub fn calc_fingerprint(&self) -> u64 {
+        let mut hasher = FingerprintHasher(DefaultHasher::new());
+
+        match self {
+            DatabaseElement::Header(header, depth) => {
+                write!(
+                    hasher,
+                    "{}{:?}{:?}",
+                    depth, header.title, header.description
+                )
+                .ok();
+            }
+            DatabaseElement::Statement(statement) => {
+                write!(hasher, "{:?}", statement).ok();
+            }
+        }
+
+        hasher.0.finish()
+    }
+}
+
+// Hashes formatted text without allocating it
+struct FingerprintHasher(DefaultHasher);
+
+impl std::fmt::Write for FingerprintHasher {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
 impl Statement {
     pub fn write_mm_string(&self, target: &mut String) {
+        self.write_mm_string_formatted(target, &ExportFormattingSettings::default());
+    }
+
+    pub fn write_mm_string_formatted(
+        &self,
+        target: &mut String,
+        formatting: &ExportFormattingSettings,
+    ) {
+        self.write_mm_string_in_scope(target, formatting, 0);
+    }
+
+    // scope_depth is the number of ${ $} blocks the statement is in. Theorems outside of any block write their own
+    // block, if they have hypotheses or distinct variable conditions
+    pub fn write_mm_string_in_scope(
+        &self,
+        target: &mut String,
+        formatting: &ExportFormattingSettings,
+        scope_depth: u32,
+    ) {
+        let line_width = formatting.line_width as usize;
+        // Proofs that are not wrapped are written on a single line
+        let proof_line_width = if formatting.wrap_proofs {
+            line_width
+        } else {
+            usize::MAX
+        };
+
         match self {
             Self::CommentStatement(comment) => {
                 target.push_str("$(");
@@ -1072,51 +1468,74 @@ impl Statement {
                 }
                 target.push_str(comment.text.trim_ascii_end());
                 // write_text_wrapped(target, &comment.text, "   ");
-                write_text_wrapped(target, "$)", "   ");
+                write_text_wrapped(target, "$)", "   ", line_width);
             }
             Self::ConstantStatement(constants) => {
                 target.push_str("  $c");
                 for constant in constants {
-                    write_text_wrapped(target, &constant.symbol, "   ");
+                    write_text_wrapped(target, &constant.symbol, "   ", line_width);
                 }
-                write_text_wrapped(target, "$.", "   ");
+                write_text_wrapped(target, "$.", "   ", line_width);
             }
             Self::VariableStatement(variables) => {
                 target.push_str("  $v");
                 for variable in variables {
-                    write_text_wrapped(target, &variable.symbol, "   ");
+                    write_text_wrapped(target, &variable.symbol, "   ", line_width);
                 }
-                write_text_wrapped(target, "$.", "   ");
+                write_text_wrapped(target, "$.", "   ", line_width);
             }
             Self::FloatingHypohesisStatement(floating_hypothesis) => {
                 target.push_str("  ");
                 target.push_str(&floating_hypothesis.label);
-                write_text_wrapped(target, "$f", "   ");
-                write_text_wrapped(target, &floating_hypothesis.typecode, "   ");
-                write_text_wrapped(target, &floating_hypothesis.variable, "   ");
-                write_text_wrapped(target, "$.", "   ");
+                write_text_wrapped(target, "$f", "   ", line_width);
+                write_text_wrapped(target, &floating_hypothesis.typecode, "   ", line_width);
+                write_text_wrapped(target, &floating_hypothesis.variable, "   ", line_width);
+                write_text_wrapped(target, "$.", "   ", line_width);
             }
             Self::TheoremStatement(theorem) => {
-                let scoped = !(theorem.distincts.is_empty() && theorem.hypotheses.is_empty());
-                let scoped_offset = if scoped { 2 } else { 0 };
+                let scoped = scope_depth == 0
+                    && !(theorem.distincts.is_empty() && theorem.hypotheses.is_empty());
+                let scoped_offset = if scoped {
+                    formatting.block_indentation
+                } else {
+                    scope_depth * formatting.block_indentation
+                };
+
+                // Inside of a block the distinct variable conditions and hypotheses are statements of the block
+                let (distincts, hypotheses): (&[String], &[Hypothesis]) = if scope_depth == 0 {
+                    (&theorem.distincts, &theorem.hypotheses)
+                } else {
+                    (&[], &[])
+                };
 
                 if scoped {
                     target.push_str("  ${\n");
                 }
 
-                for dist_vars in &theorem.distincts {
-                    target.push_str("    $d");
-                    write_text_wrapped(target, dist_vars, "       ");
-                    write_text_wrapped(target, "$.", "       ");
+                for dist_vars in distincts {
+                    target.push_str(util::spaces(scoped_offset + 2));
+                    target.push_str("$d");
+                    write_text_wrapped(
+                        target,
+                        dist_vars,
+                        util::spaces(scoped_offset + 5),
+                        line_width,
+                    );
+                    write_text_wrapped(target, "$.", util::spaces(scoped_offset + 5), line_width);
                     target.push('\n');
                 }
 
-                for hyp in &theorem.hypotheses {
-                    target.push_str("    ");
+                for hyp in hypotheses {
+                    target.push_str(util::spaces(scoped_offset + 2));
                     target.push_str(&hyp.label);
-                    write_text_wrapped(target, "$e", "       ");
-                    write_text_wrapped(target, &hyp.expression, "       ");
-                    write_text_wrapped(target, "$.", "       ");
+                    write_text_wrapped(target, "$e", util::spaces(scoped_offset + 5), line_width);
+                    write_text_wrapped(
+                        target,
+                        &hyp.expression,
+                        util::spaces(scoped_offset + 5),
+                        line_width,
+                    );
+                    write_text_wrapped(target, "$.", util::spaces(scoped_offset + 5), line_width);
                     target.push('\n');
                 }
 
@@ -1127,8 +1546,9 @@ impl Statement {
                         target,
                         &theorem.description,
                         util::spaces(scoped_offset + 5),
+                        line_width,
                     );
-                    write_text_wrapped(target, "$)", util::spaces(scoped_offset + 5));
+                    write_text_wrapped(target, "$)", util::spaces(scoped_offset + 5), line_width);
                     target.push('\n');
                 }
 
@@ -1136,40 +1556,83 @@ impl Statement {
                 target.push_str(&theorem.label);
                 match &theorem.proof {
                     None => {
-                        write_text_wrapped(target, "$a", util::spaces(scoped_offset + 4));
+                        write_text_wrapped(
+                            target,
+                            "$a",
+                            util::spaces(scoped_offset + 4),
+                            line_width,
+                        );
                         write_text_wrapped(
                             target,
                             &theorem.assertion,
                             util::spaces(scoped_offset + 4),
+                            line_width,
+                        );
+                        write_text_wrapped(
+                            target,
+                            "$.",
+                            util::spaces(scoped_offset + 4),
+                            line_width,
                         );
-                        write_text_wrapped(target, "$.", util::spaces(scoped_offset + 4));
                     }
                     Some(proof) => {
-                        write_text_wrapped(target, "$p", util::spaces(scoped_offset + 4));
+                        write_text_wrapped(
+                            target,
+                            "$p",
+                            util::spaces(scoped_offset + 4),
+                            line_width,
+                        );
                         write_text_wrapped(
                             target,
                             &theorem.assertion,
                             util::spaces(scoped_offset + 4),
+                            line_width,
+                        );
+                        write_text_wrapped(
+                            target,
+                            "$=",
+                            util::spaces(scoped_offset + 4),
+                            line_width,
                         );
-                        write_text_wrapped(target, "$=", util::spaces(scoped_offset + 4));
                         target.push('\n');
                         target.push_str(util::spaces(scoped_offset + 3));
                         if proof.starts_with('(') {
                             // should always be the case
                             if let Some((labels, steps)) = proof.split_once(')') {
-                                write_text_wrapped(target, labels, util::spaces(scoped_offset + 4));
-                                write_text_wrapped(target, ")", util::spaces(scoped_offset + 4));
+                                write_text_wrapped(
+                                    target,
+                                    labels,
+                                    util::spaces(scoped_offset + 4),
+                                    proof_line_width,
+                                );
+                                write_text_wrapped(
+                                    target,
+                                    ")",
+                                    util::spaces(scoped_offset + 4),
+                                    proof_line_width,
+                                );
                                 target.push(' ');
                                 write_text_wrapped_no_whitespace(
                                     target,
                                     steps,
                                     util::spaces(scoped_offset + 4),
+                                    proof_line_width,
                                 );
                             }
                         } else {
-                            write_text_wrapped(target, proof, util::spaces(scoped_offset + 4));
+                            write_text_wrapped(
+                                target,
+                                proof,
+                                util::spaces(scoped_offset + 4),
+                                proof_line_width,
+                            );
                         }
-                        write_text_wrapped(target, "$.", util::spaces(scoped_offset + 4));
+                        write_text_wrapped(
+                            target,
+                            "$.",
+                            util::spaces(scoped_offset + 4),
+                            proof_line_width,
+                        );
                     }
                 }
 
@@ -1177,17 +1640,67 @@ impl Statement {
                     target.push_str("\n  $}");
                 }
             }
+            Self::ScopeStatement(scope_statement) => {
+                // The opening and closing statements of a block are not inside of it themselves
+                let offset = scope_depth * formatting.block_indentation;
+                let indentation = util::spaces(offset + 2);
+                let continuation_indentation = util::spaces(offset + 5);
+
+                target.push_str(indentation);
+
+                match scope_statement {
+                    ScopeStatement::OpeningScope => target.push_str("${"),
+                    ScopeStatement::ClosingScope => target.push_str("$}"),
+                    ScopeStatement::Comment(comment) => {
+                        target.push_str("$(");
+                        write_text_wrapped_maintain_paragraphs(
+                            target,
+                            &comment.text,
+                            continuation_indentation,
+                            line_width,
+                        );
+                        write_text_wrapped(target, "$)", continuation_indentation, line_width);
+                    }
+                    ScopeStatement::Variables(variables) => {
+                        target.push_str("$v");
+                        for variable in variables {
+                            write_text_wrapped(
+                                target,
+                                &variable.symbol,
+                                continuation_indentation,
+                                line_width,
+                            );
+                        }
+                        write_text_wrapped(target, "$.", continuation_indentation, line_width);
+                    }
+                    ScopeStatement::FloatingHypothesis(floating_hypothesis) => {
+                        target.push_str(&floating_hypothesis.label);
+                        for token in [
+                            "$f",
+                            &floating_hypothesis.typecode,
+                            &floating_hypothesis.variable,
+                            "$.",
+                        ] {
+                            write_text_wrapped(target, token, continuation_indentation, line_width);
+                        }
+                    }
+                    ScopeStatement::EssentialHypothesis(hypothesis) => {
+                        target.push_str(&hypothesis.label);
+                        for token in ["$e", &hypothesis.expression, "$."] {
+                            write_text_wrapped(target, token, continuation_indentation, line_width);
+                        }
+                    }
+                    ScopeStatement::DistinctVariables(distinct_variables) => {
+                        target.push_str("$d");
+                        for token in [&**distinct_variables, "$."] {
+                            write_text_wrapped(target, token, continuation_indentation, line_width);
+                        }
+                    }
+                }
+            }
         }
     }
 
-    pub fn insert_mm_string(&self, target: &mut String, insert_pos: usize) {
-        let mut mm_string = String::new();
-
-        self.write_mm_string(&mut mm_string);
-
-        target.insert_str(insert_pos, &mm_string);
-    }
-
     pub fn to_header_content_representation(&self) -> HeaderContentRepresentation {
         match self {
             CommentStatement(_) => HeaderContentRepresentation {
@@ -1216,6 +1729,10 @@ impl Statement {
                 content_type: HeaderContentType::TheoremStatement,
                 title: theorem.label.clone(),
             },
+            ScopeStatement(_) => HeaderContentRepresentation {
+                content_type: HeaderContentType::ScopeStatement,
+                title: String::new(),
+            },
         }
     }
 
@@ -1227,6 +1744,7 @@ impl Statement {
         let mut result_string = String::new();
 
         match self {
+            Statement::ScopeStatement(_) => return Err(Error::InternalLogicError),
             Statement::CommentStatement(comment) => {
                 result_string.push('*');
                 result_string.push_str(&comment.text);
@@ -2194,6 +2712,116 @@ impl SymbolNumberMapping {
         let expression_input_vec =
             self.expression_to_input_vec_skip_first(expression, floating_hypotheses)?;
 
+        let (typecode, syntax_typecode_number) =
+            self.calc_typecode_numbers(expression, syntax_typecodes, logical_typecodes)?;
+
+        let top_node = earley_parser_optimized::earley_parse(
+            grammar,
+            &expression_input_vec,
+            vec![Symbol {
+                symbol_i: syntax_typecode_number,
+            }],
+            self,
+        )?
+        .ok_or(Error::ExpressionParseError)?
+        .into_iter()
+        .next()
+        .ok_or(Error::InternalLogicError)?;
+
+        Ok(ParseTree { typecode, top_node })
+    }
+
+    // Used to parse the statements of the database. Tries the faster packrat parser first and falls back to the Earley
+    // parser
+    pub fn bulk_expression_to_parse_tree(
+        &self,
+        expression: &str,
+        grammar: &Grammar,
+        floating_hypotheses: &Vec<FloatingHypothesis>,
+        syntax_typecodes: &Vec<SyntaxTypecode>,
+        logical_typecodes: &Vec<LogicalTypecode>,
+    ) -> Result<ParseTree, Error> {
+        let expression_input_vec =
+            self.expression_to_input_vec_skip_first(expression, floating_hypotheses)?;
+
+        let (typecode, syntax_typecode_number) =
+            self.calc_typecode_numbers(expression, syntax_typecodes, logical_typecodes)?;
+
+        match packrat_parser::packrat_parse(
+            grammar,
+            &expression_input_vec,
+            syntax_typecode_number,
+            self,
+        )? {
+            Some(top_node) => Ok(ParseTree { typecode, top_node }),
+            None => self.expression_to_parse_tree(
+                expression,
+                grammar,
+                floating_hypotheses,
+                syntax_typecodes,
+                logical_typecodes,
+            ),
+        }
+    }
+
+    // Calculates where and why parsing an expression failed, after expression_to_parse_tree returned an
+    // ExpressionParseError
+    pub fn calc_expression_parse_failure(
+        &self,
+        expression: &str,
+        grammar: &Grammar,
+        floating_hypotheses: &Vec<FloatingHypothesis>,
+        syntax_typecodes: &Vec<SyntaxTypecode>,
+        logical_typecodes: &Vec<LogicalTypecode>,
+    ) -> Result<EarleyParseFailure, Error> {
+        let expression_input_vec =
+            self.expression_to_input_vec_skip_first(expression, floating_hypotheses)?;
+
+        let (_, syntax_typecode_number) =
+            self.calc_typecode_numbers(expression, syntax_typecodes, logical_typecodes)?;
+
+        earley_parser_optimized::calc_earley_parse_failure(
+            grammar,
+            &expression_input_vec,
+            vec![Symbol {
+                symbol_i: syntax_typecode_number,
+            }],
+            self,
+        )
+    }
+
+    // Returns all terminals that can follow the given beginning of an expression
+    pub fn calc_expression_next_terminals(
+        &self,
+        expression: &str,
+        grammar: &Grammar,
+        floating_hypotheses: &Vec<FloatingHypothesis>,
+        syntax_typecodes: &Vec<SyntaxTypecode>,
+        logical_typecodes: &Vec<LogicalTypecode>,
+    ) -> Result<Vec<u32>, Error> {
+        let expression_input_vec =
+            self.expression_to_input_vec_skip_first(expression, floating_hypotheses)?;
+
+        let (_, syntax_typecode_number) =
+            self.calc_typecode_numbers(expression, syntax_typecodes, logical_typecodes)?;
+
+        earley_parser_optimized::calc_next_terminals(
+            grammar,
+            &expression_input_vec,
+            vec![Symbol {
+                symbol_i: syntax_typecode_number,
+            }],
+            self,
+        )
+    }
+
+    // Returns the number of the typecode of the expression and the number of the syntax typecode it is parsed as
+    pub fn calc_typecode_numbers(
+        &self,
+        expression: &str,
+        syntax_typecodes: &Vec<SyntaxTypecode>,
+        logical_typecodes: &Vec<LogicalTypecode>,
+    ) -> Result<(u32, u32), Error> {
         let typecode_str = expression
             .split_ascii_whitespace()
             .next()
@@ -2231,20 +2859,7 @@ impl SymbolNumberMapping {
             .get(&format!("${}", syntax_typecode))
             .ok_or(Error::SyntaxTypecodeWithoutFloatHypsError)?;
 
-        let top_node = earley_parser_optimized::earley_parse(
-            grammar,
-            &expression_input_vec,
-            vec![Symbol {
-                symbol_i: syntax_typecode_number,
-            }],
-            self,
-        )?
-        .ok_or(Error::ExpressionParseError)?
-        .into_iter()
-        .next()
-        .ok_or(Error::InternalLogicError)?;
-
-        Ok(ParseTree { typecode, top_node })
+        Ok((typecode, syntax_typecode_number))
     }
 
     pub fn is_typecode(&self, number: u32) -> bool {
@@ -2544,6 +3159,8 @@ impl Theorem {
         theorem_number: u32,
         optimized_data: &OptimizedMetamathData,
     ) -> TheoremListEntry {
+        let theorem_data = optimized_data.theorem_data.get(&self.label);
+
         TheoremListEntry {
             label: self.label.clone(),
             theorem_number,
@@ -2553,11 +3170,12 @@ impl Theorem {
                 .map(|hypothesis| hypothesis.expression.clone())
                 .collect(),
             assertion: self.assertion.clone(),
-            description_parsed: optimized_data
-                .theorem_data
-                .get(&self.label)
+            description_parsed: theorem_data
                 .map(|t_d| t_d.description_parsed.clone())
                 .unwrap_or(Vec::new()),
+            is_discouraged: theorem_data.is_some_and(|t_d| t_d.is_discouraged),
+            is_proof_modification_discouraged: theorem_data
+                .is_some_and(|t_d| t_d.is_proof_modification_discouraged),
         }
     }
 
@@ -2573,7 +3191,7 @@ impl Theorem {
             .hypotheses
             .iter()
             .map(|h| {
-                symbol_number_mapping.expression_to_parse_tree(
+                symbol_number_mapping.bulk_expression_to_parse_tree(
                     &h.expression,
                     grammar,
                     floating_hypotheses,
@@ -2583,7 +3201,7 @@ impl Theorem {
             })
             .collect::<Result<Vec<ParseTree>, Error>>()?;
 
-        let assertion_parsed = symbol_number_mapping.expression_to_parse_tree(
+        let assertion_parsed = symbol_number_mapping.bulk_expression_to_parse_tree(
             &self.assertion,
             grammar,
             floating_hypotheses,
@@ -2613,7 +3231,23 @@ impl Theorem {
             };
         };
 
-        let labels: Vec<&str> = if proof.starts_with("(") {
+        let labels = Theorem::calc_proof_labels(proof);
+
+        // Add references to prior theorems
+        for label in &labels {
+            if let Some(theorem_data) = optimized_data.theorem_data.get_mut(*label) {
+                theorem_data.references.push(i);
+            }
+        }
+
+        Theorem::calc_dependencies_from_labels(&labels, |label| {
+            optimized_data.theorem_data.get(label)
+        })
+    }
+
+    // Returns every label referenced in the proof once, for compressed proofs the labels in parentheses
+    pub fn calc_proof_labels(proof: &str) -> Vec<&str> {
+        if proof.starts_with("(") {
             proof
                 .split_ascii_whitespace()
                 .skip(1)
@@ -2622,8 +3256,8 @@ impl Theorem {
         } else {
             let mut already_seen: HashSet<&str> = HashSet::new();
 
-            proof
-                .split_ascii_whitespace()
+            Theorem::calc_uncompressed_proof_step_labels(proof)
+                .into_iter()
                 .filter(|label| {
                     if already_seen.contains(label) {
                         false
@@ -2633,26 +3267,36 @@ impl Theorem {
                     }
                 })
                 .collect()
-        };
-
-        // Add references to prior theorems
-        for label in &labels {
-            if let Some(theorem_data) = optimized_data.theorem_data.get_mut(*label) {
-                theorem_data.references.push(i);
-            }
         }
+    }
+
+    // Returns the label of every step of an uncompressed proof, leaving out the references to earlier steps of the
+    // packed format
+    pub fn calc_uncompressed_proof_step_labels(proof: &str) -> Vec<&str> {
+        let mut local_labels: HashSet<&str> = HashSet::new();
 
-        Theorem::calc_dependencies_from_labels(&labels, optimized_data)
+        proof
+            .split_ascii_whitespace()
+            .map(verify::split_uncompressed_proof_token)
+            .filter(|(local_label, label)| match local_label {
+                Some(local_label) => {
+                    local_labels.insert(local_label);
+                    true
+                }
+                None => !local_labels.contains(label),
+            })
+            .map(|(_, label)| label)
+            .collect()
     }
 
-    pub fn calc_dependencies_from_labels(
+    pub fn calc_dependencies_from_labels<'a>(
         labels: &Vec<&str>,
-        optimized_data: &OptimizedMetamathData,
+        theorem_data: impl Fn(&str) -> Option<&'a OptimizedTheoremData>,
     ) -> (Vec<usize>, Vec<usize>) {
         let dependencies: Vec<(&Vec<usize>, &Vec<usize>)> = labels
             .iter()
             .filter_map(|label| {
-                optimized_data.theorem_data.get(*label).map(|theorem_data| {
+                theorem_data(label).map(|theorem_data| {
                     (
                         &theorem_data.axiom_dependencies,
                         &theorem_data.definition_dependencies,
@@ -2730,7 +3374,7 @@ impl Theorem {
                 .take_while(|token| *token != ")")
                 .collect()
         } else {
-            proof.split_ascii_whitespace().collect()
+            Theorem::calc_uncompressed_proof_step_labels(proof)
         };
 
         for label in labels {
@@ -2788,6 +3432,20 @@ impl Header {
         self.theorem_iter().find(|t| t.label == label)
     }
 
+    pub fn find_theorem_by_label_mut(&mut self, label: &str) -> Option<&mut Theorem> {
+        for statement in &mut self.content {
+            if let Statement::TheoremStatement(theorem) = statement {
+                if theorem.label == label {
+                    return Some(theorem);
+                }
+            }
+        }
+
+        self.subheaders
+            .iter_mut()
+            .find_map(|subheader| subheader.find_theorem_by_label_mut(label))
+    }
+
     pub fn find_theorem_and_index_by_label(&self, label: &str) -> Option<(usize, &Theorem)> {
         self.theorem_iter()
             .enumerate()
@@ -2833,21 +3491,28 @@ impl Header {
         description: &str,
         target: &mut String,
         insert_pos: usize,
+        formatting: &ExportFormattingSettings,
     ) -> Result<(), Error> {
+        let line_width = formatting.line_width as usize;
         let mut header_mm_string = String::new();
 
         let description_not_empty = description.split_ascii_whitespace().next().is_some();
 
         header_mm_string.push_str("$(\n");
-        Header::write_header_banner_string(&mut header_mm_string, depth)?;
+        Header::write_header_banner_string(&mut header_mm_string, depth, line_width)?;
         header_mm_string.push_str("\n ");
-        write_text_wrapped(&mut header_mm_string, title, "  ");
+        write_text_wrapped(&mut header_mm_string, title, "  ", line_width);
         header_mm_string.push('\n');
-        Header::write_header_banner_string(&mut header_mm_string, depth)?;
+        Header::write_header_banner_string(&mut header_mm_string, depth, line_width)?;
         if description_not_empty {
             header_mm_string.push_str("\n\n ");
         }
-        write_text_wrapped_maintain_paragraphs(&mut header_mm_string, description, "  ");
+        write_text_wrapped_maintain_paragraphs(
+            &mut header_mm_string,
+            description,
+            "  ",
+            line_width,
+        );
         if description_not_empty {
             header_mm_string.push('\n');
         }
@@ -2858,42 +3523,21 @@ impl Header {
         Ok(())
     }
 
-    fn write_header_banner_string(target: &mut String, depth: u32) -> Result<(), Error> {
-        match depth {
-            1 => {
-                for _ in 0..79 {
-                    target.push('#');
-                }
-            }
-            2 => {
-                for i in 0..79 {
-                    if i % 2 == 0 {
-                        target.push('#');
-                    } else {
-                        target.push('*');
-                    }
-                }
-            }
-            3 => {
-                for i in 0..79 {
-                    if i % 2 == 0 {
-                        target.push('=');
-                    } else {
-                        target.push('-');
-                    }
-                }
-            }
-            4 => {
-                for i in 0..79 {
-                    if i % 2 == 0 {
-                        target.push('-');
-                    } else {
-                        target.push('.');
-                    }
-                }
-            }
+    // Banners fill the line up to, but not including, the last column
+    pub fn write_header_banner_string(
+        target: &mut String,
+        depth: u32,
+        line_width: usize,
+    ) -> Result<(), Error> {
+        let pattern = match depth {
+            1 => "##",
+            2 => "#*",
+            3 => "=-",
+            4 => "-.",
             _ => return Err(Error::InternalLogicError),
-        }
+        };
+
+        target.extend(pattern.chars().cycle().take(line_width.saturating_sub(1)));
 
         Ok(())
     }
@@ -2925,6 +3569,30 @@ impl Header {
         ConstantIterator::new(self)
     }
 
+    pub fn calc_constant_usage_counts(&self) -> HashMap<String, u32> {
+        let mut constant_usage_counts: HashMap<String, u32> = self
+            .constant_iter()
+            .map(|constant| (constant.symbol.clone(), 0))
+            .collect();
+
+        for theorem in self.theorem_iter() {
+            for expression in theorem
+                .hypotheses
+                .iter()
+                .map(|hypothesis| &hypothesis.expression)
+                .chain(std::iter::once(&theorem.assertion))
+            {
+                for token in expression.split_ascii_whitespace() {
+                    if let Some(usage_count) = constant_usage_counts.get_mut(token) {
+                        *usage_count += 1;
+                    }
+                }
+            }
+        }
+
+        constant_usage_counts
+    }
+
     pub fn variable_iter<'a>(&'a self) -> VariableIterator<'a> {
         VariableIterator::new(self)
     }
@@ -3363,13 +4031,18 @@ impl serde::Serialize for ListEntry {
                 state.end()
             }
             Self::Theorem(ref theorem_list_entry) => {
-                let mut state = serializer.serialize_struct("TheoremListEntry", 5)?;
+                let mut state = serializer.serialize_struct("TheoremListEntry", 7)?;
                 state.serialize_field("label", &theorem_list_entry.label)?;
                 state.serialize_field("theoremNumber", &theorem_list_entry.theorem_number)?;
                 state.serialize_field("hypotheses", &theorem_list_entry.hypotheses)?;
                 state.serialize_field("assertion", &theorem_list_entry.assertion)?;
                 state
                     .serialize_field("descriptionParsed", &theorem_list_entry.description_parsed)?;
+                state.serialize_field("isDiscouraged", &theorem_list_entry.is_discouraged)?;
+                state.serialize_field(
+                    "isProofModificationDiscouraged",
+                    &theorem_list_entry.is_proof_modification_discouraged,
+                )?;
                 state.serialize_field("discriminator", "TheoremListEntry")?;
                 state.end()
             }
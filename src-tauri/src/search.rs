@@ -5,8 +5,8 @@ use tauri::async_runtime::Mutex;
 
 use crate::{
     model::{
-        Header, ListEntry, MetamathData, ParseTree, ParseTreeNode, Theorem, TheoremListData,
-        TheoremParseTrees,
+        Header, HtmlRepresentation, ListEntry, MetamathData, ParseTree, ParseTreeNode, Theorem,
+        TheoremListData, TheoremParseTrees,
     },
     util::earley_parser_optimized::WorkVariable,
     AppState, Error,
@@ -415,7 +415,7 @@ fn parse_tree_contains(
     false
 }
 
-fn ordered_list_contained_in_other_ordered_list(
+pub fn ordered_list_contained_in_other_ordered_list(
     list: &Vec<usize>,
     other_list: &Vec<usize>,
 ) -> bool {
@@ -476,6 +476,71 @@ pub async fn search_by_parse_tree_syntax_check(
     Ok(metamath_data.expression_to_parse_tree(search).is_ok())
 }
 
+// Returns the constants that can follow the expression up to the cursor position, ordered by how often they are used
+// in the assertions and hypotheses of the database. If the cursor is at the end of a token, only constants starting
+// with that token are returned. The expression starts with its typecode
+#[tauri::command]
+pub async fn expression_autocomplete(
+    state: tauri::State<'_, Mutex<AppState>>,
+    expression: &str,
+    cursor_position: u32,
+) -> Result<Vec<HtmlRepresentation>, Error> {
+    let app_state = state.lock().await;
+    let metamath_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let before_cursor: String = expression.chars().take(cursor_position as usize).collect();
+
+    let (expression_start, partial_token) =
+        match before_cursor.rfind(|c: char| c.is_ascii_whitespace()) {
+            Some(i) => before_cursor.split_at(i + 1),
+            None => return Ok(Vec::new()),
+        };
+
+    let Ok(next_terminals) = metamath_data.calc_expression_next_terminals(expression_start) else {
+        return Ok(Vec::new());
+    };
+
+    let symbol_number_mapping = &metamath_data.optimized_data.symbol_number_mapping;
+
+    let usage_counts: HashMap<&str, u32> = next_terminals
+        .into_iter()
+        .filter(|&symbol_i| {
+            symbol_i > symbol_number_mapping.typecode_count + symbol_number_mapping.variable_count
+        })
+        .filter_map(|symbol_i| symbol_number_mapping.symbols.get(&symbol_i))
+        .filter(|symbol| symbol.starts_with(partial_token))
+        .map(|symbol| {
+            (
+                &**symbol,
+                metamath_data
+                    .optimized_data
+                    .constant_usage_counts
+                    .get(symbol)
+                    .copied()
+                    .unwrap_or(0),
+            )
+        })
+        .collect();
+
+    let mut constants: Vec<(&str, u32)> = usage_counts.into_iter().collect();
+    constants.sort_by(|(symbol_1, count_1), (symbol_2, count_2)| {
+        count_2.cmp(count_1).then(symbol_1.cmp(symbol_2))
+    });
+
+    Ok(constants
+        .into_iter()
+        .map(|(symbol, _)| HtmlRepresentation {
+            symbol: symbol.to_string(),
+            html: metamath_data
+                .html_representations
+                .iter()
+                .find(|html_rep| html_rep.symbol == symbol)
+                .map(|html_rep| html_rep.html.clone())
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
 // If successful, returns a tuple (a,b) where:
 // a is whether the query is a valid axiom label
 // b is a list of 5 axiom labels to be shown as autocomplete
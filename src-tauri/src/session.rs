@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::{editor::add_to_database, AppState, Error};
+
+const SESSION_STORE_PATH: &str = "session.json";
+
+const SESSION_VERSION: u32 = 1;
+
+const MAX_RECENT_DATABASES: usize = 10;
+
+// What was open when the app was last closed
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Session {
+    #[serde(rename = "databasePath")]
+    database_path: Option<String>,
+    #[serde(rename = "databaseHash")]
+    database_hash: Option<String>,
+    #[serde(rename = "folderPath")]
+    folder_path: Option<String>,
+    #[serde(rename = "openFiles")]
+    open_files: Vec<String>,
+}
+
+// The parts of the last session that can still be restored
+#[derive(Serialize)]
+pub struct PreviousSession {
+    #[serde(rename = "databasePath")]
+    database_path: Option<String>,
+    // If the database file has not changed since the session, it does not have to be confirmed again after opening it
+    #[serde(rename = "databaseUnchanged")]
+    database_unchanged: bool,
+    #[serde(rename = "folderPath")]
+    folder_path: Option<String>,
+    #[serde(rename = "openFiles")]
+    open_files: Vec<String>,
+}
+
+#[tauri::command]
+pub async fn get_recent_databases(app: AppHandle) -> Result<Vec<String>, Error> {
+    Ok(load_recent_databases(&app)
+        .into_iter()
+        .filter(|database_path| Path::new(database_path).is_file())
+        .collect())
+}
+
+#[tauri::command]
+pub async fn get_previous_session(app: AppHandle) -> Result<Option<PreviousSession>, Error> {
+    let session = load_session(&app);
+
+    let database_path = session
+        .database_path
+        .filter(|database_path| Path::new(database_path).is_file());
+
+    let database_unchanged = match (&database_path, &session.database_hash) {
+        (Some(database_path), Some(database_hash)) => {
+            !add_to_database::database_has_changed(database_path, database_hash).unwrap_or(true)
+        }
+        _ => false,
+    };
+
+    let folder_path = session
+        .folder_path
+        .filter(|folder_path| Path::new(folder_path).is_dir());
+
+    let open_files = if let Some(ref folder_path) = folder_path {
+        session
+            .open_files
+            .into_iter()
+            .filter(|relative_path| Path::new(folder_path).join(relative_path).is_file())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if database_path.is_none() && folder_path.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(PreviousSession {
+        database_path,
+        database_unchanged,
+        folder_path,
+        open_files,
+    }))
+}
+
+// The session is only a convenience, so failing to store it should not keep the user from working
+pub fn add_recent_database(app: &AppHandle, database_path: &str) {
+    let Ok(store) = app.store(SESSION_STORE_PATH) else {
+        return;
+    };
+
+    let mut recent_databases = load_recent_databases(app);
+    recent_databases.retain(|recent_database| recent_database != database_path);
+    recent_databases.insert(0, database_path.to_string());
+    recent_databases.truncate(MAX_RECENT_DATABASES);
+
+    store.set("version", SESSION_VERSION);
+    store.set("recentDatabases", Value::from(recent_databases));
+    store.save().ok();
+}
+
+pub fn save_session(app: &AppHandle, app_state: &AppState) {
+    let Ok(store) = app.store(SESSION_STORE_PATH) else {
+        return;
+    };
+
+    let mut open_files: Vec<String> = app_state
+        .open_folder_data
+        .as_ref()
+        .map(|folder_data| folder_data.file_handles.keys().cloned().collect())
+        .unwrap_or_default();
+    open_files.sort();
+
+    let session = Session {
+        database_path: app_state
+            .metamath_data
+            .as_ref()
+            .map(|mm_data| mm_data.database_path.clone()),
+        database_hash: app_state
+            .metamath_data
+            .as_ref()
+            .map(|mm_data| mm_data.database_hash.clone()),
+        folder_path: app_state
+            .open_folder_data
+            .as_ref()
+            .and_then(|folder_data| folder_data.path.to_str())
+            .map(|folder_path| folder_path.to_string()),
+        open_files,
+    };
+
+    let Ok(session_value) = serde_json::to_value(&session) else {
+        return;
+    };
+
+    store.set("version", SESSION_VERSION);
+    store.set("session", session_value);
+    store.save().ok();
+}
+
+fn load_recent_databases(app: &AppHandle) -> Vec<String> {
+    app.store(SESSION_STORE_PATH)
+        .ok()
+        .and_then(|store| store.get("recentDatabases"))
+        .and_then(|recent_databases| serde_json::from_value(recent_databases).ok())
+        .unwrap_or_default()
+}
+
+// Falls back to an empty session if the store can't be read
+fn load_session(app: &AppHandle) -> Session {
+    app.store(SESSION_STORE_PATH)
+        .ok()
+        .and_then(|store| store.get("session"))
+        .and_then(|session| serde_json::from_value(session).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,217 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{async_runtime::Mutex, AppHandle};
+use tauri_plugin_store::StoreExt;
+
+use crate::{AppState, Error, ExportFormattingSettings, ProofFormatOption, Settings};
+
+const SETTINGS_STORE_PATH: &str = "settings.json";
+
+// Version 0 are the settings that were written by the frontend before the backend stored them. They have no version
+// number and only contain the settings the frontend knew about back then
+const SETTINGS_VERSION: u32 = 1;
+
+// Settings of a database that are stored beside its mm file, so that they are shared by everyone working on it
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct SettingsOverrides {
+    #[serde(
+        rename = "definitionsStartWith",
+        skip_serializing_if = "Option::is_none"
+    )]
+    definitions_start_with: Option<String>,
+    #[serde(rename = "proofFormat", skip_serializing_if = "Option::is_none")]
+    proof_format: Option<ProofFormatOption>,
+    #[serde(rename = "exportFormatting", skip_serializing_if = "Option::is_none")]
+    export_formatting: Option<ExportFormattingSettings>,
+}
+
+// Returns the error that occurred reading the stored settings at startup, so the user can be told that the defaults
+// are used instead and will replace the stored settings once they are saved
+#[tauri::command]
+pub async fn get_settings(state: tauri::State<'_, Mutex<AppState>>) -> Result<Settings, Error> {
+    let app_state = state.lock().await;
+
+    if let Some(error) = app_state.settings_load_error {
+        return Err(error);
+    }
+
+    Ok(app_state.global_settings.clone())
+}
+
+#[tauri::command]
+pub async fn get_default_settings() -> Result<Settings, Error> {
+    Ok(Settings::default())
+}
+
+#[tauri::command]
+pub async fn set_settings(
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+    settings: Settings,
+) -> Result<(), Error> {
+    let mut app_state = state.lock().await;
+
+    save_settings(&app, &settings)?;
+
+    app_state.settings_load_error = None;
+    app_state.settings = settings.with_overrides(&app_state.database_settings_overrides);
+    app_state.global_settings = settings;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_database_settings_overrides(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<SettingsOverrides, Error> {
+    let app_state = state.lock().await;
+
+    if app_state.metamath_data.is_none() {
+        return Err(Error::NoMmDbError);
+    }
+
+    Ok(app_state.database_settings_overrides.clone())
+}
+
+#[tauri::command]
+pub async fn set_database_settings_overrides(
+    state: tauri::State<'_, Mutex<AppState>>,
+    overrides: SettingsOverrides,
+) -> Result<(), Error> {
+    let mut app_state = state.lock().await;
+    let mm_data = app_state.metamath_data.as_ref().ok_or(Error::NoMmDbError)?;
+
+    let settings_file_path = database_settings_file_path(&mm_data.database_path);
+
+    if overrides.is_empty() {
+        if Path::new(&settings_file_path).exists() {
+            fs::remove_file(&settings_file_path).or(Err(Error::FileWriteError))?;
+        }
+    } else {
+        let mut file_value = serde_json::to_value(&overrides).or(Err(Error::InternalLogicError))?;
+        if let Value::Object(ref mut fields) = file_value {
+            fields.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+        }
+
+        let file_content =
+            serde_json::to_string_pretty(&file_value).or(Err(Error::InternalLogicError))?;
+        fs::write(&settings_file_path, file_content + "\n").or(Err(Error::FileWriteError))?;
+    }
+
+    app_state.settings = app_state.global_settings.with_overrides(&overrides);
+    app_state.database_settings_overrides = overrides;
+
+    Ok(())
+}
+
+// Returns the default settings if none have been stored yet
+pub fn load_settings(app: &AppHandle) -> Result<Settings, Error> {
+    let store = app
+        .store(SETTINGS_STORE_PATH)
+        .or(Err(Error::SettingsStoreError))?;
+
+    let Some(settings) = store.get("settings") else {
+        return Ok(Settings::default());
+    };
+
+    let version = store
+        .get("version")
+        .and_then(|version| version.as_u64())
+        .unwrap_or(0) as u32;
+
+    serde_json::from_value(migrate_settings(settings, version)?).or(Err(Error::SettingsStoreError))
+}
+
+fn save_settings(app: &AppHandle, settings: &Settings) -> Result<(), Error> {
+    let store = app
+        .store(SETTINGS_STORE_PATH)
+        .or(Err(Error::SettingsStoreError))?;
+
+    store.set("version", SETTINGS_VERSION);
+    store.set(
+        "settings",
+        serde_json::to_value(settings).or(Err(Error::InternalLogicError))?,
+    );
+
+    store.save().or(Err(Error::SettingsStoreError))
+}
+
+// Brings settings written by an older version up to the current version, one version at a time. Settings of a newer
+// version are kept as they are, their unknown fields are ignored when deserializing
+fn migrate_settings(mut settings: Value, version: u32) -> Result<Value, Error> {
+    if version < 1 {
+        settings = migrate_settings_from_v0(settings)?;
+    }
+
+    Ok(settings)
+}
+
+// The frontend only stored the settings it had at the time, which were all kept with the same names. The settings
+// that were added since are filled in with their defaults
+fn migrate_settings_from_v0(settings: Value) -> Result<Value, Error> {
+    let Value::Object(fields) = settings else {
+        return Err(Error::SettingsStoreError);
+    };
+
+    let Value::Object(mut migrated_fields) =
+        serde_json::to_value(Settings::default()).or(Err(Error::InternalLogicError))?
+    else {
+        return Err(Error::InternalLogicError);
+    };
+
+    for (name, value) in fields {
+        if migrated_fields.contains_key(&name) {
+            migrated_fields.insert(name, value);
+        }
+    }
+
+    Ok(Value::Object(migrated_fields))
+}
+
+// Returns default overrides if the database has no settings file
+pub fn load_database_settings_overrides(database_path: &str) -> Result<SettingsOverrides, Error> {
+    let settings_file_path = database_settings_file_path(database_path);
+
+    if !Path::new(&settings_file_path).exists() {
+        return Ok(SettingsOverrides::default());
+    }
+
+    let file_content = fs::read_to_string(&settings_file_path).or(Err(Error::FileReadError))?;
+    let file_value: Value =
+        serde_json::from_str(&file_content).or(Err(Error::InvalidDatabaseSettingsError))?;
+
+    // Database settings files have been written with a version number from the start, so there is nothing to migrate
+    serde_json::from_value(file_value).or(Err(Error::InvalidDatabaseSettingsError))
+}
+
+fn database_settings_file_path(database_path: &str) -> String {
+    format!("{}.mmt1.json", database_path)
+}
+
+impl Settings {
+    pub fn with_overrides(&self, overrides: &SettingsOverrides) -> Settings {
+        let mut settings = self.clone();
+
+        if let Some(ref definitions_start_with) = overrides.definitions_start_with {
+            settings.definitons_start_with = definitions_start_with.clone();
+        }
+        if let Some(proof_format) = overrides.proof_format {
+            settings.proof_format = proof_format;
+        }
+        if let Some(ref export_formatting) = overrides.export_formatting {
+            settings.export_formatting = export_formatting.clone();
+        }
+
+        settings
+    }
+}
+
+impl SettingsOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.definitions_start_with.is_none()
+            && self.proof_format.is_none()
+            && self.export_formatting.is_none()
+    }
+}
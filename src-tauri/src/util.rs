@@ -1,4 +1,7 @@
-use std::{collections::HashSet, ops::Deref};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+};
 
 use sha2::{Digest, Sha256};
 
@@ -9,12 +12,16 @@ use crate::{
     Error,
 };
 
+#[cfg(feature = "bench")]
+pub mod bulk_parsing_benchmark;
 pub mod description_parser;
 pub mod earley_parser;
 pub mod earley_parser_optimized;
 pub mod header_iterators;
 pub mod last_curr_next_iterator;
+pub mod packrat_parser;
 pub mod parse_tree_node_iterator;
+pub mod unicode_input;
 pub mod work_variable_manager;
 
 pub fn spaces(num: u32) -> &'static str {
@@ -224,6 +231,65 @@ where
     distinct_variable_pairs
 }
 
+// Groups distinct variable pairs into as few $d statements as possible, the way set.mm writes them. Every pair in
+// pairs_to_cover ends up in a group, and groups only contain pairs that are either in pairs_to_cover or in
+// allowed_pairs. Variables are ordered by their position in variable_order, unknown variables come last
+pub fn group_distinct_variable_pairs(
+    pairs_to_cover: &HashSet<(String, String)>,
+    allowed_pairs: &HashSet<(String, String)>,
+    variable_order: &HashMap<&str, usize>,
+) -> Vec<Vec<String>> {
+    let is_pair = |pairs: &HashSet<(String, String)>, var_1: &str, var_2: &str| {
+        pairs.contains(&(var_1.to_string(), var_2.to_string()))
+            || pairs.contains(&(var_2.to_string(), var_1.to_string()))
+    };
+    let is_allowed = |var_1: &str, var_2: &str| {
+        is_pair(pairs_to_cover, var_1, var_2) || is_pair(allowed_pairs, var_1, var_2)
+    };
+
+    let mut variables: Vec<&str> = pairs_to_cover
+        .iter()
+        .flat_map(|(var_1, var_2)| [var_1.as_str(), var_2.as_str()])
+        .collect::<HashSet<&str>>()
+        .into_iter()
+        .collect();
+    variables.sort_by_key(|var| (variable_order.get(var).unwrap_or(&usize::MAX), *var));
+
+    let mut uncovered_pairs: Vec<(&str, &str)> = Vec::new();
+    for (i, var_1) in variables.iter().enumerate() {
+        for var_2 in &variables[(i + 1)..] {
+            if is_pair(pairs_to_cover, var_1, var_2) {
+                uncovered_pairs.push((var_1, var_2));
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<String>> = Vec::new();
+
+    while let Some(&(var_1, var_2)) = uncovered_pairs.first() {
+        let mut group: Vec<&str> = vec![var_1, var_2];
+
+        for var in &variables {
+            if !group.contains(var)
+                && group.iter().all(|group_var| is_allowed(group_var, var))
+                && group.iter().any(|group_var| {
+                    uncovered_pairs.contains(&(group_var, var))
+                        || uncovered_pairs.contains(&(var, group_var))
+                })
+            {
+                group.push(var);
+            }
+        }
+
+        uncovered_pairs.retain(|(var_1, var_2)| !(group.contains(var_1) && group.contains(var_2)));
+
+        group.sort_by_key(|var| (variable_order.get(var).unwrap_or(&usize::MAX), *var));
+        groups.push(group.into_iter().map(|var| var.to_string()).collect());
+    }
+
+    groups
+}
+
 pub fn calc_next_header_path(header_path: &mut HeaderPath, depth: u32) -> Result<(), Error> {
     if depth > header_path.path.len() as u32 {
         header_path.path.push(0);
@@ -278,6 +344,12 @@ pub fn locate_after_to_mmp_file_format_of_statement_it_refers_to(
     for (last, curr, next) in mm_data
         .database_header
         .locate_after_iter(Some(locate_after))
+        .filter(|element| {
+            !matches!(
+                element,
+                DatabaseElement::Statement(Statement::ScopeStatement(_))
+            )
+        })
         .last_curr_next()
     {
         if let Some(DatabaseElement::Header(_, depth)) = last {
@@ -301,6 +373,7 @@ pub fn locate_after_to_mmp_file_format_of_statement_it_refers_to(
                         LocateAfterRef::LocateAfterHeader(&locate_after_ref_string)
                     }
                     DatabaseElement::Statement(s) => match s {
+                        Statement::ScopeStatement(_) => return Err(Error::InternalLogicError),
                         Statement::CommentStatement(_) => {
                             locate_after_ref_string = format!(
                                 "{}#{}",
@@ -0,0 +1,117 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    metamath::mm_parser,
+    model::{ParseTree, SymbolNumberMapping},
+    util::{earley_parser_optimized::Grammar, packrat_parser::packrat_parse},
+    Error, Settings,
+};
+
+pub struct BulkParsingBenchmark {
+    pub expressions: usize,
+    // Expressions the packrat parser couldn't handle, so that the bulk path fell back to the Earley parser
+    pub fallbacks: usize,
+    // Expressions where both paths returned different parse trees
+    pub mismatches: usize,
+    pub bulk_duration: Duration,
+    pub earley_duration: Duration,
+}
+
+// Parses every assertion and hypothesis of the database, that is parsed when the grammar is calculated, once with the
+// bulk path (packrat parser with Earley fallback) and once with the Earley parser only
+pub fn benchmark_bulk_parsing(file_path: &str) -> Result<BulkParsingBenchmark, Error> {
+    let mm_data = mm_parser::load_metamath_data(file_path, 0, &Settings::default())?;
+
+    let symbol_number_mapping = SymbolNumberMapping::calc_mapping(&mm_data.database_header);
+
+    let Some((grammar, parse_trees)) = Grammar::calc_grammar_and_parse_trees(
+        &mm_data.database_header,
+        &symbol_number_mapping,
+        &mm_data.optimized_data.floating_hypotheses,
+        &mm_data.syntax_typecodes,
+        &mm_data.logical_typecodes,
+        mm_data.optimized_data.theorem_amount,
+        mm_data.database_id,
+        None,
+        None,
+    )?
+    else {
+        return Err(Error::InternalLogicError);
+    };
+
+    let parsed_labels: HashSet<&str> = parse_trees.iter().map(|(label, _, _)| *label).collect();
+
+    let expressions: Vec<&str> = mm_data
+        .database_header
+        .theorem_iter()
+        .filter(|theorem| parsed_labels.contains(&*theorem.label))
+        .flat_map(|theorem| {
+            theorem
+                .hypotheses
+                .iter()
+                .map(|hyp| &*hyp.expression)
+                .chain(std::iter::once(&*theorem.assertion))
+        })
+        .collect();
+
+    let parse_all = |parse: &dyn Fn(&str) -> Result<ParseTree, Error>| {
+        let start = Instant::now();
+        let parse_trees = expressions
+            .iter()
+            .map(|expression| parse(expression))
+            .collect::<Result<Vec<ParseTree>, Error>>()?;
+        Ok::<_, Error>((parse_trees, start.elapsed()))
+    };
+
+    let (bulk_parse_trees, bulk_duration) = parse_all(&|expression| {
+        symbol_number_mapping.bulk_expression_to_parse_tree(
+            expression,
+            &grammar,
+            &mm_data.optimized_data.floating_hypotheses,
+            &mm_data.syntax_typecodes,
+            &mm_data.logical_typecodes,
+        )
+    })?;
+
+    let (earley_parse_trees, earley_duration) = parse_all(&|expression| {
+        symbol_number_mapping.expression_to_parse_tree(
+            expression,
+            &grammar,
+            &mm_data.optimized_data.floating_hypotheses,
+            &mm_data.syntax_typecodes,
+            &mm_data.logical_typecodes,
+        )
+    })?;
+
+    let mut fallbacks = 0;
+    for expression in &expressions {
+        let input_vec = symbol_number_mapping.expression_to_input_vec_skip_first(
+            expression,
+            &mm_data.optimized_data.floating_hypotheses,
+        )?;
+        let (_, typecode) = symbol_number_mapping.calc_typecode_numbers(
+            expression,
+            &mm_data.syntax_typecodes,
+            &mm_data.logical_typecodes,
+        )?;
+
+        if packrat_parse(&grammar, &input_vec, typecode, &symbol_number_mapping)?.is_none() {
+            fallbacks += 1;
+        }
+    }
+
+    Ok(BulkParsingBenchmark {
+        expressions: expressions.len(),
+        fallbacks,
+        mismatches: bulk_parse_trees
+            .iter()
+            .zip(earley_parse_trees.iter())
+            .filter(|(bulk, earley)| bulk != earley)
+            .count(),
+        bulk_duration,
+        earley_duration,
+    })
+}
@@ -5,7 +5,7 @@ use crate::{
     Error,
 };
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Grammar {
     pub rules: Vec<GrammarRule>,
     pub earley_optimized_data: EarleyOptimizedData,
@@ -16,7 +16,7 @@ pub struct ExtendedGrammar<'a> {
     pub main_rule: GrammarRule,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GrammarRule {
     pub left_side: Symbol,
     pub right_side: Vec<Symbol>,
@@ -43,7 +43,7 @@ pub struct WorkVariable {
     pub number: u32,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct EarleyOptimizedData {
     pub completer_rules: Vec<Vec<Vec<usize>>>,
     pub combined_states_to_add: Vec<Vec<u32>>,
@@ -257,6 +257,199 @@ pub fn earley_parse(
         },
     };
 
+    let mut state_sets = calc_state_sets(expression, &extended_grammar, symbol_number_mapping)?;
+
+    // println!("{:?}", state_sets.get(expression.len()));
+    state_sets.pop();
+
+    let ret = state_sets
+        .pop()
+        .ok_or(Error::InternalLogicError)?
+        .take_processed(&SingleState {
+            rule_i: -1,
+            processed_i: match_against_len as u32,
+            start_i: 0,
+            parse_trees: Vec::new(),
+        });
+
+    // if ret.is_none() {
+    // if expression
+    //     .iter()
+    //     .any(|symbol| matches!(symbol, InputSymbol::WorkVariable(_)))
+    // {
+    //     println!("\n\nEarley parser for: {:?}", expression);
+    //     for k in 0..(expression.len()) {
+    //         println!("{}:", k);
+    //         for state in &state_sets.get(k).unwrap().processed_states {
+    //             if let State::Single(single_state) = state {
+    //                 println!(
+    //                     "{} ::= {:?}",
+    //                     single_state.rule(&extended_grammar).left_side.symbol_i,
+    //                     single_state
+    //                         .rule(&extended_grammar)
+    //                         .right_side
+    //                         .iter()
+    //                         .map(|symbol| symbol.symbol_i)
+    //                         .collect::<Vec<u32>>()
+    //                 );
+    //                 print!("{:?} ", single_state.rule(&extended_grammar).label);
+    //             }
+    //             println!("{:?}", state);
+    //         }
+    //     }
+    // }
+
+    Ok(ret.map(|s| s.parse_trees))
+    //.map(|s| s.parse_trees))
+}
+
+// Reasons why an expression could not be parsed
+pub struct EarleyParseFailure {
+    // Index of the first input symbol that could not be parsed, equal to the length of the expression if the
+    // expression ended too early
+    pub position: u32,
+    // Terminals and typecodes that would have been accepted at that position. Typecodes stand for any expression of
+    // that typecode
+    pub expected_symbols: Vec<u32>,
+}
+
+// Only meant to be called if earley_parse returned None for the same arguments
+pub fn calc_earley_parse_failure(
+    grammar: &Grammar,
+    expression: &Vec<InputSymbol>,
+    match_against: Vec<Symbol>,
+    symbol_number_mapping: &SymbolNumberMapping,
+) -> Result<EarleyParseFailure, Error> {
+    if expression.is_empty() {
+        return Ok(EarleyParseFailure {
+            position: 0,
+            expected_symbols: match_against
+                .first()
+                .map(|s| s.symbol_i)
+                .into_iter()
+                .collect(),
+        });
+    }
+
+    let extended_grammar = ExtendedGrammar {
+        grammar,
+        main_rule: GrammarRule {
+            left_side: Symbol { symbol_i: 0 },
+            right_side: match_against,
+            label: String::new(),
+            var_order: Vec::new(), // never accessed
+            is_floating_hypothesis: false,
+        },
+    };
+
+    let state_sets = calc_state_sets(expression, &extended_grammar, symbol_number_mapping)?;
+
+    // The last state set is never processed, so the furthest position is at most the length of the expression
+    let (position, furthest_state_set) = state_sets
+        .iter()
+        .enumerate()
+        .take(expression.len() + 1)
+        .rev()
+        .find(|(_, state_set)| !state_set.processed_states.is_empty())
+        .ok_or(Error::InternalLogicError)?;
+
+    let mut expected_symbols: Vec<u32> = Vec::new();
+
+    for state in &furthest_state_set.processed_states {
+        let expected_symbol = match state {
+            State::Single(single_state) => {
+                match single_state.next_token(&extended_grammar) {
+                    // Nonterminals are covered by the combined states the predictor added
+                    Some(symbol) if !symbol_number_mapping.is_typecode(symbol.symbol_i) => {
+                        symbol.symbol_i
+                    }
+                    _ => continue,
+                }
+            }
+            State::Combined(combined_state) => combined_state.typecode,
+        };
+
+        if !expected_symbols.contains(&expected_symbol) {
+            expected_symbols.push(expected_symbol);
+        }
+    }
+
+    expected_symbols.sort();
+
+    Ok(EarleyParseFailure {
+        position: position as u32,
+        expected_symbols,
+    })
+}
+
+// Returns all terminals that can follow the given beginning of an expression. Returns an empty vec if the beginning can
+// not be continued to a valid expression
+pub fn calc_next_terminals(
+    grammar: &Grammar,
+    expression: &Vec<InputSymbol>,
+    match_against: Vec<Symbol>,
+    symbol_number_mapping: &SymbolNumberMapping,
+) -> Result<Vec<u32>, Error> {
+    let extended_grammar = ExtendedGrammar {
+        grammar,
+        main_rule: GrammarRule {
+            left_side: Symbol { symbol_i: 0 },
+            right_side: match_against,
+            label: String::new(),
+            var_order: Vec::new(), // never accessed
+            is_floating_hypothesis: false,
+        },
+    };
+
+    let state_sets = calc_state_sets(expression, &extended_grammar, symbol_number_mapping)?;
+
+    let last_state_set = state_sets
+        .get(expression.len())
+        .ok_or(Error::InternalLogicError)?;
+
+    let mut next_terminals: Vec<u32> = Vec::new();
+
+    for state in &last_state_set.processed_states {
+        match state {
+            State::Single(single_state) => {
+                if let Some(symbol) = single_state.next_token(&extended_grammar) {
+                    if !symbol_number_mapping.is_typecode(symbol.symbol_i)
+                        && !next_terminals.contains(&symbol.symbol_i)
+                    {
+                        next_terminals.push(symbol.symbol_i);
+                    }
+                }
+            }
+            // Every terminal that a rule of the typecode starts with
+            State::Combined(combined_state) => {
+                for (i, rules) in grammar
+                    .earley_optimized_data
+                    .single_states_to_add
+                    .get(combined_state.typecode as usize - 1)
+                    .ok_or(Error::InternalLogicError)?
+                    .iter()
+                    .enumerate()
+                {
+                    let symbol_i = i as u32 + symbol_number_mapping.typecode_count + 1;
+
+                    if !rules.is_empty() && !next_terminals.contains(&symbol_i) {
+                        next_terminals.push(symbol_i);
+                    }
+                }
+            }
+        }
+    }
+
+    next_terminals.sort();
+
+    Ok(next_terminals)
+}
+
+fn calc_state_sets(
+    expression: &Vec<InputSymbol>,
+    extended_grammar: &ExtendedGrammar,
+    symbol_number_mapping: &SymbolNumberMapping,
+) -> Result<Vec<StateSet>, Error> {
     let mut state_sets: Vec<StateSet> = vec![StateSet::new()];
     state_sets
         .get_mut(0)
@@ -274,15 +467,15 @@ pub fn earley_parse(
             match state {
                 State::Single(state) => {
                     // if state is not finished
-                    if let Some(num) = state.next_token(&extended_grammar) {
+                    if let Some(num) = state.next_token(extended_grammar) {
                         // if the next element of state is a nonterminal
                         if symbol_number_mapping.is_typecode(num.symbol_i) {
-                            predictor(&state, k, &extended_grammar, &mut state_sets)?;
+                            predictor(&state, k, extended_grammar, &mut state_sets)?;
                         } else {
-                            scanner(&state, k, expression, &extended_grammar, &mut state_sets)?;
+                            scanner(&state, k, expression, extended_grammar, &mut state_sets)?;
                         }
                     } else {
-                        completer(&state, k, &extended_grammar, &mut state_sets)?;
+                        completer(&state, k, extended_grammar, &mut state_sets)?;
                     }
                 }
                 State::Combined(combined_state) => {
@@ -291,7 +484,8 @@ pub fn earley_parse(
                         .get_mut(k as usize)
                         .ok_or(Error::InternalLogicError)?;
 
-                    for &typecode in grammar
+                    for &typecode in extended_grammar
+                        .grammar
                         .earley_optimized_data
                         .combined_states_to_add
                         .get(combined_state.typecode as usize - 1)
@@ -316,7 +510,8 @@ pub fn earley_parse(
                             .ok_or(Error::InternalLogicError)?
                         {
                             InputSymbol::Symbol(symbol) => {
-                                for &rule in grammar
+                                for &rule in extended_grammar
+                                    .grammar
                                     .earley_optimized_data
                                     .single_states_to_add
                                     .get(combined_state.typecode as usize - 1)
@@ -354,48 +549,7 @@ pub fn earley_parse(
         }
     }
 
-    // println!("{:?}", state_sets.get(expression.len()));
-    state_sets.pop();
-
-    let ret = state_sets
-        .pop()
-        .ok_or(Error::InternalLogicError)?
-        .take_processed(&SingleState {
-            rule_i: -1,
-            processed_i: match_against_len as u32,
-            start_i: 0,
-            parse_trees: Vec::new(),
-        });
-
-    // if ret.is_none() {
-    // if expression
-    //     .iter()
-    //     .any(|symbol| matches!(symbol, InputSymbol::WorkVariable(_)))
-    // {
-    //     println!("\n\nEarley parser for: {:?}", expression);
-    //     for k in 0..(expression.len()) {
-    //         println!("{}:", k);
-    //         for state in &state_sets.get(k).unwrap().processed_states {
-    //             if let State::Single(single_state) = state {
-    //                 println!(
-    //                     "{} ::= {:?}",
-    //                     single_state.rule(&extended_grammar).left_side.symbol_i,
-    //                     single_state
-    //                         .rule(&extended_grammar)
-    //                         .right_side
-    //                         .iter()
-    //                         .map(|symbol| symbol.symbol_i)
-    //                         .collect::<Vec<u32>>()
-    //                 );
-    //                 print!("{:?} ", single_state.rule(&extended_grammar).label);
-    //             }
-    //             println!("{:?}", state);
-    //         }
-    //     }
-    // }
-
-    Ok(ret.map(|s| s.parse_trees))
-    //.map(|s| s.parse_trees))
+    Ok(state_sets)
 }
 
 fn predictor(
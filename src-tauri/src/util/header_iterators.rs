@@ -273,6 +273,7 @@ impl<'a, 'b> Iterator for HeaderLocateAfterIterator<'a, 'b> {
                 }
             }
             DatabaseElement::Statement(statement) => match statement {
+                Statement::ScopeStatement(_) => {}
                 Statement::CommentStatement(_) => {
                     if let Some(LocateAfterRef::LocateAfterComment(comment_path_str)) =
                         self.locate_after
@@ -0,0 +1,208 @@
+use std::rc::Rc;
+
+use crate::{
+    model::{ParseTreeNode, SymbolNumberMapping},
+    util::earley_parser_optimized::{Grammar, InputSymbol},
+    Error,
+};
+
+// Only up to this many ways to parse a typecode at one position are kept. Unambiguous grammars stay far below this
+// number
+const MAX_PARSES_PER_POSITION: usize = 64;
+
+// End positions and root node indices of all ways to parse a typecode starting at a fixed position
+type Parses = Rc<[(u32, usize)]>;
+
+struct PackratParser<'a> {
+    grammar: &'a Grammar,
+    symbol_number_mapping: &'a SymbolNumberMapping,
+    expression: Vec<u32>,
+    // Nodes of all parse trees found so far, as rule and indices of the sub nodes. Parse trees share their nodes, so
+    // that they don't have to be cloned while parsing
+    nodes: Vec<(u32, Vec<usize>)>,
+    // Indexed by position and typecode
+    memo: Vec<MemoEntry>,
+}
+
+#[derive(Clone)]
+enum MemoEntry {
+    NotParsed,
+    // The typecode is currently being parsed at this position. Reaching it again means the grammar is left recursive
+    InProgress,
+    Parsed(Parses),
+}
+
+// Memoized top-down parser that is a lot faster than the Earley parser on the mostly unambiguous grammars of metamath
+// databases. The rules are looked up by their first symbol using the data of the Earley parser.
+// Returns None if the expression can't be parsed or the parser can't handle it, because it contains work variables,
+// is ambiguous or the grammar is left recursive. In that case the Earley parser has to be used instead
+pub fn packrat_parse(
+    grammar: &Grammar,
+    expression: &[InputSymbol],
+    typecode: u32,
+    symbol_number_mapping: &SymbolNumberMapping,
+) -> Result<Option<ParseTreeNode>, Error> {
+    let Some(expression) = expression
+        .iter()
+        .map(|input_symbol| match input_symbol {
+            InputSymbol::Symbol(symbol) => Some(symbol.symbol_i),
+            InputSymbol::WorkVariable(_) => None,
+        })
+        .collect::<Option<Vec<u32>>>()
+    else {
+        return Ok(None);
+    };
+
+    let expression_len = expression.len() as u32;
+
+    let mut parser = PackratParser {
+        grammar,
+        symbol_number_mapping,
+        memo: vec![
+            MemoEntry::NotParsed;
+            (expression.len() + 1) * symbol_number_mapping.typecode_count as usize
+        ],
+        expression,
+        nodes: Vec::new(),
+    };
+
+    let Some(parses) = parser.parse_typecode(typecode, 0)? else {
+        return Ok(None);
+    };
+
+    let mut full_parses = parses.iter().filter(|(end, _)| *end == expression_len);
+
+    match (full_parses.next(), full_parses.next()) {
+        (Some(&(_, node_i)), None) => Ok(Some(parser.to_parse_tree_node(node_i)?)),
+        _ => Ok(None),
+    }
+}
+
+impl PackratParser<'_> {
+    fn parse_typecode(&mut self, typecode: u32, pos: u32) -> Result<Option<Parses>, Error> {
+        let memo_i = pos as usize * self.symbol_number_mapping.typecode_count as usize
+            + typecode as usize
+            - 1;
+
+        match self.memo.get(memo_i).ok_or(Error::InternalLogicError)? {
+            MemoEntry::Parsed(parses) => return Ok(Some(parses.clone())),
+            MemoEntry::InProgress => return Ok(None),
+            MemoEntry::NotParsed => {}
+        }
+
+        self.memo[memo_i] = MemoEntry::InProgress;
+
+        let mut parses = Vec::new();
+
+        // Rules starting with a terminal
+        if let Some(&symbol_i) = self.expression.get(pos as usize) {
+            let grammar = self.grammar;
+            if let Some(rules) = grammar
+                .earley_optimized_data
+                .single_states_to_add
+                .get(typecode as usize - 1)
+                .ok_or(Error::InternalLogicError)?
+                .get((symbol_i - self.symbol_number_mapping.typecode_count - 1) as usize)
+            {
+                for &rule_i in rules {
+                    if !self.match_rule(rule_i, 1, pos + 1, Vec::new(), &mut parses)? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        // Rules starting with a typecode
+        for first_typecode in 1..=self.symbol_number_mapping.typecode_count {
+            let grammar = self.grammar;
+            let rules = grammar
+                .earley_optimized_data
+                .completer_rules
+                .get(typecode as usize - 1)
+                .ok_or(Error::InternalLogicError)?
+                .get(first_typecode as usize - 1)
+                .ok_or(Error::InternalLogicError)?;
+
+            if rules.is_empty() {
+                continue;
+            }
+
+            let Some(first_parses) = self.parse_typecode(first_typecode, pos)? else {
+                return Ok(None);
+            };
+
+            for &(end, node_i) in first_parses.iter() {
+                for &rule_i in rules {
+                    if !self.match_rule(rule_i, 1, end, vec![node_i], &mut parses)? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        let parses: Parses = parses.into();
+        self.memo[memo_i] = MemoEntry::Parsed(parses.clone());
+
+        Ok(Some(parses))
+    }
+
+    // Matches the right side of a rule from the given symbol on and pushes every complete match to parses. Returns
+    // false if the parser has to give up
+    fn match_rule(
+        &mut self,
+        rule_i: usize,
+        symbol_i: usize,
+        pos: u32,
+        sub_nodes: Vec<usize>,
+        parses: &mut Vec<(u32, usize)>,
+    ) -> Result<bool, Error> {
+        let rule = self
+            .grammar
+            .rules
+            .get(rule_i)
+            .ok_or(Error::InternalLogicError)?;
+
+        let Some(symbol) = rule.right_side.get(symbol_i) else {
+            if parses.len() == MAX_PARSES_PER_POSITION {
+                return Ok(false);
+            }
+
+            self.nodes.push((rule_i as u32, sub_nodes));
+            parses.push((pos, self.nodes.len() - 1));
+            return Ok(true);
+        };
+
+        if self.symbol_number_mapping.is_typecode(symbol.symbol_i) {
+            let Some(symbol_parses) = self.parse_typecode(symbol.symbol_i, pos)? else {
+                return Ok(false);
+            };
+
+            for &(end, node_i) in symbol_parses.iter() {
+                let mut new_sub_nodes = sub_nodes.clone();
+                new_sub_nodes.push(node_i);
+
+                if !self.match_rule(rule_i, symbol_i + 1, end, new_sub_nodes, parses)? {
+                    return Ok(false);
+                }
+            }
+
+            Ok(true)
+        } else if self.expression.get(pos as usize) == Some(&symbol.symbol_i) {
+            self.match_rule(rule_i, symbol_i + 1, pos + 1, sub_nodes, parses)
+        } else {
+            Ok(true)
+        }
+    }
+
+    fn to_parse_tree_node(&self, node_i: usize) -> Result<ParseTreeNode, Error> {
+        let (rule_i, sub_nodes) = self.nodes.get(node_i).ok_or(Error::InternalLogicError)?;
+
+        Ok(ParseTreeNode::Node {
+            rule_i: *rule_i,
+            sub_nodes: sub_nodes
+                .iter()
+                .map(|&sub_node_i| self.to_parse_tree_node(sub_node_i))
+                .collect::<Result<Vec<ParseTreeNode>, Error>>()?,
+        })
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+
+use kuchiki::{parse_fragment, traits::TendrilSink};
+use markup5ever::{LocalName, Namespace, Prefix, QualName};
+
+use crate::{
+    model::{HtmlRepresentation, MetamathData, SymbolNumberMapping},
+    Error,
+};
+
+// Limits the number of tokenizations that are tried for a single expression, so that badly ambiguous input can't
+// freeze the editor
+const MAX_SEARCH_STEPS: u32 = 1_000;
+
+// Maps the text that symbols are rendered as to the numbers of all symbols that are rendered that way. Symbols also
+// map to themselves, so that Unicode and plain tokens can be mixed. Calculated together with the grammar
+#[derive(Debug, Default, Clone)]
+pub struct UnicodeInputMapping {
+    symbols_by_text: HashMap<String, Vec<u32>>,
+    max_text_len: usize,
+}
+
+impl UnicodeInputMapping {
+    pub fn new(
+        symbol_number_mapping: &SymbolNumberMapping,
+        html_representations: &[HtmlRepresentation],
+    ) -> UnicodeInputMapping {
+        let mut symbols_by_text: HashMap<String, Vec<u32>> = HashMap::new();
+
+        for (symbol, &symbol_i) in &symbol_number_mapping.numbers {
+            // Typecodes are prefixed with a '$' to distinguish them from the constants of the same name
+            if !symbol.starts_with('$') {
+                symbols_by_text
+                    .entry(symbol.clone())
+                    .or_default()
+                    .push(symbol_i);
+            }
+        }
+
+        for html_representation in html_representations {
+            let Some(&symbol_i) = symbol_number_mapping
+                .numbers
+                .get(&html_representation.symbol)
+            else {
+                continue;
+            };
+
+            let text = rendered_text(html_representation);
+
+            if text.is_empty() {
+                continue;
+            }
+
+            let symbols = symbols_by_text.entry(text).or_default();
+            if !symbols.contains(&symbol_i) {
+                symbols.push(symbol_i);
+            }
+        }
+
+        let max_text_len = symbols_by_text.keys().map(|t| t.len()).max().unwrap_or(0);
+
+        UnicodeInputMapping {
+            symbols_by_text,
+            max_text_len,
+        }
+    }
+}
+
+// Returns the text the browser shows for the html of a symbol, without surrounding whitespace
+fn rendered_text(html_representation: &HtmlRepresentation) -> String {
+    let fragment = parse_fragment(
+        QualName::new(
+            Some(Prefix::from("html")),
+            Namespace::from("http://www.w3.org/1999/xhtml"),
+            LocalName::from(""),
+        ),
+        Vec::new(),
+    )
+    .from_utf8()
+    .one(html_representation.html.as_bytes());
+
+    fragment.text_contents().trim().to_string()
+}
+
+// Converts an expression written with the rendered symbols of the database into space separated metamath tokens.
+// Symbols don't have to be separated by spaces, as long as there is only one way to split the input into symbols that
+// can be parsed with the grammar of the database. Work variables are written as a rendered variable followed by '$'
+// and their number.
+// Returns None if there is no such way, or more than one
+pub fn unicode_expression_to_tokens(
+    expression: &str,
+    mm_data: &MetamathData,
+) -> Result<Option<String>, Error> {
+    let symbol_number_mapping = &mm_data.optimized_data.symbol_number_mapping;
+
+    let typecodes: HashSet<u32> = mm_data
+        .logical_typecodes
+        .iter()
+        .map(|lt| &*lt.typecode)
+        .chain(mm_data.syntax_typecodes.iter().map(|st| &*st.typecode))
+        .filter_map(|typecode| symbol_number_mapping.numbers.get(typecode).copied())
+        .collect();
+
+    let mut tokenizer = UnicodeTokenizer {
+        mm_data,
+        mapping: &mm_data.optimized_data.unicode_input_mapping,
+        chunks: expression.split_whitespace().collect(),
+        typecodes,
+        tokens: Vec::new(),
+        results: Vec::new(),
+        steps: 0,
+        next_terminals_cache: HashMap::new(),
+    };
+
+    tokenizer.search(0, 0)?;
+
+    Ok(
+        if tokenizer.results.len() == 1 && tokenizer.steps <= MAX_SEARCH_STEPS {
+            tokenizer.results.pop()
+        } else {
+            None
+        },
+    )
+}
+
+struct UnicodeTokenizer<'a> {
+    mm_data: &'a MetamathData,
+    mapping: &'a UnicodeInputMapping,
+    // The whitespace separated parts of the input
+    chunks: Vec<&'a str>,
+    typecodes: HashSet<u32>,
+    tokens: Vec<String>,
+    results: Vec<String>,
+    steps: u32,
+    // The symbols the grammar allows after a space separated token prefix, or None if the prefix can't be continued
+    next_terminals_cache: HashMap<String, Option<HashSet<u32>>>,
+}
+
+impl UnicodeTokenizer<'_> {
+    // Depth first search over all ways to split the rest of the input into symbols. Only symbols that the grammar
+    // allows after the tokens found so far are tried, longest first. Stops as soon as a second result is found
+    fn search(&mut self, chunk_i: usize, offset: usize) -> Result<(), Error> {
+        self.steps += 1;
+        if self.steps > MAX_SEARCH_STEPS || self.results.len() > 1 {
+            return Ok(());
+        }
+
+        let Some(&chunk) = self.chunks.get(chunk_i) else {
+            let expression = self.tokens.join(" ");
+            if self.mm_data.expression_to_parse_tree(&expression).is_ok() {
+                self.results.push(expression);
+            }
+            return Ok(());
+        };
+
+        if offset == chunk.len() {
+            return self.search(chunk_i + 1, 0);
+        }
+
+        let allowed_symbols: HashSet<u32> = if self.tokens.is_empty() {
+            self.typecodes.clone()
+        } else {
+            let prefix = self.tokens.join(" ");
+            let next_terminals = match self.next_terminals_cache.get(&prefix) {
+                Some(next_terminals) => next_terminals.clone(),
+                None => {
+                    let next_terminals = self
+                        .mm_data
+                        .calc_expression_next_terminals(&prefix)
+                        .ok()
+                        .map(|next_terminals| next_terminals.into_iter().collect());
+                    self.next_terminals_cache
+                        .insert(prefix, next_terminals.clone());
+                    next_terminals
+                }
+            };
+
+            match next_terminals {
+                Some(next_terminals) => next_terminals,
+                None => return Ok(()),
+            }
+        };
+
+        let rest = &chunk[offset..];
+        let symbol_number_mapping = &self.mm_data.optimized_data.symbol_number_mapping;
+
+        for len in (1..=rest.len().min(self.mapping.max_text_len)).rev() {
+            if !rest.is_char_boundary(len) {
+                continue;
+            }
+
+            let Some(symbols) = self.mapping.symbols_by_text.get(&rest[..len]) else {
+                continue;
+            };
+
+            for &symbol_i in symbols {
+                if !allowed_symbols.contains(&symbol_i) {
+                    continue;
+                }
+
+                let symbol = symbol_number_mapping
+                    .symbols
+                    .get(&symbol_i)
+                    .ok_or(Error::InternalLogicError)?;
+
+                let work_variable_number_len = if !self.tokens.is_empty()
+                    && symbol_number_mapping.is_variable(symbol_i)
+                    && rest[len..].starts_with('$')
+                {
+                    rest[len + 1..]
+                        .bytes()
+                        .take_while(|b| b.is_ascii_digit())
+                        .count()
+                } else {
+                    0
+                };
+
+                if work_variable_number_len != 0 {
+                    let number_end = len + 1 + work_variable_number_len;
+                    self.tokens
+                        .push(format!("{}{}", symbol, &rest[len..number_end]));
+                    self.search(chunk_i, offset + number_end)?;
+                } else {
+                    self.tokens.push(symbol.clone());
+                    self.search(chunk_i, offset + len)?;
+                }
+                self.tokens.pop();
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use crate::{
     Error,
 };
 
+#[derive(Clone)]
 pub struct WorkVariableManager {
     next_vars: Vec<WorkVariable>,
 }
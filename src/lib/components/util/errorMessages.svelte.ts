@@ -1,9 +1,10 @@
 import monaco from "$lib/monaco/monaco";
+import type { UnificationExplanation } from "$lib/sharedState/model.svelte";
 
 export function getMmpFileErrorMessage(errorType: string): string {
   switch (errorType) {
     case "NonAsciiSymbolError": {
-      return ".mmp files may only contain ASCII tokens.";
+      return ".mmp files may only contain ASCII tokens. With a $unicodeinput statement, proof lines may also contain the Unicode characters symbols are rendered as.";
     }
     case "WhitespaceBeforeFirstTokenError": {
       return "Statements can't have trailing whitespace.\n\n(This error only shows before the first statement, because other lines with trailing whitespace continue the previous statement.)";
@@ -118,6 +119,15 @@ export function getMmpFileErrorMessage(errorType: string): string {
     case "AllowDiscouragedOutOfPlaceError": {
       return "$allowdiscouraged statement should not be here. $allowdiscouraged may only appear alongside a $theorem statement.";
     }
+    case "AllowProofModificationOutOfPlaceError": {
+      return "$allowproofmodification statement should not be here. $allowproofmodification may only appear alongside a $theorem statement.";
+    }
+    case "DeductionModeOutOfPlaceError": {
+      return "$deductionmode statement should not be here. $deductionmode may only appear alongside a $theorem statement.";
+    }
+    case "UnicodeInputOutOfPlaceError": {
+      return "$unicodeinput statement should not be here. $unicodeinput may only appear alongside proof lines.";
+    }
     case "LocateAfterOutOfPlaceError": {
       return "Locate after statement should not be here. Locate after statements may not appear alongside $header statements, as their location in the database is determined by their header path.";
     }
@@ -188,9 +198,30 @@ export function getMmpFileErrorMessage(errorType: string): string {
     case "TokensAfterAllowIncompleteError": {
       return "$allowincomplete statements should not be followed by any tokens.";
     }
+    case "MultipleAllowProofModificationError": {
+      return "There should be at most one $allowproofmodification statement per mmp file.";
+    }
+    case "TokensAfterAllowProofModificationError": {
+      return "$allowproofmodification statements should not be followed by any tokens.";
+    }
+    case "MultipleDeductionModeError": {
+      return "There should be at most one $deductionmode statement per mmp file.";
+    }
+    case "TokensAfterDeductionModeError": {
+      return "$deductionmode statements should not be followed by any tokens.";
+    }
+    case "MultipleUnicodeInputError": {
+      return "There should be at most one $unicodeinput statement per mmp file.";
+    }
+    case "TokensAfterUnicodeInputError": {
+      return "$unicodeinput statements should not be followed by any tokens.";
+    }
     case "DiscouragedTheoremUsedError": {
       return "The theorem referenced is discouraged. Use $allowdiscouraged to allow discouraged theorems in this proof.";
     }
+    case "ProofModificationDiscouragedError": {
+      return "Modifying the proof of this theorem is discouraged. Use $allowproofmodification to allow changing its proof.";
+    }
     case "IncompleteTheoremUsedError": {
       return "The theorem referenced is incomplete or has an incomplete theorem in it's dependency tree. Use $allowdincomplete to allow incomplete theorems in this proof.";
     }
@@ -227,6 +258,39 @@ export function getMmpFileErrorMessage(errorType: string): string {
     case "NotAValidLabelError": {
       return "Not a floating hypothesis or theorem label.";
     }
+    case "UnificationError": {
+      return "The step can't be proven by its step ref.";
+    }
+    case "UnusedHypothesisWarning": {
+      return "This hypothesis is never used in the proof.";
+    }
+    case "UnnecessaryDistinctVarWarning": {
+      return "The proof doesn't need these variables to be distinct.";
+    }
+    case "MissingDistinctVarWarning": {
+      return "The proof needs variables to be distinct that are not declared in a $d statement.";
+    }
+    case "LabelNamingWarning": {
+      return "Theorem labels should not start like axiom or definition labels, and axiom labels should start with \"ax-\".";
+    }
+    case "MissingDiscouragedTagWarning": {
+      return "\"OLD\" and \"ALT\" theorems should be marked with \"(New usage is discouraged.)\" and \"(Proof modification is discouraged.)\".";
+    }
+    case "MalformedDiscouragedTagWarning": {
+      return "This discouraged tag is misspelled. It should read \"(New usage is discouraged.)\" or \"(Proof modification is discouraged.)\".";
+    }
+    case "MissingDescriptionWarning": {
+      return "The statement has no description.";
+    }
+    case "MissingContributedByWarning": {
+      return "The description has no \"(Contributed by NAME, D-Mon-YYYY.)\" line.";
+    }
+    case "MalformedContributorLineWarning": {
+      return "Contributor lines should have the form \"(Contributed by NAME, D-Mon-YYYY.)\".";
+    }
+    case "LineTooLongWarning": {
+      return "This line is longer than the maximum line length.";
+    }
   }
 
   return "You should not be seeing this error message. Please post a Github issue with your editor content.";
@@ -237,6 +301,7 @@ export function getErrorSeverity(errorType: string): monaco.MarkerSeverity {
     case "MissingMmpStepExpressionError":
     case "NonSymbolInExpressionError":
     case "ExpressionParseError":
+    case "UnificationError":
       return monaco.MarkerSeverity.Warning;
     default:
       return monaco.MarkerSeverity.Error;
@@ -258,7 +323,7 @@ export function getAddToDatabaseErrorMessage(errorType: string): string {
       return "The opened mm file has changed since it was loaded. Please reload the database to add statements to the database again.";
     }
     case "AddingToInnerScopeError": {
-      return "The location you are trying to add to is inbetween two statements sharing a scope. mmt1 is not yet cappable of handling this case. Please add the statement manually.";
+      return "The location you are trying to add to is inside of a ${ $} block that contains a header. mmt1 is not yet cappable of handling this case. Please add the statement manually.";
     }
     case "FileReadError": {
       return "Cannot read mm file. Please make sure that the file was not moved or deleted.";
@@ -271,11 +336,48 @@ export function getAddToDatabaseErrorMessage(errorType: string): string {
   return "You should not be seeing this error message. Please post a Github issue with your mmp file.";
 }
 
+export function getMergeDatabaseErrorMessage(errorType: string): string {
+  switch (errorType) {
+    case "NotFoundError": {
+      return "Only theorems and axioms of the other database can be merged.";
+    }
+    case "DatabaseHasChangedError": {
+      return "The opened mm file has changed since it was loaded. Please reload the database to merge changes into it.";
+    }
+    case "LabelAlreadyExistsError": {
+      return "The label of a theorem to merge or of one of its hypotheses is already taken by another statement or math symbol of the open database.";
+    }
+    case "MergeMissingDependencyError": {
+      return "A theorem to merge references a label that neither exists in the open database nor is merged before it.";
+    }
+    case "MergeStatementChangedError": {
+      return "A theorem to merge already exists in the open database with different hypotheses, a different assertion or different $d statements.";
+    }
+    case "MergeDependencyOrderError": {
+      return "The new proof of a theorem that already exists in the open database references a theorem that comes after it.";
+    }
+    case "MergeProofModificationDiscouragedError": {
+      return "Modifying the proof of a theorem to merge is discouraged. Allow replacing these proofs to merge it anyway.";
+    }
+    case "FileReadError": {
+      return "Cannot read mm file. Please make sure that the file was not moved or deleted.";
+    }
+    case "NoMmDbError": {
+      return "You must first open or create a database before you can merge changes into it.";
+    }
+  }
+
+  return "You should not be seeing this error message. Please post a Github issue with both databases.";
+}
+
 export function getMmFileErrorMessage(errorType: string): string {
   switch (errorType) {
     case "FileReadError": {
       return "Could not read mm file. Make sure you have the necessary permissions";
     }
+    case "InvalidDatabaseSettingsError": {
+      return "The settings file beside the mm file (ending in .mmt1.json) is not valid. Fix or delete it to open the database.";
+    }
     case "InvalidCharactersError": {
       return "The mm file contains non-ascii characters.";
     }
@@ -364,3 +466,32 @@ export function getMmFileErrorMessage(errorType: string): string {
 
   return "You should not be seeing this error message. Please post a Github issue with a link to your mm file.";
 }
+
+export function getExpectedSymbolsMessage(expectedSymbols: string[]): string {
+  if (expectedSymbols.length === 0) {
+    return "\n\nExpected the expression to end here.";
+  }
+
+  return "\n\nExpected " + expectedSymbols.map((symbol) => "`" + symbol + "`").join(" or ") + " here.";
+}
+
+export function getUnificationExplanationMessage(explanation: UnificationExplanation): string {
+  let message = "";
+
+  if (explanation.clash !== null) {
+    message += "\n\nThe step doesn't match\n  " + explanation.pattern;
+    message += "\nbecause\n  " + explanation.clash[0] + "\nclashes with\n  " + explanation.clash[1];
+  }
+  if (explanation.distinctVariablePair !== null) {
+    message += "\n\nThe step ref requires\n  " + explanation.pattern;
+    message += "\nwhich is violated by the variables " + explanation.distinctVariablePair[0] + " and " + explanation.distinctVariablePair[1] + ".";
+  }
+  if (explanation.substitution.length !== 0) {
+    message += "\n\nSubstitution:";
+    for (const [variable, expression] of explanation.substitution) {
+      message += "\n  " + variable + " := " + expression;
+    }
+  }
+
+  return message;
+}
@@ -1,4 +1,5 @@
-import type { ColorInformation } from "$lib/sharedState/model.svelte";
+import type { ColorInformation, HtmlRepresentation } from "$lib/sharedState/model.svelte";
+import { invoke } from "@tauri-apps/api/core";
 import * as monaco from "monaco-editor";
 
 // Import the workers in a production-safe way.
@@ -71,11 +72,47 @@ let colorInformationToRules = (colorInformation: ColorInformation[]): { token: s
 
 monaco.languages.register({ id: "mmp" });
 
+// Suggests the constants that can come next in the expression of a proof line
+monaco.languages.registerCompletionItemProvider("mmp", {
+  triggerCharacters: [" "],
+  provideCompletionItems: async (model, position) => {
+    // Proof lines can continue on following lines that start with whitespace
+    let statementStartLine = position.lineNumber;
+    while (statementStartLine > 1 && /^\s/.test(model.getLineContent(statementStartLine))) {
+      statementStartLine--;
+    }
+
+    const statement = model.getValueInRange(new monaco.Range(statementStartLine, 1, position.lineNumber, position.column));
+    const stepPrefix = statement.match(/^\S+\s/);
+    if (stepPrefix === null || statement.startsWith("$") || statement.startsWith("*") || !stepPrefix[0].includes(":")) {
+      return { suggestions: [] };
+    }
+
+    const expression = statement.slice(stepPrefix[0].length);
+    const constants = (await invoke("expression_autocomplete", { expression, cursorPosition: [...expression].length })) as HtmlRepresentation[];
+
+    const partialToken = expression.match(/\S*$/)![0];
+    const range = new monaco.Range(position.lineNumber, position.column - partialToken.length, position.lineNumber, position.column);
+
+    return {
+      suggestions: constants.map((constant, i) => ({
+        label: constant.symbol,
+        kind: monaco.languages.CompletionItemKind.Constant,
+        insertText: constant.symbol,
+        documentation: constant.html !== "" ? { value: constant.html, supportHtml: true } : undefined,
+        // Keep the order of the backend, which ranks the constants by usage
+        sortText: i.toString().padStart(6, "0"),
+        range,
+      })),
+    };
+  },
+});
+
 export let setEditorSyntaxHighlighting = (colorInformation: ColorInformation[]) => {
   monaco.languages.setMonarchTokensProvider("mmp", {
     ...colorInformationToKeywords(colorInformation),
-    keywords: ["$theorem", "$axiom", "$c", "$v", "$f", "$header", "$locateafter", "$locateaftervar", "$locateafterconst", "$locateafterheader", "$locateaftercomment", "$locateafterstart", "$allowdiscouraged", "$allowincomplete", "$d", "$="],
-    keywordsWithoutVarColor: ["$theorem", "$axiom", "$header", "$locateafter", "$locateafterheader", "$locateaftercomment", "$locateafterstart", "$allowdiscouraged", "$allowincomplete", "$="],
+    keywords: ["$theorem", "$axiom", "$c", "$v", "$f", "$header", "$locateafter", "$locateaftervar", "$locateafterconst", "$locateafterheader", "$locateaftercomment", "$locateafterstart", "$allowdiscouraged", "$allowincomplete", "$allowproofmodification", "$deductionmode", "$unicodeinput", "$d", "$="],
+    keywordsWithoutVarColor: ["$theorem", "$axiom", "$header", "$locateafter", "$locateafterheader", "$locateaftercomment", "$locateafterstart", "$allowdiscouraged", "$allowincomplete", "$allowproofmodification", "$deductionmode", "$unicodeinput", "$="],
     tokenizer: {
       root: [{ include: "line" }],
 
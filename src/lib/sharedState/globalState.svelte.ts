@@ -4,7 +4,10 @@ import { listen } from "@tauri-apps/api/event";
 class GlobalState {
   databaseState: DatabaseState | null = $state(null);
   databaseBeingOpened: string = $state("");
+  // Set when restoring a session whose database has not changed since, so it does not have to be confirmed again
+  confirmDatabaseAutomatically: boolean = $state(false);
   lastEditorContent: string = $state("");
+  databaseToMerge: string = $state("");
 }
 
 export class DatabaseState {
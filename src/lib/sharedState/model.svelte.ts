@@ -52,7 +52,7 @@ export interface HeaderRepresentation {
 }
 
 export interface HeaderContentRepresentation {
-  contentType: "CommentStatement" | "ConstantStatement" | "VariableStatement" | "FloatingHypothesisStatement" | "TheoremStatement";
+  contentType: "CommentStatement" | "ConstantStatement" | "VariableStatement" | "FloatingHypothesisStatement" | "TheoremStatement" | "ScopeStatement";
   title: string;
 }
 
@@ -225,6 +225,8 @@ export interface TheoremListEntry {
   hypotheses: string[];
   assertion: string;
   descriptionParsed: ParsedDescriptionSegment[];
+  isDiscouraged: boolean;
+  isProofModificationDiscouraged: boolean;
   discriminator: "TheoremListEntry";
 }
 
@@ -244,6 +246,45 @@ export interface DetailedError {
   startColumn: number;
   endLineNumber: number;
   endColumn: number;
+  explanation: UnificationExplanation | null;
+  expectedSymbols: string[] | null;
+}
+
+export interface UnificationExplanation {
+  pattern: string;
+  substitution: [string, string][];
+  clash: [string, string] | null;
+  distinctVariablePair: [string, string] | null;
+}
+
+export interface DatabaseDiff {
+  addedStatements: DatabaseDiffStatement[];
+  removedStatements: DatabaseDiffStatement[];
+  movedStatements: MovedDatabaseDiffStatement[];
+  changedAssertions: ChangedDatabaseDiffStatement[];
+  changedProofs: ChangedDatabaseDiffStatement[];
+  changedDescriptions: ChangedDatabaseDiffStatement[];
+}
+
+export interface DatabaseDiffStatement {
+  label: string;
+  headerPath: string;
+  headerTitles: string[];
+}
+
+export interface MovedDatabaseDiffStatement {
+  label: string;
+  oldHeaderPath: string;
+  newHeaderPath: string;
+  oldHeaderTitles: string[];
+  newHeaderTitles: string[];
+}
+
+export interface ChangedDatabaseDiffStatement {
+  label: string;
+  headerPath: string;
+  oldValue: string;
+  newValue: string;
 }
 
 export type AddToDatabaseResult = NewHeader | NewStatement;
@@ -0,0 +1,58 @@
+import { goto } from "$app/navigation";
+import { EditorTab } from "$lib/components/tabs/EditorTabComponent.svelte";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { confirm } from "@tauri-apps/plugin-dialog";
+import { fileExplorerData } from "./fileExplorerData.svelte";
+import { globalState } from "./globalState.svelte";
+import type { FolderRepresentation } from "./model.svelte";
+import { tabManager } from "./tabManager.svelte";
+
+// The parts of the session of the last launch that can still be restored
+export interface PreviousSession {
+  databasePath: string | null;
+  databaseUnchanged: boolean;
+  folderPath: string | null;
+  openFiles: string[];
+}
+
+class SessionData {
+  recentDatabases: string[] = $state([]);
+
+  async loadRecentDatabases() {
+    this.recentDatabases = await invoke("get_recent_databases");
+  }
+
+  async restorePreviousSession() {
+    let previousSession = (await invoke("get_previous_session")) as PreviousSession | null;
+
+    if (previousSession === null || !(await confirm("Do you want to restore the folder, files and database you had open last time?", { title: "Restore Previous Session", okLabel: "Restore", cancelLabel: "Start Empty" }))) {
+      return;
+    }
+
+    if (previousSession.folderPath !== null) {
+      let folderRep = (await invoke("open_folder", { folderPath: previousSession.folderPath })) as FolderRepresentation;
+      let folderPathSplit = previousSession.folderPath.split(/[/\\]/);
+      fileExplorerData.resetDataWithFirstFolder(folderPathSplit[folderPathSplit.length - 1], folderRep);
+
+      for (let openFile of previousSession.openFiles) {
+        // Skip files that can't be opened anymore
+        await tabManager.openTab(new EditorTab(openFile), true).catch(() => {});
+      }
+    }
+
+    if (previousSession.databasePath !== null) {
+      globalState.databaseBeingOpened = previousSession.databasePath;
+      globalState.confirmDatabaseAutomatically = previousSession.databaseUnchanged;
+      await goto("/main/openDatabase");
+    }
+  }
+}
+
+let sessionData = new SessionData();
+
+listen("mm-db-opened", () => {
+  sessionData.loadRecentDatabases();
+});
+
+export { sessionData };
@@ -1,46 +1,78 @@
-import { load, Store } from "@tauri-apps/plugin-store";
 import { util } from "./util.svelte";
 import { invoke } from "@tauri-apps/api/core";
+import { message } from "@tauri-apps/plugin-dialog";
 
 export interface Settings {
   definitionsStartWith: string;
   colorUnicodePreview: boolean;
   showUnifyResultInUnicodePreview: boolean;
   defaultShowAll: boolean;
-  proofFormat: "uncompressed" | "compressed";
+  proofFormat: ProofFormat;
   formatOnSave: boolean;
+  styleLint: StyleLintSettings;
+  deductionBridgingTheorems: string[];
+  exportFormatting: ExportFormattingSettings;
 }
 
-let defaultSettings: Settings = {
-  definitionsStartWith: "df-",
-  colorUnicodePreview: true,
-  showUnifyResultInUnicodePreview: true,
-  defaultShowAll: false,
-  proofFormat: "compressed",
-  formatOnSave: true,
-};
+// Settings stored beside the mm file of the open database. They take precedence over the settings of the user
+export interface DatabaseSettingsOverrides {
+  definitionsStartWith?: string;
+  proofFormat?: ProofFormat;
+  exportFormatting?: ExportFormattingSettings;
+}
+
+export interface ExportFormattingSettings {
+  lineWidth: number;
+  wrapProofs: boolean;
+  blankLinesBetweenStatements: number;
+  blockIndentation: number;
+}
+
+export type ProofFormat = "uncompressed" | "compressed" | "compressedNormalized" | "explicit" | "packed";
+
+export interface StyleLintSettings {
+  labelNaming: boolean;
+  discouragedTags: boolean;
+  descriptionPresence: boolean;
+  contributorLines: boolean;
+  lineLength: boolean;
+  maxLineLength: number;
+}
 
 class SettingsData {
-  settings: Settings = $state(util.clone(defaultSettings) as Settings);
-  settingsStore: Store | null = null;
+  // Filled in by setupSettings before any settings are read
+  settings: Settings = $state({} as Settings);
+  databaseSettingsOverrides: DatabaseSettingsOverrides = $state({});
 
+  effectiveSettings: Settings = $derived({ ...this.settings, ...this.databaseSettingsOverrides });
+
+  // If the stored settings can't be read, the defaults are used until the user saves new settings
   async setupSettings() {
-    this.settingsStore = await load("settings.json");
-    let settingsIfExists = (await this.settingsStore.get("settings")) as Settings | undefined;
-    if (settingsIfExists !== undefined) {
-      this.settings = { ...util.clone(defaultSettings), ...settingsIfExists };
-    } else {
-      this.settingsStore.set("settings", defaultSettings);
+    try {
+      this.settings = await invoke("get_settings");
+    } catch {
+      this.settings = await invoke("get_default_settings");
+      await message("Your stored settings could not be read, so the default settings are used. Saving your settings will replace the stored ones.", { title: "Settings Error", kind: "error" });
     }
+  }
+
+  async loadDatabaseSettingsOverrides() {
+    this.databaseSettingsOverrides = await invoke("get_database_settings_overrides");
+  }
 
-    await invoke("set_settings", { settings: this.settings });
+  resetDatabaseSettingsOverrides() {
+    this.databaseSettingsOverrides = {};
   }
 
   cloneSettings(): Settings {
     return util.clone(this.settings) as Settings;
   }
+
+  cloneDatabaseSettingsOverrides(): DatabaseSettingsOverrides {
+    return util.clone(this.databaseSettingsOverrides) as DatabaseSettingsOverrides;
+  }
 }
 
 let settingsData = new SettingsData();
 
-export { defaultSettings, settingsData };
+export { settingsData };